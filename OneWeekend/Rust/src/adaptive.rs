@@ -0,0 +1,142 @@
+use super::vector::Color;
+use super::color;
+
+// Running luminance statistics of one pixel (Welford's algorithm).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PixelStats {
+    count: u32,
+    mean: f64,
+    m2: f64
+}
+
+impl PixelStats {
+    pub fn from_parts(count: u32, mean: f64, m2: f64) -> Self {
+        Self { count, mean, m2 }
+    }
+
+    pub fn parts(&self) -> (u32, f64, f64) {
+        (self.count, self.mean, self.m2)
+    }
+
+    pub fn add(&mut self, sample: Color) {
+        let x = color::luminance(sample);
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    // Standard error of the mean relative to its brightness; dark pixels are
+    // judged against a floor so they don't sample forever.
+    pub fn relative_error(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        let n = self.count as f64;
+        let variance = self.m2 / (n - 1.0);
+        libm::sqrt(variance / n) / libm::fmax(self.mean, 0.05)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AdaptiveSampling {
+    threshold: f64,
+    min_samples: u32,
+    tile: u32
+}
+
+impl AdaptiveSampling {
+    pub fn new(threshold: f64, min_samples: u32) -> Self {
+        Self { threshold, min_samples: min_samples.max(2), tile: 8 }
+    }
+
+    pub fn with_tile(mut self, tile: u32) -> Self {
+        self.tile = tile.max(1);
+        self
+    }
+
+    // A tile keeps sampling until its worst pixel is below the threshold.
+    pub fn update(&self, stats: &[PixelStats], width: u32, height: u32, active: &mut [bool]) {
+        for ty in (0 .. height).step_by(self.tile as usize) {
+            for tx in (0 .. width).step_by(self.tile as usize) {
+                let (x1, y1) = ((tx + self.tile).min(width), (ty + self.tile).min(height));
+                let pixels = || (ty .. y1).flat_map(
+                    move |y| (tx .. x1).map(move |x| (y * width + x) as usize)
+                );
+                let converged = pixels().all(|i| {
+                    stats[i].count >= self.min_samples && stats[i].relative_error() < self.threshold
+                });
+                if converged {
+                    pixels().for_each(|i| active[i] = false);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayMode {
+    #[default]
+    Image,
+    SampleHeatmap
+}
+
+// Blue for few samples through green to red for the maximum, in linear space.
+pub fn heatmap(count: u32, max: u32) -> Color {
+    let t = if max == 0 { 0.0 } else { (count as f64 / max as f64).min(1.0) };
+    if t < 0.5 {
+        let t = t * 2.0;
+        Color::new(0.0, t, 1.0 - t)
+    } else {
+        let t = (t - 0.5) * 2.0;
+        Color::new(t, 1.0 - t, 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(x: f64) -> Color {
+        Color::new(x, x, x)
+    }
+
+    #[test]
+    fn constant_pixels_stop_after_the_minimum() {
+        let sampling = AdaptiveSampling::new(0.01, 4).with_tile(2);
+        let mut stats = [PixelStats::default(); 4];
+        let mut active = [true; 4];
+        for pass in 1 ..= 4 {
+            stats.iter_mut().for_each(|s| s.add(gray(0.5)));
+            sampling.update(&stats, 2, 2, &mut active);
+            assert_eq!(active.iter().any(|&a| a), pass < 4, "pass {}", pass);
+        }
+        assert_eq!(stats[0].relative_error(), 0.0);
+    }
+
+    #[test]
+    fn noisy_pixels_keep_sampling() {
+        let sampling = AdaptiveSampling::new(0.01, 4).with_tile(2);
+        // a constant tile on the left, a flickering one on the right
+        let mut stats = [PixelStats::default(); 8];
+        let mut active = [true; 8];
+        for pass in 0 .. 64 {
+            for (i, s) in stats.iter_mut().enumerate() {
+                let noisy = i % 4 >= 2;
+                s.add(gray(if noisy && pass % 2 == 0 { 0.0 } else if noisy { 10.0 } else { 5.0 }));
+            }
+            sampling.update(&stats, 4, 2, &mut active);
+        }
+        assert_eq!(active, [false, false, true, true, false, false, true, true]);
+        let (count, mean, _) = stats[2].parts();
+        assert_eq!(count, 64);
+        assert!((mean - 5.0).abs() < 1e-9);
+        // 1/sqrt(n) convergence on the alternating samples
+        let error = stats[2].relative_error();
+        assert!(error > 0.1 && error < 0.15, "{}", error);
+    }
+}
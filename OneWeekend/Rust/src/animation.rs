@@ -0,0 +1,183 @@
+use super::vector::{Vec3, Point};
+use super::camera::Camera;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    Linear,
+    // Passes through every key with tangents from the neighbouring keys.
+    #[default]
+    CatmullRom,
+    // Cubic Bezier segments with auto-clamped handles: flat at the first and
+    // last key and wherever a channel turns around, so there's no overshoot.
+    Bezier
+}
+
+// Camera pose and lens at a point in time (seconds).
+#[derive(Debug, Clone, Copy)]
+pub struct Keyframe {
+    pub time: f64,
+    pub origin: Point,
+    pub target: Point,
+    pub fov: f64,
+    pub focus: f64
+}
+
+impl Keyframe {
+    pub fn new(time: f64, origin: Point, target: Point, fov: f64, focus: f64) -> Self {
+        Self { time, origin, target, fov, focus }
+    }
+
+    // Keyframe matching the camera's current state.
+    pub fn from_camera(time: f64, camera: &Camera) -> Self {
+        let focus = camera.focus_distance();
+        let target = camera.origin() + camera.front() * focus;
+        Self::new(time, camera.origin(), target, camera.fov(), focus)
+    }
+
+    fn channels(&self) -> [f64; 8] {
+        let (o, t) = (self.origin, self.target);
+        [o.x(), o.y(), o.z(), t.x(), t.y(), t.z(), self.fov, self.focus]
+    }
+
+    fn from_channels(time: f64, c: [f64; 8]) -> Self {
+        Self::new(
+            time, Vec3::new(c[0], c[1], c[2]), Vec3::new(c[3], c[4], c[5]), c[6], c[7]
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CameraTrack {
+    keys: Vec<Keyframe>,
+    interpolation: Interpolation
+}
+
+impl CameraTrack {
+    pub fn new(interpolation: Interpolation) -> Self {
+        Self { keys: Vec::new(), interpolation }
+    }
+
+    // Keys are kept sorted by time; a key at an existing time replaces it.
+    pub fn insert(&mut self, key: Keyframe) {
+        match self.keys.binary_search_by(|k| k.time.total_cmp(&key.time)) {
+            Ok(i) => self.keys[i] = key,
+            Err(i) => self.keys.insert(i, key)
+        }
+    }
+
+    pub fn keys(&self) -> &[Keyframe] {
+        &self.keys
+    }
+
+    pub fn interpolation(&self) -> Interpolation {
+        self.interpolation
+    }
+
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.interpolation = interpolation;
+    }
+
+    // Time of the last key.
+    pub fn duration(&self) -> f64 {
+        self.keys.last().map_or(0.0, |k| k.time)
+    }
+
+    // Slope of one channel at key `i`.
+    fn tangent(&self, i: usize, channel: usize) -> f64 {
+        let n = self.keys.len();
+        let value = |k: usize| self.keys[k].channels()[channel];
+        let (prev, next) = (i.saturating_sub(1), (i + 1).min(n - 1));
+        if prev == next {
+            return 0.0;
+        }
+        match self.interpolation {
+            Interpolation::Bezier => {
+                let (a, b, c) = (value(prev), value(i), value(next));
+                if i == 0 || i == n - 1 || (b - a) * (c - b) <= 0.0 {
+                    0.0
+                } else {
+                    (c - a) / (self.keys[next].time - self.keys[prev].time)
+                }
+            }
+            _ => (value(next) - value(prev)) / (self.keys[next].time - self.keys[prev].time)
+        }
+    }
+
+    // Interpolated state at `time`, held constant outside the key range.
+    pub fn sample(&self, time: f64) -> Option<Keyframe> {
+        let (first, last) = (self.keys.first()?, self.keys.last()?);
+        if time <= first.time {
+            return Some(Keyframe { time, ..*first });
+        }
+        if time >= last.time {
+            return Some(Keyframe { time, ..*last });
+        }
+        let i = self.keys.partition_point(|k| k.time <= time) - 1;
+        let (a, b) = (&self.keys[i], &self.keys[i + 1]);
+        let dt = b.time - a.time;
+        let t = (time - a.time) / dt;
+        let (ca, cb) = (a.channels(), b.channels());
+        let mut channels = [0.0; 8];
+        for (c, value) in channels.iter_mut().enumerate() {
+            *value = match self.interpolation {
+                Interpolation::Linear => ca[c] + (cb[c] - ca[c]) * t,
+                _ => {
+                    // cubic Hermite; for Bezier this is the segment with
+                    // handles a third of the way along the tangents
+                    let (t2, t3) = (t * t, t * t * t);
+                    let (ma, mb) = (self.tangent(i, c) * dt, self.tangent(i + 1, c) * dt);
+                    (2.0 * t3 - 3.0 * t2 + 1.0) * ca[c] + (t3 - 2.0 * t2 + t) * ma
+                        + (-2.0 * t3 + 3.0 * t2) * cb[c] + (t3 - t2) * mb
+                }
+            };
+        }
+        Some(Keyframe::from_channels(time, channels))
+    }
+
+    // Moves the camera to the animated state at `time`. Does nothing for
+    // an empty track.
+    pub fn apply(&self, camera: &mut Camera, time: f64) {
+        let Some(key) = self.sample(time) else {
+            return;
+        };
+        camera.set_view(key.origin, key.target - key.origin);
+        camera.set_fov(key.fov);
+        camera.set_focus_distance(key.focus);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(interpolation: Interpolation) -> CameraTrack {
+        let mut track = CameraTrack::new(interpolation);
+        let target = Point::new(0.0, 0.0, 0.0);
+        track.insert(Keyframe::new(0.0, Point::new(0.0, 0.0, 10.0), target, 20.0, 10.0));
+        track.insert(Keyframe::new(2.0, Point::new(10.0, 0.0, 0.0), target, 60.0, 10.0));
+        track.insert(Keyframe::new(1.0, Point::new(5.0, 4.0, 5.0), target, 40.0, 10.0));
+        track
+    }
+
+    #[test]
+    fn passes_through_keys() {
+        for interpolation in [Interpolation::Linear, Interpolation::CatmullRom, Interpolation::Bezier] {
+            let track = track(interpolation);
+            for key in track.keys() {
+                let sampled = track.sample(key.time).unwrap();
+                assert!((sampled.origin - key.origin).length() < 1e-12);
+                assert!((sampled.fov - key.fov).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn bezier_does_not_overshoot() {
+        // y peaks at the middle key, clamped handles keep it there
+        let track = track(Interpolation::Bezier);
+        let peak = (0 ..= 200)
+            .map(|i| track.sample(i as f64 / 100.0).unwrap().origin.y())
+            .fold(f64::MIN, f64::max);
+        assert!((peak - 4.0).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,245 @@
+use super::vector::{Vec3, Point};
+use super::camera::Ray;
+use super::sence::HitRecord;
+use super::transform::Transform;
+use super::utils::Interval;
+
+// Axis-aligned bounding box.
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    min: Point,
+    max: Point
+}
+
+impl Default for Aabb {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl Aabb {
+    // Box spanning both corners, in any order.
+    pub fn new(a: Point, b: Point) -> Self {
+        Self::empty().grow(a).grow(b)
+    }
+
+    pub fn empty() -> Self {
+        Self {
+            min: Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY)
+        }
+    }
+
+    pub fn min(&self) -> Point {
+        self.min
+    }
+
+    pub fn max(&self) -> Point {
+        self.max
+    }
+
+    pub fn is_empty(&self) -> bool {
+        (0 .. 3).any(|a| self.min.axis(a) > self.max.axis(a))
+    }
+
+    pub fn grow(self, p: Point) -> Self {
+        Self {
+            min: Vec3::new(self.min.x().min(p.x()), self.min.y().min(p.y()), self.min.z().min(p.z())),
+            max: Vec3::new(self.max.x().max(p.x()), self.max.y().max(p.y()), self.max.z().max(p.z()))
+        }
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        self.grow(other.min).grow(other.max)
+    }
+
+    // Widens flat boxes so axis-aligned planes still get hit.
+    pub fn pad(self, delta: f64) -> Self {
+        let d = Vec3::new(delta, delta, delta);
+        Self { min: self.min - d, max: self.max + d }
+    }
+
+    pub fn centroid(&self) -> Point {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        let e = self.max - self.min;
+        if e.x() >= e.y() && e.x() >= e.z() { 0 } else if e.y() >= e.z() { 1 } else { 2 }
+    }
+
+    // Box around the transformed corners.
+    pub fn transform(&self, transform: &Transform) -> Self {
+        (0 .. 8).fold(Self::empty(), |b, i| {
+            let corner = Vec3::new(
+                if i & 1 == 0 { self.min.x() } else { self.max.x() },
+                if i & 2 == 0 { self.min.y() } else { self.max.y() },
+                if i & 4 == 0 { self.min.z() } else { self.max.z() }
+            );
+            b.grow(transform.point(corner))
+        })
+    }
+
+    // Entry time of the ray, clipped to `interval`.
+    pub fn hit(&self, ray: &Ray, interval: Interval) -> Option<f64> {
+        self.clip(ray, interval).map(|inside| inside.min())
+    }
+
+    // Part of `interval` the ray spends inside the box.
+    pub fn clip(&self, ray: &Ray, interval: Interval) -> Option<Interval> {
+        let (mut t0, mut t1) = (interval.min(), interval.max());
+        let (origin, direction) = (ray.origin(), ray.direction());
+        for a in 0 .. 3 {
+            let inv = 1.0 / direction.axis(a);
+            let mut near = (self.min.axis(a) - origin.axis(a)) * inv;
+            let mut far = (self.max.axis(a) - origin.axis(a)) * inv;
+            if inv < 0.0 {
+                std::mem::swap(&mut near, &mut far);
+            }
+            // NaN from 0 * inf leaves the bounds untouched
+            t0 = if near > t0 { near } else { t0 };
+            t1 = if far < t1 { far } else { t1 };
+            if t1 < t0 {
+                return None;
+            }
+        }
+        Some(Interval::new(t0, t1))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Leaf { start: usize, count: usize },
+    // The first child directly follows its parent.
+    Interior { second: usize, axis: usize }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    bounds: Aabb,
+    kind: Kind
+}
+
+const LEAF_SIZE: usize = 4;
+
+// Bounding volume hierarchy over primitives given by their boxes, split at
+// the centroid median of the longest axis.
+#[derive(Debug, Clone, Default)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    indices: Vec<usize>
+}
+
+impl Bvh {
+    pub fn new(boxes: &[Aabb]) -> Self {
+        let mut bvh = Self { nodes: Vec::new(), indices: (0 .. boxes.len()).collect() };
+        if !boxes.is_empty() {
+            bvh.build(boxes, 0, boxes.len());
+        }
+        bvh
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.nodes.first().map_or(Aabb::empty(), |n| n.bounds)
+    }
+
+    fn build(&mut self, boxes: &[Aabb], start: usize, end: usize) {
+        let slice = &mut self.indices[start .. end];
+        let bounds = slice.iter().fold(Aabb::empty(), |b, &i| b.union(boxes[i]));
+        let centroids = slice.iter().fold(Aabb::empty(), |b, &i| b.grow(boxes[i].centroid()));
+        let axis = centroids.longest_axis();
+        let node = self.nodes.len();
+        let extent = centroids.max().axis(axis) - centroids.min().axis(axis);
+        if end - start <= LEAF_SIZE || extent <= 0.0 {
+            self.nodes.push(Node { bounds, kind: Kind::Leaf { start, count: end - start } });
+            return;
+        }
+        let mid = (end - start) / 2;
+        slice.select_nth_unstable_by(mid, |&a, &b| {
+            boxes[a].centroid().axis(axis).total_cmp(&boxes[b].centroid().axis(axis))
+        });
+        self.nodes.push(Node { bounds, kind: Kind::Interior { second: 0, axis } });
+        self.build(boxes, start, start + mid);
+        let second = self.nodes.len();
+        self.nodes[node].kind = Kind::Interior { second, axis };
+        self.build(boxes, start + mid, end);
+    }
+
+    // Closest hit, `hit` intersects primitive `index` within the interval.
+    pub fn hit(
+        &self, ray: &Ray, interval: Interval,
+        mut hit: impl FnMut(usize, Interval) -> Option<HitRecord>
+    ) -> Option<HitRecord> {
+        let mut record = None;
+        let mut max_time = interval.max();
+        let mut stack = Vec::with_capacity(32);
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.bounds.hit(ray, Interval::new(interval.min(), max_time)).is_none() {
+                continue;
+            }
+            match node.kind {
+                Kind::Leaf { start, count } => {
+                    for &primitive in &self.indices[start .. start + count] {
+                        if let Some(rec) = hit(primitive, Interval::new(interval.min(), max_time)) {
+                            if rec.time() < max_time {
+                                max_time = rec.time();
+                                record = Some(rec);
+                            }
+                        }
+                    }
+                }
+                Kind::Interior { second, axis } => {
+                    // visit the near child first
+                    if ray.direction().axis(axis) < 0.0 {
+                        stack.push(index + 1);
+                        stack.push(second);
+                    } else {
+                        stack.push(second);
+                        stack.push(index + 1);
+                    }
+                }
+            }
+        }
+        record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    use super::*;
+    use crate::sence::{Hittable, Sphere};
+    use crate::material::MaterialId;
+
+    #[test]
+    fn matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(17);
+        let mut point = |scale: f64| {
+            Point::new(rng.gen_range(-scale .. scale), rng.gen_range(-scale .. scale),
+                rng.gen_range(-scale .. scale))
+        };
+        let m = MaterialId::from_index(0);
+        let spheres: Vec<_> = (0 .. 200)
+            .map(|i| Sphere::new(point(10.0), 0.2 + (i % 7) as f64 * 0.15, m))
+            .collect();
+        let boxes: Vec<_> = spheres.iter().map(|s| s.bounding_box().unwrap()).collect();
+        let bvh = Bvh::new(&boxes);
+        let interval = Interval::new(0.001, f64::INFINITY);
+        let mut hits = 0;
+        for _ in 0 .. 2000 {
+            let ray = Ray::new(point(12.0), point(1.0));
+            let expected = spheres.iter().filter_map(|s| s.hit(&ray, interval))
+                .map(|r| r.time()).min_by(f64::total_cmp);
+            let found = bvh.hit(&ray, interval, |i, range| spheres[i].hit(&ray, range));
+            assert_eq!(found.map(|r| r.time()), expected);
+            hits += expected.is_some() as u32;
+        }
+        assert!(hits > 200 && hits < 1800, "{}", hits);
+    }
+}
@@ -1,122 +1,428 @@
-use std::vec;
-use std::iter;
+use std::io;
+use std::sync::Arc;
 
 use super::vector::{Vec3, Point, Color};
 use super::sence::{Sence, Hittable};
+use super::spectrum::{SampledSpectrum, SampledWavelengths};
+use super::sampler::Sampler;
+use super::scheduler::Tile;
+use super::projection::{Frame, Projection, Perspective};
+use super::lens::Lens;
+use super::color::ColorSpace;
 use super::utils::{self, Interval};
+use super::serialize::{self, invalid, unsupported, Record, Value};
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct Camera {
     depth: u32,
     origin: Point,
-    viewport: Viewport,
-    fdist: (Vec3, Vec3)
+    front: Vec3,
+    vup: Vec3,
+    focus: f64,
+    fov: f64,
+    defocus: f64,
+    lens: Option<Lens>,
+    shift: (f64, f64),
+    width: u32,
+    height: u32,
+    frame: Frame,
+    projection: Arc<dyn Projection>,
+    spectral: bool,
+    // Working space the paths are traced in, set by the renderer.
+    space: ColorSpace
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         origin: Point, focal: f64, fov: f64, depth: u32,
         vup: Vec3, front:Vec3, defocus: f64, w: u32, h: u32
     ) -> Self {
-        let (viewport, fdist) = {
-            let right = front.cross(&vup).unit();
-            let up = right.cross(&front).unit();
-            let center = focal * front.unit() + origin;
-            let height = focal * libm::tan(utils::degrees_to_radians(fov / 2.0)) * 2.0;
-            let width = height * (w as f64 / h as f64);
-            let x = width * right;
-            let y = height * up;
-            let o = center - x / 2.0 + y / 2.0;
-            let fradius = focal * libm::tan(utils::degrees_to_radians(defocus / 2.0));
-            (Viewport::new(o, x, -y, w, h), (right * fradius, up * fradius))
+        let mut camera = Self {
+            depth, origin, front: front.unit(), vup, focus: focal, fov, defocus,
+            lens: None, shift: (0.0, 0.0),
+            width: w, height: h, frame: Frame::default(),
+            projection: Arc::new(Perspective), spectral: false, space: ColorSpace::default()
         };
-        Self { depth, origin, viewport, fdist }
+        camera.build(w, h);
+        camera
+    }
+
+    // Physical camera: field of view and lens radius follow from the lens,
+    // the image plane sits at its focus distance.
+    pub fn physical(
+        origin: Point, front: Vec3, vup: Vec3, depth: u32, lens: Lens, w: u32, h: u32
+    ) -> Self {
+        let mut camera = Self::new(origin, 1.0, 90.0, depth, vup, front, 0.0, w, h);
+        camera.set_lens(lens);
+        camera
     }
 
-    pub fn render(&self, world: &Sence, width: u32, height: u32) -> Vec<Vec3> {
-        let mut sample = Vec::new();
-        sample.resize((width * height) as usize, Vec3::default());
-        for (color, point) in iter::zip(&mut sample, &self.viewport) {
-            let origin = self.defocus_disk_sample();
-            let ray = Ray::new(origin, point - origin);
-            *color = self.ray_color(&ray, &world, self.depth);
+    fn build(&mut self, w: u32, h: u32) {
+        let right = self.front.cross(&self.vup).unit();
+        let aspect = w as f64 / h as f64;
+        let lens_radius = match &self.lens {
+            Some(lens) => {
+                self.fov = lens.fov(aspect);
+                lens.aperture_radius()
+            }
+            None => self.focus * libm::tan(utils::degrees_to_radians(self.defocus / 2.0))
+        };
+        self.width = w;
+        self.height = h;
+        self.frame = Frame {
+            origin: self.origin,
+            right,
+            up: right.cross(&self.front).unit(),
+            front: self.front,
+            focus: self.focus,
+            fov: self.fov,
+            lens_radius,
+            aspect,
+            shift: self.shift
+        };
+    }
+
+    pub(crate) fn to_record(&self) -> io::Result<Record> {
+        let projection = self.projection.to_record()
+            .ok_or_else(|| unsupported(format!("projection {:?}", self.projection)))?;
+        let mut record = Record::new("camera")
+            .with("depth", self.depth)
+            .with("origin", self.origin)
+            .with("front", self.front)
+            .with("vup", self.vup)
+            .with("focus", self.focus)
+            .with("fov", self.fov)
+            .with("defocus", self.defocus)
+            .with("shift", vec![self.shift.0, self.shift.1])
+            .with("width", self.width)
+            .with("height", self.height)
+            .with("projection", projection)
+            .with("spectral", self.spectral);
+        if let Some(lens) = &self.lens {
+            record = record.with("lens", lens.to_record());
         }
-        sample
+        Ok(record)
     }
 
-    pub fn ray_color(&self, ray: &Ray, world: &Sence, depth: u32) -> Color {
-        if depth <= 0 {
-            return Color::default();
+    // Fields are restored as saved rather than through `new`, which would
+    // renormalize the view direction.
+    pub(crate) fn from_record(record: &Record) -> io::Result<Self> {
+        let [sx, sy] = record.numbers("shift")?[..] else {
+            return Err(invalid("shift of camera needs 2 values"));
+        };
+        let (width, height) = (record.integer("width")? as u32, record.integer("height")? as u32);
+        let mut camera = Self {
+            depth: record.integer("depth")? as u32,
+            origin: record.vector("origin")?,
+            front: record.vector("front")?,
+            vup: record.vector("vup")?,
+            focus: record.number("focus")?,
+            fov: record.number("fov")?,
+            defocus: record.number("defocus")?,
+            lens: record.get("lens").map(Value::as_record).transpose()?
+                .map(Lens::from_record).transpose()?,
+            shift: (sx, sy),
+            width, height, frame: Frame::default(),
+            projection: serialize::projection(record.record("projection")?)?,
+            spectral: record.boolean("spectral")?,
+            space: ColorSpace::default()
+        };
+        camera.build(width, height);
+        Ok(camera)
+    }
+
+    pub fn lens(&self) -> Option<&Lens> {
+        self.lens.as_ref()
+    }
+
+    pub fn set_lens(&mut self, lens: Lens) {
+        self.focus = lens.focus_distance();
+        self.lens = Some(lens);
+        self.build(self.width, self.height);
+    }
+
+    pub fn set_projection(&mut self, projection: impl Projection + 'static) {
+        self.projection = Arc::new(projection);
+    }
+
+    pub fn shift(&self) -> (f64, f64) {
+        self.shift
+    }
+
+    // Shifts the image window off the view axis without rotating the
+    // camera, in tangents of the view angle (right, up).
+    pub fn set_shift(&mut self, x: f64, y: f64) {
+        self.shift = (x, y);
+        self.build(self.width, self.height);
+    }
+
+    pub fn origin(&self) -> Point {
+        self.origin
+    }
+
+    pub fn front(&self) -> Vec3 {
+        self.front
+    }
+
+    pub fn vup(&self) -> Vec3 {
+        self.vup
+    }
+
+    pub fn right(&self) -> Vec3 {
+        self.front.cross(&self.vup).unit()
+    }
+
+    pub fn up(&self) -> Vec3 {
+        self.right().cross(&self.front).unit()
+    }
+
+    pub fn fov(&self) -> f64 {
+        self.fov
+    }
+
+    pub fn focus_distance(&self) -> f64 {
+        self.focus
+    }
+
+    pub fn resize(&mut self, w: u32, h: u32) {
+        self.build(w, h);
+    }
+
+    pub fn set_view(&mut self, origin: Point, front: Vec3) {
+        self.origin = origin;
+        self.front = front.unit();
+        self.build(self.width, self.height);
+    }
+
+    // With a physical lens this zooms, changing the focal length.
+    pub fn set_fov(&mut self, fov: f64) {
+        self.fov = fov;
+        let aspect = self.width as f64 / self.height as f64;
+        if let Some(lens) = &mut self.lens {
+            lens.set_focal_length(lens.focal_length_for(fov, aspect));
         }
-        if let Some(rec) = world.hit(ray, Interval::new(0.001, f64::INFINITY)) {
-            if let Some((scatterd, attenuation)) = rec.material().scatter(ray, &rec) {
-                return attenuation * self.ray_color(&scatterd, world, depth - 1);
+        self.build(self.width, self.height);
+    }
+
+    pub fn set_focus_distance(&mut self, focus: f64) {
+        self.focus = focus;
+        if let Some(lens) = &mut self.lens {
+            lens.set_focus_distance(focus);
+        }
+        self.build(self.width, self.height);
+    }
+
+    pub fn set_spectral(&mut self, spectral: bool) {
+        self.spectral = spectral;
+    }
+
+    // Scene colors are authored in linear sRGB and converted into `space`
+    // as the paths pick them up.
+    pub fn set_color_space(&mut self, space: ColorSpace) {
+        self.space = space;
+    }
+
+    pub fn color_space(&self) -> ColorSpace {
+        self.space
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn render(&self, world: &Sence, sampler: &mut dyn Sampler, index: u32) -> Vec<Vec3> {
+        let mut sample = Vec::with_capacity((self.width * self.height) as usize);
+        for i in 0 .. self.height {
+            for j in 0 .. self.width {
+                sample.push(self.sample_pixel(world, i, j, sampler, index).0);
             }
-            return Color::default();
         }
-        let unit = ray.direction().unit();
-        let a = 0.5 * (unit.y() + 1.0);
-        (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0)
+        sample
     }
 
-    fn defocus_disk_sample(&self) -> Point {
-        let p = Vec3::random_in_unit_disk();
-        self.origin + (p.x() * self.fdist.0) + (p.y() * self.fdist.1)
+    // Traces only the pixels inside `region`, framing stays that of the
+    // full image. Returns the region's pixels row by row.
+    pub fn render_region(
+        &self, world: &Sence, sampler: &mut dyn Sampler, index: u32, region: Tile
+    ) -> Vec<Vec3> {
+        let mut sample = Vec::with_capacity(region.area() as usize);
+        for i in region.y0 .. region.y1.min(self.height) {
+            for j in region.x0 .. region.x1.min(self.width) {
+                sample.push(self.sample_pixel(world, i, j, sampler, index).0);
+            }
+        }
+        sample
     }
-}
 
-#[derive(Debug, Default, Clone, Copy)]
-struct Viewport {
-    o: Point,
-    x: Vec3,
-    y: Vec3,
-    w: u32,
-    h: u32
-}
+    // Traces one sample of pixel (i, j) and prints every bounce of the path.
+    pub fn debug_pixel(
+        &self, world: &Sence, i: u32, j: u32, sampler: &mut dyn Sampler, index: u32
+    ) -> Color {
+        sampler.start_pixel(j, i, index);
+        eprintln!("pixel ({}, {}) sample {}", j, i, index);
+        let Some(ray) = self.primary_ray(i, j, sampler.get_2d(), sampler.get_2d()) else {
+            eprintln!("  outside the projection");
+            return Color::default();
+        };
+        let color = self.debug_ray(&ray, world, self.depth, sampler, Color::new(1.0, 1.0, 1.0));
+        eprintln!("  result {:?}", color);
+        color
+    }
 
-impl Viewport {
-    fn new(o: Point, x: Vec3, y: Vec3, w: u32, h: u32) -> Self {
-        Self { o, x, y, w, h }
+    fn debug_ray(
+        &self, ray: &Ray, world: &Sence, depth: u32, sampler: &mut dyn Sampler, throughput: Color
+    ) -> Color {
+        let bounce = self.depth - depth;
+        if depth == 0 {
+            eprintln!("  [{}] depth limit reached", bounce);
+            return Color::default();
+        }
+        let Some(rec) = world.hit(ray, Interval::new(0.001, f64::INFINITY)) else {
+            let background = self.space.from_linear_srgb(world.background(ray));
+            eprintln!(
+                "  [{}] escaped dir {:?} background {:?} contribution {:?}",
+                bounce, ray.direction().unit(), background, throughput * background
+            );
+            return background;
+        };
+        eprintln!(
+            "  [{}] hit t {:.6} point {:?} normal {:?} front {} material {:?} {:?}",
+            bounce, rec.time(), rec.point(), rec.normal(), rec.front(),
+            world.materials().name(rec.material()), world.materials().get(rec.material())
+        );
+        let emitted = self.space.from_linear_srgb(world.emitted(ray, &rec));
+        if !emitted.near_zero() {
+            eprintln!(
+                "  [{}] emitted {:?} contribution {:?}", bounce, emitted, throughput * emitted
+            );
+        }
+        match world.scatter(ray, &rec, sampler) {
+            Some((scatterd, attenuation)) => {
+                let attenuation = self.space.from_linear_srgb(attenuation);
+                eprintln!(
+                    "  [{}] scattered dir {:?} attenuation {:?}",
+                    bounce, scatterd.direction(), attenuation
+                );
+                let incoming = self.debug_ray(
+                    &scatterd, world, depth - 1, sampler, throughput * attenuation
+                );
+                emitted + attenuation * incoming
+            }
+            None => {
+                eprintln!("  [{}] absorbed", bounce);
+                emitted
+            }
+        }
     }
 
-    fn point_sample(origin: Point, i: u32, j: u32, dx: Vec3, dy: Vec3) -> Point {
-        let i = i as f64 + utils::randomf(0.0, 1.0);
-        let j = j as f64 + utils::randomf(0.0, 1.0);
-        origin + i * dy + j * dx
+    // Returns the sample's color and its position on the film in pixels.
+    pub fn sample_pixel(
+        &self, world: &Sence, i: u32, j: u32, sampler: &mut dyn Sampler, index: u32
+    ) -> (Color, (f64, f64)) {
+        sampler.start_pixel(j, i, index);
+        let (u, v) = sampler.get_2d();
+        let position = (j as f64 + u, i as f64 + v);
+        let Some(ray) = self.primary_ray(i, j, (u, v), sampler.get_2d()) else {
+            return (Color::default(), position);
+        };
+        let color = if self.spectral {
+            let mut lambda = SampledWavelengths::sample_uniform(sampler.get_1d());
+            let spectrum = self.ray_spectrum(&ray, world, self.depth, sampler, &mut lambda);
+            self.space.from_linear_srgb(spectrum.to_rgb(&lambda))
+        } else {
+            self.ray_color(&ray, world, self.depth, sampler)
+        };
+        (color, position)
     }
 
-    fn iter(&self) -> vec::IntoIter<Vec3> {
-        let dx = self.x / self.w as f64;
-        let dy = self.y / self.h as f64;
-        let mut points = Vec::new();
-        for i in 0 .. self.h {
-            for j in 0 .. self.w {
-                let point = Self::point_sample(self.o, i, j, dx, dy);
-                points.push(point);
+    fn primary_ray(&self, i: u32, j: u32, (u, v): (f64, f64), (a, b): (f64, f64)) -> Option<Ray> {
+        let s = (j as f64 + u) / self.width as f64;
+        let t = (i as f64 + v) / self.height as f64;
+        let Some(lens) = &self.lens else {
+            let lens = Vec3::in_unit_disk_from(a, b);
+            return self.projection.generate_ray(&self.frame, s, t, lens)
+                .map(|ray| ray.with_primary(true));
+        };
+        let point = lens.aperture().sample(a, b);
+        if lens.cat_eye() > 0.0 {
+            // Off axis the pupil is clipped by the rear of the barrel, seen
+            // as a unit disk shifted towards the image corner.
+            let corner = libm::sqrt(self.frame.aspect * self.frame.aspect + 1.0);
+            let shift = lens.cat_eye() * 2.0 / corner;
+            let x = point.x() - (s - 0.5) * self.frame.aspect * shift;
+            let y = point.y() + (t - 0.5) * shift;
+            if x * x + y * y > 1.0 {
+                return None;
             }
         }
-        points.into_iter()
+        self.projection.generate_ray(&self.frame, s, t, point)
+            .map(|ray| ray.with_primary(true))
     }
-}
 
-impl IntoIterator for &Viewport {
-    type Item = Point;
-    type IntoIter = vec::IntoIter<Self::Item>;
+    pub fn ray_color(
+        &self, ray: &Ray, world: &Sence, depth: u32, sampler: &mut dyn Sampler
+    ) -> Color {
+        if depth == 0 {
+            return Color::default();
+        }
+        if let Some(rec) = world.hit(ray, Interval::new(0.001, f64::INFINITY)) {
+            let emitted = self.space.from_linear_srgb(world.emitted(ray, &rec));
+            if let Some((scatterd, attenuation)) = world.scatter(ray, &rec, sampler) {
+                return emitted + self.space.from_linear_srgb(attenuation)
+                    * self.ray_color(&scatterd, world, depth - 1, sampler);
+            }
+            return emitted;
+        }
+        self.space.from_linear_srgb(world.background(ray))
+    }
 
-    fn into_iter(self) -> Self::IntoIter {
-        self.iter()
+    pub fn ray_spectrum(
+        &self, ray: &Ray, world: &Sence, depth: u32, sampler: &mut dyn Sampler,
+        lambda: &mut SampledWavelengths
+    ) -> SampledSpectrum {
+        if depth == 0 {
+            return SampledSpectrum::constant(0.0);
+        }
+        if let Some(rec) = world.hit(ray, Interval::new(0.001, f64::INFINITY)) {
+            let emitted = SampledSpectrum::from_rgb(world.emitted(ray, &rec), lambda);
+            if let Some((scatterd, attenuation)) =
+                world.scatter_spectral(ray, &rec, sampler, lambda)
+            {
+                let incoming = self.ray_spectrum(&scatterd, world, depth - 1, sampler, lambda);
+                return emitted + attenuation * incoming;
+            }
+            return emitted;
+        }
+        SampledSpectrum::from_rgb(world.background(ray), lambda)
     }
 }
 
 #[derive(Debug, Default, Clone, Copy)]
 pub struct Ray {
     origin: Point,
-    direction: Vec3
+    direction: Vec3,
+    primary: bool
 }
 
 impl Ray {
     pub fn new(origin: Point, direction: Vec3) -> Self {
-        Self { origin, direction }
+        Self { origin, direction, primary: false }
+    }
+
+    // Primary rays come straight from the camera, everything scattered is
+    // secondary.
+    pub fn with_primary(mut self, primary: bool) -> Self {
+        self.primary = primary;
+        self
+    }
+
+    pub fn is_primary(&self) -> bool {
+        self.primary
     }
 
     pub fn origin(&self) -> Point {
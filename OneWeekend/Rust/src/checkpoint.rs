@@ -0,0 +1,203 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use super::vector::Color;
+use super::adaptive::PixelStats;
+
+const MAGIC: &[u8; 8] = b"RTLCKPT1";
+// Magic, fingerprints and seed, then size and pass.
+const HEADER_BYTES: u64 = 8 + 4 * 8 + 3 * 4;
+// Film sum and weight, then the pixel's statistics.
+const PIXEL_BYTES: u64 = 4 * 8 + 4 + 2 * 8;
+
+// Accumulation state of an interrupted render. `scene`, `camera` and
+// `settings` are fingerprints used to refuse resuming a different render;
+// `seed` and `pass` pin the position in the sample sequence.
+#[derive(Debug, Clone)]
+pub(crate) struct Checkpoint {
+    pub scene: u64,
+    pub camera: u64,
+    pub settings: u64,
+    pub seed: u64,
+    pub width: u32,
+    pub height: u32,
+    pub pass: u32,
+    pub sum: Vec<Color>,
+    pub weight: Vec<f64>,
+    pub stats: Vec<PixelStats>,
+    pub active: Vec<bool>,
+    pub finished: Vec<bool>
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn write_u32(out: &mut impl Write, v: u32) -> io::Result<()> {
+    out.write_all(&v.to_le_bytes())
+}
+
+fn write_u64(out: &mut impl Write, v: u64) -> io::Result<()> {
+    out.write_all(&v.to_le_bytes())
+}
+
+fn write_f64(out: &mut impl Write, v: f64) -> io::Result<()> {
+    out.write_all(&v.to_le_bytes())
+}
+
+fn write_flags(out: &mut impl Write, flags: &[bool]) -> io::Result<()> {
+    write_u64(out, flags.len() as u64)?;
+    out.write_all(&flags.iter().map(|f| *f as u8).collect::<Vec<u8>>())
+}
+
+fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f64(input: &mut impl Read) -> io::Result<f64> {
+    Ok(f64::from_bits(read_u64(input)?))
+}
+
+fn read_flags(input: &mut impl Read, limit: usize) -> io::Result<Vec<bool>> {
+    let len = read_u64(input)? as usize;
+    if len > limit {
+        return Err(invalid("corrupt checkpoint"));
+    }
+    let mut bytes = vec![0; len];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes.into_iter().map(|b| b != 0).collect())
+}
+
+impl Checkpoint {
+    // Written to a temporary file first so a crash never leaves a
+    // truncated checkpoint behind.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let tmp = path.with_extension("tmp");
+        {
+            let mut out = BufWriter::new(File::create(&tmp)?);
+            out.write_all(MAGIC)?;
+            for v in [self.scene, self.camera, self.settings, self.seed] {
+                write_u64(&mut out, v)?;
+            }
+            for v in [self.width, self.height, self.pass] {
+                write_u32(&mut out, v)?;
+            }
+            for (sum, weight) in self.sum.iter().zip(&self.weight) {
+                for v in [sum.x(), sum.y(), sum.z(), *weight] {
+                    write_f64(&mut out, v)?;
+                }
+            }
+            for stats in &self.stats {
+                let (count, mean, m2) = stats.parts();
+                write_u32(&mut out, count)?;
+                write_f64(&mut out, mean)?;
+                write_f64(&mut out, m2)?;
+            }
+            write_flags(&mut out, &self.active)?;
+            write_flags(&mut out, &self.finished)?;
+            out.flush()?;
+        }
+        fs::rename(tmp, path)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let length = file.metadata()?.len();
+        let mut input = BufReader::new(file);
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a checkpoint file"));
+        }
+        let scene = read_u64(&mut input)?;
+        let camera = read_u64(&mut input)?;
+        let settings = read_u64(&mut input)?;
+        let seed = read_u64(&mut input)?;
+        let width = read_u32(&mut input)?;
+        let height = read_u32(&mut input)?;
+        let pass = read_u32(&mut input)?;
+        // The size comes from the file, check it fits before reading on.
+        let pixels = width as u64 * height as u64;
+        let needed = pixels.checked_mul(PIXEL_BYTES).and_then(|n| n.checked_add(HEADER_BYTES));
+        if needed.is_none_or(|needed| needed > length) {
+            return Err(invalid("truncated checkpoint"));
+        }
+        let size = pixels as usize;
+        let (mut sum, mut weight) = (Vec::new(), Vec::new());
+        for _ in 0 .. size {
+            let (x, y, z) = (read_f64(&mut input)?, read_f64(&mut input)?, read_f64(&mut input)?);
+            sum.push(Color::new(x, y, z));
+            weight.push(read_f64(&mut input)?);
+        }
+        let mut stats = Vec::new();
+        for _ in 0 .. size {
+            let count = read_u32(&mut input)?;
+            let (mean, m2) = (read_f64(&mut input)?, read_f64(&mut input)?);
+            stats.push(PixelStats::from_parts(count, mean, m2));
+        }
+        let active = read_flags(&mut input, size)?;
+        let finished = read_flags(&mut input, size)?;
+        if active.len() != size {
+            return Err(invalid("corrupt checkpoint"));
+        }
+        Ok(Self {
+            scene, camera, settings, seed, width, height, pass,
+            sum, weight, stats, active, finished
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let checkpoint = Checkpoint {
+            scene: 1, camera: 2, settings: 3, seed: 4, width: 2, height: 1, pass: 7,
+            sum: vec![Color::new(0.1, 0.2, 0.3), Color::new(1.0, 2.0, 3.0)],
+            weight: vec![7.0, 6.5],
+            stats: vec![PixelStats::from_parts(7, 0.2, 0.01), PixelStats::from_parts(6, 2.0, 0.5)],
+            active: vec![true, false],
+            finished: vec![false]
+        };
+        let path = std::env::temp_dir().join(format!("rtl-checkpoint-{}.bin", std::process::id()));
+        checkpoint.save(&path).unwrap();
+        let loaded = Checkpoint::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", checkpoint));
+    }
+
+    #[test]
+    fn corrupt_files_fail() {
+        let path = std::env::temp_dir().join(format!("rtl-corrupt-{}.bin", std::process::id()));
+        let checkpoint = Checkpoint {
+            scene: 1, camera: 2, settings: 3, seed: 4, width: 3, height: 2, pass: 1,
+            sum: vec![Color::default(); 6],
+            weight: vec![1.0; 6],
+            stats: vec![PixelStats::default(); 6],
+            active: vec![true; 6],
+            finished: vec![true]
+        };
+        checkpoint.save(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        // cut short, and claiming a huge image
+        let mut huge = bytes.clone();
+        huge[HEADER_BYTES as usize - 12 ..][.. 8].copy_from_slice(&[0xff; 8]);
+        for corrupt in [&bytes[.. bytes.len() - 3], &bytes[.. 60], &huge[..]] {
+            fs::write(&path, corrupt).unwrap();
+            let kind = Checkpoint::load(&path).unwrap_err().kind();
+            assert!(matches!(kind, io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof));
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,174 @@
+use super::vector::Color;
+use super::utils::Interval;
+
+const SRGB_TO_ACESCG: [[f64; 3]; 3] = [
+    [0.6130974024, 0.3395231462, 0.0473794514],
+    [0.0701937225, 0.9163538791, 0.0134523985],
+    [0.0206155929, 0.1095697729, 0.8698146342]
+];
+
+// Inverse of the above, so grays map back to themselves.
+const ACESCG_TO_SRGB: [[f64; 3]; 3] = [
+    [1.7050509927, -0.6217921207, -0.0832588720],
+    [-0.1302564176, 1.1408047365, -0.0105483191],
+    [-0.0240033568, -0.1289689760, 1.1529723328]
+];
+
+fn transform(m: &[[f64; 3]; 3], c: Color) -> Color {
+    Color::new(
+        m[0][0] * c.x() + m[0][1] * c.y() + m[0][2] * c.z(),
+        m[1][0] * c.x() + m[1][1] * c.y() + m[1][2] * c.z(),
+        m[2][0] * c.x() + m[2][1] * c.y() + m[2][2] * c.z()
+    )
+}
+
+// Working space of the renderer. Scene colors are authored in linear
+// Rec.709/sRGB primaries; the camera converts them with `from_linear_srgb`
+// as paths pick them up when rendering in ACEScg.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    #[default]
+    LinearSrgb,
+    AcesCg
+}
+
+impl ColorSpace {
+    pub fn from_linear_srgb(&self, color: Color) -> Color {
+        match self {
+            Self::LinearSrgb => color,
+            Self::AcesCg => transform(&SRGB_TO_ACESCG, color)
+        }
+    }
+
+    pub fn to_linear_srgb(&self, color: Color) -> Color {
+        match self {
+            Self::LinearSrgb => color,
+            Self::AcesCg => transform(&ACESCG_TO_SRGB, color)
+        }
+    }
+}
+
+// Transfer function applied when writing (or reading) color values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Linear,
+    Srgb,
+    Gamma(f64)
+}
+
+impl Encoding {
+    pub fn encode(&self, x: f64) -> f64 {
+        match self {
+            Self::Linear => x,
+            Self::Srgb => linear_to_srgb(x),
+            Self::Gamma(gamma) => libm::pow(x.max(0.0), 1.0 / gamma)
+        }
+    }
+
+    pub fn decode(&self, x: f64) -> f64 {
+        match self {
+            Self::Linear => x,
+            Self::Srgb => srgb_to_linear(x),
+            Self::Gamma(gamma) => libm::pow(x.max(0.0), *gamma)
+        }
+    }
+
+    pub fn encode_color(&self, color: Color) -> Color {
+        Color::new(self.encode(color.x()), self.encode(color.y()), self.encode(color.z()))
+    }
+
+    pub fn decode_color(&self, color: Color) -> Color {
+        Color::new(self.decode(color.x()), self.decode(color.y()), self.decode(color.z()))
+    }
+}
+
+pub fn srgb_to_linear(x: f64) -> f64 {
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        libm::pow((x + 0.055) / 1.055, 2.4)
+    }
+}
+
+pub fn linear_to_srgb(x: f64) -> f64 {
+    if x <= 0.0031308 {
+        x * 12.92
+    } else {
+        1.055 * libm::pow(x, 1.0 / 2.4) - 0.055
+    }
+}
+
+// Linear color from sRGB-encoded components in [0, 1], e.g. values picked
+// in an image editor.
+pub fn srgb(r: f64, g: f64, b: f64) -> Color {
+    Encoding::Srgb.decode_color(Color::new(r, g, b))
+}
+
+pub fn srgb8(r: u8, g: u8, b: u8) -> Color {
+    srgb(r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0)
+}
+
+pub fn to_rgb8(color: Color, encoding: Encoding) -> [u8; 3] {
+    let interval = Interval::new(0.000, 0.999);
+    let color = encoding.encode_color(color);
+    [
+        (interval.clamp(color.x()) * 256.0) as u8,
+        (interval.clamp(color.y()) * 256.0) as u8,
+        (interval.clamp(color.z()) * 256.0) as u8
+    ]
+}
+
+pub fn luminance(color: Color) -> f64 {
+    0.2126 * color.x() + 0.7152 * color.y() + 0.0722 * color.z()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn srgb_transfer() {
+        assert_close(linear_to_srgb(0.0), 0.0);
+        assert_close(linear_to_srgb(1.0), 1.0);
+        assert_close(linear_to_srgb(0.18), 0.46135);
+        assert_close(linear_to_srgb(0.5), 0.73536);
+        assert_close(srgb_to_linear(0.5), 0.21404);
+        assert_close(srgb_to_linear(0.04045), 0.0031308);
+        for i in 0 ..= 100 {
+            let x = i as f64 / 100.0;
+            assert_close(srgb_to_linear(linear_to_srgb(x)), x);
+        }
+    }
+
+    #[test]
+    fn srgb8_reference() {
+        let c = srgb8(128, 128, 128);
+        assert_close(c.x(), 0.21586);
+        assert_eq!(to_rgb8(c, Encoding::Srgb), [128, 128, 128]);
+        assert_eq!(to_rgb8(Color::new(1.0, 0.0, 2.0), Encoding::Srgb), [255, 0, 255]);
+    }
+
+    #[test]
+    fn acescg_reference() {
+        let red = ColorSpace::AcesCg.from_linear_srgb(Color::new(1.0, 0.0, 0.0));
+        assert_close(red.x(), 0.61310);
+        assert_close(red.y(), 0.07019);
+        assert_close(red.z(), 0.02062);
+
+        // Both spaces share an achromatic axis after adaptation.
+        let white = ColorSpace::AcesCg.from_linear_srgb(Color::new(1.0, 1.0, 1.0));
+        assert_close(white.x(), 1.0);
+        assert_close(white.y(), 1.0);
+        assert_close(white.z(), 1.0);
+
+        let c = Color::new(0.2, 0.5, 0.8);
+        let back = ColorSpace::AcesCg.to_linear_srgb(ColorSpace::AcesCg.from_linear_srgb(c));
+        assert_close(back.x(), 0.2);
+        assert_close(back.y(), 0.5);
+        assert_close(back.z(), 0.8);
+    }
+}
@@ -0,0 +1,139 @@
+use winit::event::{WindowEvent, ElementState, MouseButton, MouseScrollDelta, KeyEvent};
+use winit::keyboard::{PhysicalKey, KeyCode, ModifiersState};
+
+use super::vector::Vec3;
+use super::camera::Camera;
+
+const ROTATE_SPEED: f64 = 0.005;
+const PAN_SPEED: f64 = 0.001;
+const DOLLY_SPEED: f64 = 0.01;
+const MOVE_STEP: f64 = 0.05;
+
+// Rodrigues' rotation of `v` around the unit vector `axis`.
+fn rotate(v: Vec3, axis: Vec3, angle: f64) -> Vec3 {
+    let (sin, cos) = (libm::sin(angle), libm::cos(angle));
+    v * cos + axis.cross(&v) * sin + axis * (axis.dot(&v) * (1.0 - cos))
+}
+
+// Mouse and keyboard navigation for the preview window.
+//
+// Left drag orbits around the focus point, right drag pans, middle drag
+// dollies. WASD/QE fly the camera, the scroll wheel changes the field of
+// view and shift + scroll the focus distance. `R` restores the initial view.
+#[derive(Debug, Clone)]
+pub struct CameraController {
+    initial: Camera,
+    button: Option<MouseButton>,
+    cursor: Option<(f64, f64)>,
+    modifiers: ModifiersState
+}
+
+impl CameraController {
+    pub fn new(camera: &Camera) -> Self {
+        Self { initial: camera.clone(), button: None, cursor: None, modifiers: ModifiersState::empty() }
+    }
+
+    // Returns true when the camera was changed by the event.
+    pub fn handle_event(&mut self, event: &WindowEvent, camera: &mut Camera) -> bool {
+        match event {
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+                false
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                self.button = match state {
+                    ElementState::Pressed => Some(*button),
+                    ElementState::Released => None
+                };
+                false
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                let last = self.cursor.replace((position.x, position.y));
+                match (self.button, last) {
+                    (Some(button), Some((x, y))) => {
+                        self.drag(button, position.x - x, position.y - y, camera)
+                    }
+                    _ => false
+                }
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor = None;
+                false
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let delta = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y as f64,
+                    MouseScrollDelta::PixelDelta(p) => p.y / 20.0
+                };
+                if self.modifiers.shift_key() {
+                    let focal = camera.focus_distance() * libm::exp(delta * 0.05);
+                    camera.set_focus_distance(focal);
+                } else {
+                    camera.set_fov((camera.fov() - delta).clamp(1.0, 170.0));
+                }
+                true
+            }
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    physical_key: PhysicalKey::Code(code),
+                    state: ElementState::Pressed,
+                    ..
+                },
+                ..
+            } => self.key(*code, camera),
+            _ => false
+        }
+    }
+
+    fn drag(&self, button: MouseButton, dx: f64, dy: f64, camera: &mut Camera) -> bool {
+        let focal = camera.focus_distance();
+        let pivot = camera.origin() + camera.front() * focal;
+        match button {
+            MouseButton::Left => {
+                let vup = camera.vup().unit();
+                let offset = rotate(camera.origin() - pivot, vup, -dx * ROTATE_SPEED);
+                let right = (-offset).cross(&vup).unit();
+                let pitched = rotate(offset, right, -dy * ROTATE_SPEED);
+                // Stop short of the poles so the basis doesn't flip.
+                let offset = if libm::fabs(pitched.unit().dot(&vup)) < 0.99 {
+                    pitched
+                } else {
+                    offset
+                };
+                camera.set_view(pivot + offset, -offset);
+            }
+            MouseButton::Right => {
+                let shift = (camera.right() * -dx + camera.up() * dy) * (focal * PAN_SPEED);
+                camera.set_view(camera.origin() + shift, camera.front());
+            }
+            MouseButton::Middle => {
+                let offset = (camera.origin() - pivot) * libm::exp(dy * DOLLY_SPEED);
+                camera.set_view(pivot + offset, camera.front());
+                camera.set_focus_distance(offset.length());
+            }
+            _ => return false
+        }
+        true
+    }
+
+    fn key(&self, code: KeyCode, camera: &mut Camera) -> bool {
+        let step = camera.focus_distance() * MOVE_STEP;
+        let direction = match code {
+            KeyCode::KeyW => camera.front(),
+            KeyCode::KeyS => -camera.front(),
+            KeyCode::KeyD => camera.right(),
+            KeyCode::KeyA => -camera.right(),
+            KeyCode::KeyE => camera.vup().unit(),
+            KeyCode::KeyQ => -camera.vup().unit(),
+            KeyCode::KeyR => {
+                let (w, h) = (camera.width(), camera.height());
+                *camera = self.initial.clone();
+                camera.resize(w, h);
+                return true;
+            }
+            _ => return false
+        };
+        camera.set_view(camera.origin() + direction * step, camera.front());
+        true
+    }
+}
@@ -0,0 +1,233 @@
+use super::vector::Color;
+use super::utils::PI;
+
+// Pixel reconstruction filters, all separable. Radii are in pixels.
+#[derive(Debug, Clone, Copy)]
+pub enum Filter {
+    Box { radius: f64 },
+    Tent { radius: f64 },
+    Gaussian { radius: f64, sigma: f64 },
+    Mitchell { radius: f64, b: f64, c: f64 },
+    Lanczos { radius: f64, tau: f64 }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::Box { radius: 0.5 }
+    }
+}
+
+impl Filter {
+    pub fn radius(&self) -> f64 {
+        match self {
+            Self::Box { radius }
+            | Self::Tent { radius }
+            | Self::Gaussian { radius, .. }
+            | Self::Mitchell { radius, .. }
+            | Self::Lanczos { radius, .. } => *radius
+        }
+    }
+
+    pub fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+
+    // Volume under `evaluate`, by the midpoint rule along one axis.
+    pub fn integral(&self) -> f64 {
+        let steps = 1024;
+        let radius = self.radius();
+        let dx = 2.0 * radius / steps as f64;
+        let line: f64 = (0 .. steps)
+            .map(|i| self.evaluate_1d(-radius + (i as f64 + 0.5) * dx) * dx)
+            .sum();
+        line * line
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        let x = libm::fabs(x);
+        let radius = self.radius();
+        if x > radius {
+            return 0.0;
+        }
+        match *self {
+            Self::Box { .. } => 1.0,
+            Self::Tent { radius } => radius - x,
+            Self::Gaussian { radius, sigma } => {
+                let g = |x: f64| libm::exp(-x * x / (2.0 * sigma * sigma));
+                libm::fmax(0.0, g(x) - g(radius))
+            }
+            Self::Mitchell { radius, b, c } => {
+                let x = 2.0 * x / radius;
+                if x > 1.0 {
+                    ((-b - 6.0 * c) * x * x * x + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)) / 6.0
+                } else {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x + (6.0 - 2.0 * b)) / 6.0
+                }
+            }
+            Self::Lanczos { tau, .. } => sinc(x) * sinc(x / tau)
+        }
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x < 1e-5 {
+        1.0
+    } else {
+        libm::sin(PI * x) / (PI * x)
+    }
+}
+
+// Pixels whose total weight is below this, about a twentieth of one
+// sample's, are left black. Only there can the negative lobes of Mitchell
+// and Lanczos blow the pixel up or flip its sign.
+const MIN_WEIGHT: f64 = 0.05;
+
+// Accumulates filter-weighted samples; each sample is splatted onto every
+// pixel whose center lies within the filter radius. Weights are scaled so
+// the filter integrates to 1.
+#[derive(Debug, Clone)]
+pub struct Film {
+    width: u32,
+    height: u32,
+    filter: Filter,
+    scale: f64,
+    sum: Vec<Color>,
+    weight: Vec<f64>
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32, filter: Filter) -> Self {
+        let size = (width * height) as usize;
+        Self {
+            width, height, filter, scale: 1.0 / filter.integral(),
+            sum: vec![Color::default(); size], weight: vec![0.0; size]
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn filter(&self) -> Filter {
+        self.filter
+    }
+
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+        self.scale = 1.0 / filter.integral();
+        self.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.sum.iter_mut().for_each(|s| *s = Color::default());
+        self.weight.iter_mut().for_each(|w| *w = 0.0);
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        *self = Self::new(width, height, self.filter);
+    }
+
+    // `x` and `y` are continuous film coordinates, pixel (j, i) covers
+    // [j, j + 1) x [i, i + 1).
+    pub fn add_sample(&mut self, x: f64, y: f64, color: Color) {
+        let radius = self.filter.radius();
+        // Pixels in (x - radius, x + radius], so a sample on a pixel edge
+        // isn't counted by the box filter on both sides.
+        let (x, y) = (x - 0.5, y - 0.5);
+        let x0 = (libm::floor(x - radius) + 1.0).max(0.0) as u32;
+        let y0 = (libm::floor(y - radius) + 1.0).max(0.0) as u32;
+        let x1 = (libm::floor(x + radius) + 1.0).clamp(0.0, self.width as f64) as u32;
+        let y1 = (libm::floor(y + radius) + 1.0).clamp(0.0, self.height as f64) as u32;
+        for py in y0 .. y1 {
+            for px in x0 .. x1 {
+                let w = self.filter.evaluate(px as f64 - x, py as f64 - y) * self.scale;
+                if w != 0.0 {
+                    let index = (py * self.width + px) as usize;
+                    self.sum[index] += color * w;
+                    self.weight[index] += w;
+                }
+            }
+        }
+    }
+
+    pub fn raw(&self) -> (&[Color], &[f64]) {
+        (&self.sum, &self.weight)
+    }
+
+    pub fn restore(&mut self, sum: Vec<Color>, weight: Vec<f64>) {
+        assert_eq!(sum.len(), self.sum.len());
+        assert_eq!(weight.len(), self.weight.len());
+        self.sum = sum;
+        self.weight = weight;
+    }
+
+    pub fn pixel(&self, index: usize) -> Color {
+        let w = self.weight[index];
+        if w < MIN_WEIGHT {
+            Color::default()
+        } else {
+            self.sum[index] / w
+        }
+    }
+
+    pub fn pixels(&self) -> Vec<Color> {
+        (0 .. self.sum.len()).map(|i| self.pixel(i)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILTERS: [Filter; 5] = [
+        Filter::Box { radius: 0.5 },
+        Filter::Tent { radius: 1.0 },
+        Filter::Gaussian { radius: 1.5, sigma: 0.5 },
+        Filter::Mitchell { radius: 2.0, b: 1.0 / 3.0, c: 1.0 / 3.0 },
+        Filter::Lanczos { radius: 3.0, tau: 3.0 }
+    ];
+
+    #[test]
+    fn filters_integrate_to_one() {
+        for filter in FILTERS {
+            // the weights one sample leaves on the pixel grid
+            for (x, y) in [(8.5, 8.5), (8.1, 8.7), (8.93, 8.02), (8.0, 9.0)] {
+                let mut film = Film::new(16, 16, filter);
+                film.add_sample(x, y, Color::new(1.0, 1.0, 1.0));
+                let total: f64 = film.raw().1.iter().sum();
+                assert!((total - 1.0).abs() < 0.05, "{:?} at {}, {}: {}", filter, x, y, total);
+            }
+        }
+    }
+
+    #[test]
+    fn constant_image_stays_constant() {
+        let color = Color::new(0.25, 0.5, 0.75);
+        for filter in FILTERS {
+            let mut film = Film::new(8, 6, filter);
+            for i in 0 .. 6 * 4 {
+                for j in 0 .. 8 * 4 {
+                    film.add_sample((j as f64 + 0.37) / 4.0, (i as f64 + 0.61) / 4.0, color);
+                }
+            }
+            for pixel in film.pixels() {
+                assert!((pixel - color).length() < 1e-9, "{:?}: {:?}", filter, pixel);
+            }
+        }
+    }
+
+    #[test]
+    fn negative_lobes_dont_blow_up() {
+        // a lone sample in the Mitchell filter's negative lobe
+        let mut film = Film::new(4, 1, FILTERS[3]);
+        film.add_sample(0.5 + 1.4, 0.5, Color::new(1.0, 1.0, 1.0));
+        assert!(film.raw().1[0] < 0.0);
+        assert!(film.pixel(0).near_zero());
+    }
+}
@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use ::gltf::{camera, image, mesh, Document};
+
+use crate::vector::{Vec3, Color, Point};
+use crate::camera::Camera;
+use crate::sence::{Sence, Hittable, Node, NodeId};
+use crate::material::{MaterialId, Pbr};
+use crate::mesh::{MeshData, TriangleMesh};
+use crate::texture::{Image, ImageTexture, SolidColor};
+use crate::transform::Transform;
+use crate::projection::Orthographic;
+use super::{invalid, Imported};
+
+const DEPTH: u32 = 50;
+// glTF cameras have no focus, the image plane just needs to be somewhere.
+const FOCUS_DISTANCE: f64 = 10.0;
+
+struct Loader<'a> {
+    document: &'a Document,
+    buffers: &'a [::gltf::buffer::Data],
+    images: &'a [image::Data],
+    // decoded images by (index, srgb)
+    textures: HashMap<(usize, bool), Arc<Image>>,
+    materials: Vec<MaterialId>,
+    default_material: Option<MaterialId>,
+    meshes: Vec<Vec<Arc<dyn Hittable>>>,
+    width: u32,
+    height: u32,
+    imported: Imported
+}
+
+// Imports the default scene of a `.gltf` or `.glb` file into `sence`,
+// keeping the node hierarchy. Cameras are set up for a `width` x `height`
+// image.
+pub fn load(
+    path: impl AsRef<Path>, sence: &mut Sence, width: u32, height: u32
+) -> io::Result<Imported> {
+    let (document, buffers, images) =
+        ::gltf::import(path.as_ref()).map_err(|e| invalid(e.to_string()))?;
+    let mut loader = Loader {
+        document: &document, buffers: &buffers, images: &images,
+        textures: HashMap::new(), materials: Vec::new(), default_material: None,
+        meshes: Vec::new(), width, height, imported: Imported::default()
+    };
+    for extension in document.extensions_used() {
+        loader.warn(format!("extension {} is not supported", extension));
+    }
+    loader.load_materials(sence)?;
+    loader.load_meshes(sence)?;
+    let scene = document.default_scene().or_else(|| document.scenes().next());
+    if document.scenes().len() > 1 {
+        loader.warn("only the default scene is imported");
+    }
+    if let Some(scene) = scene {
+        for node in scene.nodes() {
+            let id = loader.load_node(sence, None, &node);
+            loader.imported.roots.push(id);
+        }
+    }
+    Ok(loader.imported)
+}
+
+fn color(c: [f32; 4]) -> Color {
+    Color::new(c[0] as f64, c[1] as f64, c[2] as f64)
+}
+
+impl Loader<'_> {
+    fn warn(&mut self, message: impl Into<String>) {
+        self.imported.warnings.push(message.into());
+    }
+
+    fn image(&mut self, index: usize, srgb: bool) -> io::Result<Arc<Image>> {
+        if let Some(image) = self.textures.get(&(index, srgb)) {
+            return Ok(image.clone());
+        }
+        let data = self.images.get(index).ok_or_else(|| invalid("image index out of range"))?;
+        let (w, h) = (data.width as usize, data.height as usize);
+        let pixels = &data.pixels;
+        let image = match data.format {
+            image::Format::R8 => Image::from_rgb8(w, h, 1, pixels, srgb),
+            image::Format::R8G8 => Image::from_rgb8(w, h, 2, pixels, srgb),
+            image::Format::R8G8B8 => Image::from_rgb8(w, h, 3, pixels, srgb),
+            image::Format::R8G8B8A8 => Image::from_rgb8(w, h, 4, pixels, srgb),
+            image::Format::R16 | image::Format::R16G16
+            | image::Format::R16G16B16 | image::Format::R16G16B16A16 => {
+                let channels = pixels.len() / 2 / (w * h).max(1);
+                let values = pixels.chunks(2)
+                    .map(|b| u16::from_ne_bytes([b[0], b[1]]) as f64 / 65535.0);
+                Image::from_channels(w, h, channels, values, srgb)
+            }
+            image::Format::R32G32B32FLOAT | image::Format::R32G32B32A32FLOAT => {
+                let channels = pixels.len() / 4 / (w * h).max(1);
+                let values = pixels.chunks(4)
+                    .map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]]) as f64);
+                Image::from_channels(w, h, channels, values, false)
+            }
+        };
+        let image = Arc::new(image);
+        self.textures.insert((index, srgb), image.clone());
+        Ok(image)
+    }
+
+    fn texture(
+        &mut self, texture: ::gltf::Texture, tex_coord: u32, srgb: bool
+    ) -> io::Result<ImageTexture> {
+        if tex_coord != 0 {
+            self.warn(format!(
+                "texture {} uses texture coordinate set {}, set 0 is used instead",
+                texture.index(), tex_coord
+            ));
+        }
+        Ok(ImageTexture::new(self.image(texture.source().index(), srgb)?))
+    }
+
+    fn load_materials(&mut self, sence: &mut Sence) -> io::Result<()> {
+        for material in self.document.materials() {
+            let pbr = material.pbr_metallic_roughness();
+            let factor = color(pbr.base_color_factor());
+            let (metallic, roughness) = (pbr.metallic_factor() as f64, pbr.roughness_factor() as f64);
+            let mut result = match pbr.base_color_texture() {
+                Some(info) => {
+                    let texture = self.texture(info.texture(), info.tex_coord(), true)?;
+                    Pbr::new(texture.with_factor(factor), metallic, roughness)
+                }
+                None => Pbr::new(SolidColor(factor), metallic, roughness)
+            };
+            if let Some(info) = material.pbr_metallic_roughness().metallic_roughness_texture() {
+                let texture = self.texture(info.texture(), info.tex_coord(), false)?;
+                result = result.with_metallic_roughness(texture);
+            }
+            if let Some(normal) = material.normal_texture() {
+                let texture = self.texture(normal.texture(), normal.tex_coord(), false)?;
+                result = result.with_normal_map(texture, normal.scale() as f64);
+            }
+            if material.emissive_factor() != [0.0; 3] {
+                let index = material.index().unwrap_or(0);
+                self.warn(format!("emission of material {} is ignored", index));
+            }
+            let name = material.name().map_or_else(
+                || format!("material {}", material.index().unwrap_or(0)), str::to_string
+            );
+            self.materials.push(sence.add_material(name, result));
+        }
+        Ok(())
+    }
+
+    // White, fully metallic and rough, as the spec says.
+    fn default_material(&mut self, sence: &mut Sence) -> MaterialId {
+        *self.default_material.get_or_insert_with(|| {
+            sence.add_material("default", Pbr::new(SolidColor(Color::new(1.0, 1.0, 1.0)), 1.0, 1.0))
+        })
+    }
+
+    fn load_primitive(
+        &mut self, sence: &mut Sence, primitive: &mesh::Primitive
+    ) -> io::Result<Option<TriangleMesh>> {
+        if primitive.mode() != mesh::Mode::Triangles {
+            self.warn(format!("primitive mode {:?} is not supported", primitive.mode()));
+            return Ok(None);
+        }
+        let reader = primitive.reader(|buffer| Some(&self.buffers[buffer.index()]));
+        let Some(positions) = reader.read_positions() else {
+            self.warn("primitive without positions");
+            return Ok(None);
+        };
+        let vec3 = |p: [f32; 3]| Vec3::new(p[0] as f64, p[1] as f64, p[2] as f64);
+        let positions: Vec<Point> = positions.map(vec3).collect();
+        let mut data = MeshData {
+            indices: match reader.read_indices() {
+                Some(indices) => {
+                    let flat: Vec<usize> = indices.into_u32().map(|i| i as usize).collect();
+                    flat.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect()
+                }
+                None => (0 .. positions.len() / 3).map(|i| [3 * i, 3 * i + 1, 3 * i + 2]).collect()
+            },
+            positions,
+            normals: reader.read_normals().map_or_else(Vec::new, |n| n.map(vec3).collect()),
+            uvs: reader.read_tex_coords(0).map_or_else(Vec::new, |t| {
+                t.into_f32().map(|uv| (uv[0] as f64, uv[1] as f64)).collect()
+            }),
+            colors: reader.read_colors(0)
+                .map_or_else(Vec::new, |c| c.into_rgb_f32().map(vec3).collect())
+        };
+        data.validate();
+        let material = match primitive.material().index() {
+            Some(index) => *self.materials.get(index)
+                .ok_or_else(|| invalid("material index out of range"))?,
+            None => self.default_material(sence)
+        };
+        Ok(Some(TriangleMesh::new(data, material)))
+    }
+
+    fn load_meshes(&mut self, sence: &mut Sence) -> io::Result<()> {
+        for mesh in self.document.meshes() {
+            let mut primitives: Vec<Arc<dyn Hittable>> = Vec::new();
+            for primitive in mesh.primitives() {
+                if let Some(triangles) = self.load_primitive(sence, &primitive)? {
+                    primitives.push(Arc::new(triangles));
+                }
+            }
+            self.meshes.push(primitives);
+        }
+        Ok(())
+    }
+
+    fn camera(&mut self, camera: &camera::Camera, transform: Transform) -> Camera {
+        let origin = transform.point(Point::default());
+        let front = transform.vector(Vec3::new(0.0, 0.0, -1.0));
+        let up = transform.vector(Vec3::new(0.0, 1.0, 0.0));
+        match camera.projection() {
+            camera::Projection::Perspective(perspective) => {
+                let fov = (perspective.yfov() as f64).to_degrees();
+                Camera::new(
+                    origin, FOCUS_DISTANCE, fov, DEPTH, up, front, 0.0, self.width, self.height
+                )
+            }
+            camera::Projection::Orthographic(orthographic) => {
+                let mut result = Camera::new(
+                    origin, FOCUS_DISTANCE, 40.0, DEPTH, up, front, 0.0, self.width, self.height
+                );
+                result.set_projection(Orthographic::new(2.0 * orthographic.ymag() as f64));
+                result
+            }
+        }
+    }
+
+    fn load_node(
+        &mut self, sence: &mut Sence, parent: Option<NodeId>, node: &::gltf::Node
+    ) -> NodeId {
+        let name = node.name().map_or_else(|| format!("node {}", node.index()), str::to_string);
+        // column major in the file
+        let m = node.transform().matrix();
+        let matrix: [[f64; 4]; 4] =
+            std::array::from_fn(|r| std::array::from_fn(|c| m[c][r] as f64));
+        let transform = Transform::from_matrix(matrix).unwrap_or_else(|| {
+            self.warn(format!("node {} has a singular transform", name));
+            Transform::identity()
+        });
+        let mut graph_node = Node::new(name.clone()).with_transform(transform);
+        let primitives = node.mesh().map_or_else(Vec::new, |m| self.meshes[m.index()].clone());
+        if primitives.len() == 1 {
+            graph_node = graph_node.with_shared(primitives[0].clone());
+        }
+        let id = sence.insert(parent, graph_node);
+        if primitives.len() > 1 {
+            for (i, primitive) in primitives.into_iter().enumerate() {
+                let child = Node::new(format!("{} primitive {}", name, i)).with_shared(primitive);
+                sence.insert(Some(id), child);
+            }
+        }
+        if let Some(camera) = node.camera() {
+            let world = sence.world_transform(id).unwrap_or_default();
+            let camera_name = camera.name().map_or_else(|| name.clone(), str::to_string);
+            let result = self.camera(&camera, world);
+            self.imported.cameras.push((camera_name, result));
+        }
+        for child in node.children() {
+            self.load_node(sence, Some(id), &child);
+        }
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::camera::Ray;
+    use crate::utils::Interval;
+
+    const QUAD: &str = r#"{
+        "asset": { "version": "2.0" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0, 1] }],
+        "nodes": [
+            { "name": "quad", "mesh": 0, "translation": [0, 0, -5] },
+            { "name": "eye", "camera": 0 }
+        ],
+        "cameras": [{ "type": "perspective", "perspective": { "yfov": 0.5, "znear": 0.1 } }],
+        "materials": [{ "name": "red", "pbrMetallicRoughness": {
+            "baseColorFactor": [1, 0, 0, 1], "metallicFactor": 0 } }],
+        "meshes": [{ "primitives": [{
+            "attributes": { "POSITION": 0 }, "indices": 1, "material": 0 }] }],
+        "buffers": [{ "uri": "BIN", "byteLength": 60 }],
+        "bufferViews": [
+            { "buffer": 0, "byteOffset": 0, "byteLength": 48 },
+            { "buffer": 0, "byteOffset": 48, "byteLength": 12 }
+        ],
+        "accessors": [
+            { "bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3",
+              "min": [-1, -1, 0], "max": [1, 1, 0] },
+            { "bufferView": 1, "componentType": 5123, "count": 6, "type": "SCALAR" }
+        ]
+    }"#;
+
+    #[test]
+    fn imports_quad_and_camera() {
+        let dir = std::env::temp_dir();
+        let stem = format!("rtl-gltf-{}", std::process::id());
+        let mut bin = Vec::new();
+        for p in [[-1.0f32, -1.0, 0.0], [1.0, -1.0, 0.0], [1.0, 1.0, 0.0], [-1.0, 1.0, 0.0]] {
+            p.iter().for_each(|v| bin.extend_from_slice(&v.to_le_bytes()));
+        }
+        for i in [0u16, 1, 2, 0, 2, 3] {
+            bin.extend_from_slice(&i.to_le_bytes());
+        }
+        let bin_path = dir.join(format!("{}.bin", stem));
+        let gltf_path = dir.join(format!("{}.gltf", stem));
+        fs::write(&bin_path, &bin).unwrap();
+        fs::write(&gltf_path, QUAD.replace("BIN", &format!("{}.bin", stem))).unwrap();
+
+        let mut sence = Sence::new();
+        let imported = load(&gltf_path, &mut sence, 64, 64);
+        fs::remove_file(&bin_path).unwrap();
+        fs::remove_file(&gltf_path).unwrap();
+        let imported = imported.unwrap();
+
+        assert_eq!(imported.roots.len(), 2);
+        assert!(imported.warnings.is_empty());
+        let (name, camera) = &imported.cameras[0];
+        assert_eq!(name, "eye");
+        assert!((camera.fov() - 0.5f64.to_degrees()).abs() < 1e-9);
+
+        let ray = Ray::new(camera.origin(), camera.front());
+        let hit = sence.hit(&ray, Interval::new(0.001, f64::INFINITY)).unwrap();
+        assert!((hit.time() - 5.0).abs() < 1e-9);
+        assert!(hit.front());
+        assert_eq!(sence.materials().name(hit.material()), Some("red"));
+    }
+}
@@ -0,0 +1,26 @@
+// Loaders for scene and mesh files from other tools.
+
+use std::io;
+
+use super::camera::Camera;
+use super::sence::NodeId;
+
+pub mod gltf;
+pub mod pbrt;
+pub mod ply;
+pub mod stl;
+
+// What an importer added to the scene.
+#[derive(Debug, Default)]
+pub struct Imported {
+    // Top-level nodes created in the scene.
+    pub roots: Vec<NodeId>,
+    // Cameras found in the file, by name.
+    pub cameras: Vec<(String, Camera)>,
+    // Parts of the file that were skipped or approximated.
+    pub warnings: Vec<String>
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
@@ -0,0 +1,703 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::vector::{Vec3, Color, Point};
+use crate::camera::Camera;
+use crate::sence::{Background, Node, NodeId, Sence, Sphere};
+use crate::material::{Dielectric, DiffuseLight, Lambertian, MaterialId, Metal};
+use crate::mesh::{MeshData, TriangleMesh};
+use crate::transform::Transform;
+use super::{invalid, ply, Imported};
+
+const DEPTH: u32 = 50;
+
+// Render settings from the `Film` and `Sampler` directives, with pbrt's
+// defaults.
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub width: u32,
+    pub height: u32,
+    pub samples: u32,
+    pub output: Option<PathBuf>
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self { width: 640, height: 480, samples: 16, output: None }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Open,
+    Close
+}
+
+fn tokenize(text: &str) -> io::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '#' => {
+                chars.by_ref().take_while(|&c| c != '\n').for_each(drop);
+            }
+            '[' | ']' => {
+                chars.next();
+                tokens.push(if c == '[' { Token::Open } else { Token::Close });
+            }
+            '"' => {
+                chars.next();
+                let s: String = chars.by_ref().take_while(|&c| c != '"').collect();
+                tokens.push(Token::Str(s));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '[' | ']' | '"' | '#') {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Number(f64),
+    Text(String)
+}
+
+// Parameter list of a directive, `"type name" value(s)` pairs.
+#[derive(Debug, Default)]
+struct Params {
+    list: Vec<(String, String, Vec<Value>)>
+}
+
+impl Params {
+    fn get(&self, name: &str) -> Option<(&str, &[Value])> {
+        self.list.iter().find(|p| p.1 == name).map(|p| (p.0.as_str(), p.2.as_slice()))
+    }
+
+    fn numbers(&self, name: &str) -> Option<Vec<f64>> {
+        let (_, values) = self.get(name)?;
+        values.iter().map(|v| match v {
+            Value::Number(n) => Some(*n),
+            Value::Text(_) => None
+        }).collect()
+    }
+
+    fn number(&self, name: &str) -> Option<f64> {
+        self.numbers(name)?.first().copied()
+    }
+
+    fn string(&self, name: &str) -> Option<&str> {
+        match self.get(name)?.1.first()? {
+            Value::Text(s) => Some(s),
+            Value::Number(_) => None
+        }
+    }
+
+    fn boolean(&self, name: &str) -> Option<bool> {
+        self.string(name).map(|s| s == "true")
+    }
+
+    // RGB colors only, spectra and textures aren't supported.
+    fn color(&self, name: &str) -> Option<Color> {
+        let (ty, _) = self.get(name)?;
+        let v = self.numbers(name)?;
+        match (ty, v.as_slice()) {
+            ("rgb" | "color", [r, g, b]) => Some(Color::new(*r, *g, *b)),
+            _ => None
+        }
+    }
+
+    fn points(&self, name: &str) -> Option<Vec<Vec3>> {
+        let v = self.numbers(name)?;
+        Some(v.chunks_exact(3).map(|c| Vec3::new(c[0], c[1], c[2])).collect())
+    }
+}
+
+#[derive(Clone)]
+struct Attributes {
+    transform: Transform,
+    material: Option<MaterialId>,
+    // area light emission and two-sidedness
+    light: Option<(Color, bool)>
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    dir: PathBuf,
+    sence: &'a mut Sence,
+    options: Options,
+    imported: Imported,
+    attributes: Attributes,
+    stack: Vec<Attributes>,
+    named: HashMap<String, MaterialId>,
+    default_material: Option<MaterialId>,
+    camera: Option<(Transform, Params)>,
+    root: Option<NodeId>,
+    // Files being read and where their tokens end, to refuse cycles.
+    includes: Vec<(PathBuf, usize)>
+}
+
+// Loads the supported subset of a pbrt-v3 scene into `sence`. Returns the
+// camera, the film and sampler settings; everything skipped is listed in
+// the warnings.
+//
+// pbrt is left-handed. The world is mirrored about the camera's vertical
+// plane where needed so images come out the same way around as pbrt's.
+pub fn load(path: impl AsRef<Path>, sence: &mut Sence) -> io::Result<(Imported, Options)> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path)?;
+    let dir = path.parent().map_or_else(PathBuf::new, Path::to_path_buf);
+    parse_file(&text, &dir, Some(path), sence)
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+// Like `load`, with relative file names resolved against `dir`.
+pub fn parse(text: &str, dir: &Path, sence: &mut Sence) -> io::Result<(Imported, Options)> {
+    parse_file(text, dir, None, sence)
+}
+
+fn parse_file(
+    text: &str, dir: &Path, path: Option<&Path>, sence: &mut Sence
+) -> io::Result<(Imported, Options)> {
+    let mut parser = Parser {
+        tokens: tokenize(text)?, position: 0, dir: dir.to_path_buf(), sence,
+        options: Options::default(), imported: Imported::default(),
+        attributes: Attributes { transform: Transform::identity(), material: None, light: None },
+        stack: Vec::new(), named: HashMap::new(), default_material: None,
+        camera: None, root: None,
+        includes: path.map(|path| (canonical(path), usize::MAX)).into_iter().collect()
+    };
+    // pbrt scenes render black where nothing is hit unless lit otherwise
+    parser.sence.set_background(Background::Solid(Color::default()));
+    parser.run()?;
+    Ok((parser.imported, parser.options))
+}
+
+fn look_at(eye: Point, look: Point, up: Vec3) -> Option<Transform> {
+    let dir = (look - eye).unit();
+    let right = up.unit().cross(&dir);
+    if right.near_zero() {
+        return None;
+    }
+    let right = right.unit();
+    let new_up = dir.cross(&right);
+    let camera_to_world = Transform::from_matrix([
+        [right.x(), new_up.x(), dir.x(), eye.x()],
+        [right.y(), new_up.y(), dir.y(), eye.y()],
+        [right.z(), new_up.z(), dir.z(), eye.z()],
+        [0.0, 0.0, 0.0, 1.0]
+    ])?;
+    Some(camera_to_world.inverse())
+}
+
+impl Parser<'_> {
+    fn warn(&mut self, message: impl Into<String>) {
+        self.imported.warnings.push(message.into());
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn number(&mut self) -> io::Result<f64> {
+        match self.next() {
+            Some(Token::Word(w)) => w.parse().map_err(|_| invalid(format!("expected a number, got {}", w))),
+            other => Err(invalid(format!("expected a number, got {:?}", other)))
+        }
+    }
+
+    fn numbers<const N: usize>(&mut self) -> io::Result<[f64; N]> {
+        let mut values = [0.0; N];
+        for v in &mut values {
+            *v = self.number()?;
+        }
+        Ok(values)
+    }
+
+    // Numbers, optionally in brackets as for `Transform`.
+    fn bracketed<const N: usize>(&mut self) -> io::Result<[f64; N]> {
+        let open = self.tokens.get(self.position) == Some(&Token::Open);
+        if open {
+            self.position += 1;
+        }
+        let values = self.numbers()?;
+        if open && self.next() != Some(Token::Close) {
+            return Err(invalid("expected ]"));
+        }
+        Ok(values)
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        match self.next() {
+            Some(Token::Str(s)) => Ok(s),
+            other => Err(invalid(format!("expected a string, got {:?}", other)))
+        }
+    }
+
+    fn value(token: Token) -> io::Result<Value> {
+        match token {
+            Token::Str(s) => Ok(Value::Text(s)),
+            Token::Word(w) => match w.parse() {
+                Ok(n) => Ok(Value::Number(n)),
+                Err(_) if w == "true" || w == "false" => Ok(Value::Text(w)),
+                Err(_) => Err(invalid(format!("bad parameter value {}", w)))
+            },
+            other => Err(invalid(format!("unexpected {:?}", other)))
+        }
+    }
+
+    fn params(&mut self) -> io::Result<Params> {
+        let mut params = Params::default();
+        while let Some(Token::Str(declaration)) = self.tokens.get(self.position).cloned() {
+            let mut words = declaration.split_whitespace();
+            let (Some(ty), Some(name), None) = (words.next(), words.next(), words.next()) else {
+                break;
+            };
+            self.position += 1;
+            let mut values = Vec::new();
+            match self.next() {
+                Some(Token::Open) => loop {
+                    match self.next() {
+                        Some(Token::Close) => break,
+                        Some(token) => values.push(Self::value(token)?),
+                        None => return Err(invalid("unterminated parameter list"))
+                    }
+                },
+                Some(token) => values.push(Self::value(token)?),
+                None => return Err(invalid("missing parameter value"))
+            }
+            params.list.push((ty.to_string(), name.to_string(), values));
+        }
+        Ok(params)
+    }
+
+    fn concat(&mut self, transform: Transform) {
+        self.attributes.transform = self.attributes.transform * transform;
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        while let Some(token) = self.next() {
+            let Token::Word(directive) = token else {
+                return Err(invalid(format!("expected a directive, got {:?}", token)));
+            };
+            match directive.as_str() {
+                "Identity" => self.attributes.transform = Transform::identity(),
+                "Translate" => {
+                    let [x, y, z] = self.numbers()?;
+                    self.concat(Transform::translate(Vec3::new(x, y, z)));
+                }
+                "Scale" => {
+                    let [x, y, z] = self.numbers()?;
+                    self.concat(Transform::scale(Vec3::new(x, y, z)));
+                }
+                "Rotate" => {
+                    let [angle, x, y, z] = self.numbers()?;
+                    self.concat(Transform::rotate(Vec3::new(x, y, z), angle));
+                }
+                "LookAt" => {
+                    let [ex, ey, ez, lx, ly, lz, ux, uy, uz] = self.numbers()?;
+                    match look_at(Vec3::new(ex, ey, ez), Vec3::new(lx, ly, lz), Vec3::new(ux, uy, uz)) {
+                        Some(t) => self.concat(t),
+                        None => self.warn("degenerate LookAt ignored")
+                    }
+                }
+                "Transform" | "ConcatTransform" => {
+                    let m: [f64; 16] = self.bracketed()?;
+                    // given column by column
+                    let matrix = std::array::from_fn(|r| std::array::from_fn(|c| m[c * 4 + r]));
+                    match Transform::from_matrix(matrix) {
+                        Some(t) if directive == "Transform" => self.attributes.transform = t,
+                        Some(t) => self.concat(t),
+                        None => self.warn(format!("singular {} ignored", directive))
+                    }
+                }
+                "Camera" => {
+                    let kind = self.string()?;
+                    let params = self.params()?;
+                    if kind != "perspective" {
+                        self.warn(format!("camera {} is not supported, using perspective", kind));
+                    }
+                    self.camera = Some((self.attributes.transform, params));
+                }
+                "Film" => {
+                    self.string()?;
+                    let params = self.params()?;
+                    if let Some(w) = params.number("xresolution") {
+                        self.options.width = w as u32;
+                    }
+                    if let Some(h) = params.number("yresolution") {
+                        self.options.height = h as u32;
+                    }
+                    self.options.output = params.string("filename").map(PathBuf::from);
+                }
+                "Sampler" => {
+                    let kind = self.string()?;
+                    let params = self.params()?;
+                    if let Some(n) = params.number("pixelsamples") {
+                        self.options.samples = n as u32;
+                    }
+                    self.warn(format!("sampler {} mapped onto the renderer's sampler", kind));
+                }
+                "WorldBegin" => self.world_begin(),
+                "WorldEnd" => {}
+                "AttributeBegin" | "TransformBegin" => self.stack.push(self.attributes.clone()),
+                "AttributeEnd" | "TransformEnd" => {
+                    let saved = self.stack.pop().ok_or_else(|| invalid(format!("unmatched {}", directive)))?;
+                    if directive == "AttributeEnd" {
+                        self.attributes = saved;
+                    } else {
+                        self.attributes.transform = saved.transform;
+                    }
+                }
+                "Material" => {
+                    let kind = self.string()?;
+                    let params = self.params()?;
+                    let id = self.material(&kind, &params, None);
+                    self.attributes.material = Some(id);
+                }
+                "MakeNamedMaterial" => {
+                    let name = self.string()?;
+                    let params = self.params()?;
+                    let kind = params.string("type").unwrap_or("matte").to_string();
+                    let id = self.material(&kind, &params, Some(&name));
+                    self.named.insert(name, id);
+                }
+                "NamedMaterial" => {
+                    let name = self.string()?;
+                    match self.named.get(&name) {
+                        Some(&id) => self.attributes.material = Some(id),
+                        None => self.warn(format!("unknown named material {}", name))
+                    }
+                }
+                "AreaLightSource" => {
+                    let kind = self.string()?;
+                    let params = self.params()?;
+                    if kind != "diffuse" {
+                        self.warn(format!("area light {} is not supported", kind));
+                    }
+                    let emit = params.color("L").unwrap_or(Color::new(1.0, 1.0, 1.0));
+                    let scale = params.color("scale").unwrap_or(Color::new(1.0, 1.0, 1.0));
+                    let two_sided = params.boolean("twosided").unwrap_or(false);
+                    self.attributes.light = Some((emit * scale, two_sided));
+                }
+                "LightSource" => {
+                    let kind = self.string()?;
+                    let params = self.params()?;
+                    match (kind.as_str(), params.string("mapname")) {
+                        ("infinite", None) => {
+                            let l = params.color("L").unwrap_or(Color::new(1.0, 1.0, 1.0));
+                            let scale = params.color("scale").unwrap_or(Color::new(1.0, 1.0, 1.0));
+                            self.sence.set_background(Background::Solid(l * scale));
+                        }
+                        _ => self.warn(format!("light source {} is not supported", kind))
+                    }
+                }
+                "Shape" => {
+                    let kind = self.string()?;
+                    let params = self.params()?;
+                    self.shape(&kind, &params)?;
+                }
+                "Include" => {
+                    let file = self.string()?;
+                    let path = self.dir.join(&file);
+                    let text = fs::read_to_string(&path)?;
+                    let included = tokenize(&text)?;
+                    let path = canonical(&path);
+                    let position = self.position;
+                    self.includes.retain(|(_, end)| *end >= position);
+                    if self.includes.iter().any(|(open, _)| *open == path) {
+                        return Err(invalid("recursive Include"));
+                    }
+                    // files still open end after the included tokens now
+                    let count = included.len();
+                    for (_, end) in &mut self.includes {
+                        *end = end.saturating_add(count);
+                    }
+                    self.includes.push((path, position + count));
+                    self.tokens.splice(position .. position, included);
+                }
+                "ReverseOrientation" => self.warn("ReverseOrientation is ignored"),
+                "CoordinateSystem" | "CoordSysTransform" | "ObjectInstance" => {
+                    self.string()?;
+                    self.warn(format!("{} is not supported", directive));
+                }
+                "ObjectBegin" => {
+                    self.string()?;
+                    self.warn("object instancing is not supported, shapes are placed directly");
+                }
+                "ObjectEnd" => {}
+                "Texture" => {
+                    let name = self.string()?;
+                    self.string()?;
+                    self.string()?;
+                    self.params()?;
+                    self.warn(format!("texture {} is not supported", name));
+                }
+                "MakeNamedMedium" | "MediumInterface" => {
+                    self.string()?;
+                    if directive == "MediumInterface" {
+                        if let Some(Token::Str(_)) = self.tokens.get(self.position) {
+                            self.position += 1;
+                        }
+                    } else {
+                        self.params()?;
+                    }
+                    self.warn(format!("{} is not supported", directive));
+                }
+                "Integrator" | "PixelFilter" | "Accelerator" | "ColorSpace" => {
+                    self.string()?;
+                    self.params()?;
+                    self.warn(format!("{} is ignored", directive));
+                }
+                other => return Err(invalid(format!("unknown directive {}", other)))
+            }
+        }
+        if self.root.is_none() {
+            self.world_begin();
+        }
+        Ok(())
+    }
+
+    // Sets up the camera and the root node everything else goes under.
+    fn world_begin(&mut self) {
+        let (world_to_camera, params) = self.camera.take()
+            .unwrap_or_else(|| (Transform::identity(), Params::default()));
+        let to_world = world_to_camera.inverse();
+        let origin = to_world.point(Point::default());
+        let front = to_world.vector(Vec3::new(0.0, 0.0, 1.0)).unit();
+        let up = to_world.vector(Vec3::new(0.0, 1.0, 0.0)).unit();
+        let right = to_world.vector(Vec3::new(1.0, 0.0, 0.0)).unit();
+
+        // pbrt's fov spans the shorter image side
+        let (w, h) = (self.options.width, self.options.height);
+        let fov = params.number("fov").unwrap_or(90.0);
+        let fov = if w >= h {
+            fov
+        } else {
+            let half = libm::tan(fov.to_radians() / 2.0) * h as f64 / w as f64;
+            2.0 * libm::atan(half).to_degrees()
+        };
+        let focus = params.number("focaldistance").unwrap_or(1e6);
+        let lens = params.number("lensradius").unwrap_or(0.0);
+        let defocus = 2.0 * libm::atan(lens / focus).to_degrees();
+        let camera = Camera::new(origin, focus, fov, DEPTH, up, front, defocus, w, h);
+
+        // our image right is front x up; mirror the world if pbrt's differs
+        let mirror = if camera.right().dot(&right) < 0.0 {
+            let n = right;
+            let d = 2.0 * origin.dot(&n);
+            let m = |r: usize, c: usize| {
+                let identity = if r == c { 1.0 } else { 0.0 };
+                identity - 2.0 * n.axis(r) * n.axis(c)
+            };
+            Transform::from_matrix([
+                [m(0, 0), m(0, 1), m(0, 2), d * n.x()],
+                [m(1, 0), m(1, 1), m(1, 2), d * n.y()],
+                [m(2, 0), m(2, 1), m(2, 2), d * n.z()],
+                [0.0, 0.0, 0.0, 1.0]
+            ]).unwrap()
+        } else {
+            Transform::identity()
+        };
+        let root = self.sence.insert(None, Node::new("pbrt").with_transform(mirror));
+        self.root = Some(root);
+        self.imported.roots.push(root);
+        self.imported.cameras.push(("camera".to_string(), camera));
+        self.attributes.transform = Transform::identity();
+        self.stack.clear();
+    }
+
+    fn material(&mut self, kind: &str, params: &Params, name: Option<&str>) -> MaterialId {
+        let name = name.map_or_else(
+            || format!("pbrt {} {}", kind, self.sence.materials().len()), str::to_string
+        );
+        let kd = params.color("Kd");
+        let material_id = match kind {
+            "matte" => self.sence.add_material(name, Lambertian::new(kd.unwrap_or(Color::new(0.5, 0.5, 0.5)))),
+            "metal" => {
+                // reflectance at normal incidence from the complex IOR,
+                // copper by default as in pbrt
+                let eta = params.color("eta").unwrap_or(Color::new(0.2, 0.92, 1.1));
+                let k = params.color("k").unwrap_or(Color::new(3.91, 2.45, 2.14));
+                let f0 = |n: f64, k: f64| ((n - 1.0).powi(2) + k * k) / ((n + 1.0).powi(2) + k * k);
+                let albedo = Color::new(
+                    f0(eta.x(), k.x()), f0(eta.y(), k.y()), f0(eta.z(), k.z())
+                );
+                let roughness = params.number("roughness").unwrap_or(0.01);
+                self.sence.add_material(name, Metal::new(albedo, roughness))
+            }
+            "mirror" => {
+                let kr = params.color("Kr").unwrap_or(Color::new(0.9, 0.9, 0.9));
+                self.sence.add_material(name, Metal::new(kr, 0.0))
+            }
+            "glass" => {
+                let eta = params.number("eta").or_else(|| params.number("index")).unwrap_or(1.5);
+                self.sence.add_material(name, Dielectric::new(eta))
+            }
+            _ => {
+                self.warn(format!("material {} is not supported, using matte", kind));
+                self.sence.add_material(name, Lambertian::new(kd.unwrap_or(Color::new(0.5, 0.5, 0.5))))
+            }
+        };
+        if params.list.iter().any(|(ty, _, _)| ty == "texture" || ty == "spectrum") {
+            self.warn(format!("textured or spectral parameters of {} are ignored", kind));
+        }
+        material_id
+    }
+
+    fn current_material(&mut self) -> MaterialId {
+        if let Some((emit, two_sided)) = self.attributes.light {
+            let name = format!("pbrt area light {}", self.sence.materials().len());
+            return self.sence.add_material(name, DiffuseLight::new(emit).with_two_sided(two_sided));
+        }
+        match self.attributes.material {
+            Some(id) => id,
+            None => *self.default_material.get_or_insert_with(|| {
+                self.sence.add_material("pbrt default", Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+            })
+        }
+    }
+
+    fn shape(&mut self, kind: &str, params: &Params) -> io::Result<()> {
+        if self.root.is_none() {
+            self.warn("shape before WorldBegin");
+            self.world_begin();
+        }
+        let material = self.current_material();
+        let node = match kind {
+            "sphere" => {
+                let radius = params.number("radius").unwrap_or(1.0);
+                for clip in ["zmin", "zmax", "phimax"] {
+                    if params.get(clip).is_some() {
+                        self.warn(format!("sphere {} is ignored", clip));
+                    }
+                }
+                Node::new("sphere").with_object(Sphere::new(Point::default(), radius, material))
+            }
+            "trianglemesh" => {
+                let indices = params.numbers("indices").unwrap_or_default();
+                let positions = params.points("P").ok_or_else(|| invalid("trianglemesh without P"))?;
+                let indices = if indices.is_empty() && positions.len() == 3 {
+                    vec![[0, 1, 2]]
+                } else {
+                    indices.chunks_exact(3).map(|t| [t[0] as usize, t[1] as usize, t[2] as usize]).collect()
+                };
+                let uvs = params.numbers("uv").or_else(|| params.numbers("st")).unwrap_or_default();
+                let data = MeshData {
+                    positions,
+                    normals: params.points("N").unwrap_or_default(),
+                    // pbrt's v runs up
+                    uvs: uvs.chunks_exact(2).map(|t| (t[0], 1.0 - t[1])).collect(),
+                    colors: Vec::new(),
+                    indices
+                };
+                Node::new("trianglemesh").with_object(TriangleMesh::new(data, material))
+            }
+            "plymesh" => {
+                let file = params.string("filename").ok_or_else(|| invalid("plymesh without filename"))?;
+                let data = ply::load(self.dir.join(file))?;
+                Node::new(file.to_string()).with_object(TriangleMesh::new(data, material))
+            }
+            _ => {
+                self.warn(format!("shape {} is not supported", kind));
+                return Ok(());
+            }
+        };
+        self.sence.insert(self.root, node.with_transform(self.attributes.transform));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Ray;
+    use crate::sence::Hittable;
+    use crate::utils::Interval;
+
+    const SCENE: &str = r#"
+        # light on the right of pbrt's image, matte on the left
+        LookAt 0 0 -5  0 0 0  0 1 0
+        Camera "perspective" "float fov" [ 40 ]
+        Film "image" "integer xresolution" [ 200 ] "integer yresolution" 100
+        Sampler "halton" "integer pixelsamples" 4
+        Integrator "path"
+        WorldBegin
+        AttributeBegin
+            AreaLightSource "diffuse" "rgb L" [ 4 4 4 ]
+            Translate 1 0 0
+            Shape "sphere" "float radius" 0.5
+        AttributeEnd
+        Material "matte" "rgb Kd" [ 0.2 0.4 0.6 ]
+        Shape "sphere" "float radius" 0.5 "float zmax" 0.2
+        Shape "disk"
+        WorldEnd
+    "#;
+
+    #[test]
+    fn camera_shapes_and_lights() {
+        let mut sence = Sence::default();
+        let (imported, options) = parse(SCENE, Path::new("."), &mut sence).unwrap();
+        assert_eq!((options.width, options.height, options.samples), (200, 100, 4));
+        assert_eq!(imported.warnings.len(), 4, "{:?}", imported.warnings);
+
+        let camera = &imported.cameras[0].1;
+        assert!((camera.origin() - Vec3::new(0.0, 0.0, -5.0)).length() < 1e-9);
+        assert!((camera.fov() - 40.0).abs() < 1e-9);
+
+        // the light shows up on the right of our image as well
+        let at = camera.origin() + 5.0 * camera.front() + camera.right();
+        let ray = Ray::new(camera.origin(), at - camera.origin());
+        let rec = sence.hit(&ray, Interval::new(0.001, f64::INFINITY)).unwrap();
+        assert!((sence.emitted(&ray, &rec) - Color::new(4.0, 4.0, 4.0)).length() < 1e-9);
+
+        let ray = Ray::new(camera.origin(), camera.front());
+        let rec = sence.hit(&ray, Interval::new(0.001, f64::INFINITY)).unwrap();
+        assert!((rec.time() - 4.5).abs() < 1e-9);
+        assert!(sence.emitted(&ray, &rec).near_zero());
+    }
+
+    #[test]
+    fn includes_nest_but_dont_recurse() {
+        let dir = std::env::temp_dir().join(format!("rtl-pbrt-include-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, text: &str| fs::write(dir.join(name), text).unwrap();
+        write("ball.pbrt", "Shape \"sphere\" \"float radius\" 0.5");
+        write("twice.pbrt", "Include \"ball.pbrt\" Translate 2 0 0 Include \"ball.pbrt\"");
+        write("main.pbrt", "WorldBegin Include \"twice.pbrt\" Include \"ball.pbrt\" WorldEnd");
+        let mut sence = Sence::default();
+        load(dir.join("main.pbrt"), &mut sence).unwrap();
+        assert_eq!(sence.nodes().filter(|(_, node)| node.object().is_some()).count(), 3);
+
+        write("a.pbrt", "WorldBegin Include \"b.pbrt\" WorldEnd");
+        write("b.pbrt", "Include \"ball.pbrt\" Include \"a.pbrt\"");
+        let error = load(dir.join("a.pbrt"), &mut Sence::default()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        write("self.pbrt", "Include \"self.pbrt\"");
+        let error = parse("Include \"self.pbrt\"", &dir, &mut Sence::default()).unwrap_err();
+        assert_eq!(error.to_string(), "recursive Include");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
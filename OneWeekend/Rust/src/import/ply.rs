@@ -0,0 +1,306 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use crate::vector::{Vec3, Color};
+use crate::mesh::MeshData;
+use crate::color;
+use super::invalid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64
+}
+
+impl Scalar {
+    fn parse(name: &str) -> io::Result<Self> {
+        Ok(match name {
+            "char" | "int8" => Self::I8,
+            "uchar" | "uint8" => Self::U8,
+            "short" | "int16" => Self::I16,
+            "ushort" | "uint16" => Self::U16,
+            "int" | "int32" => Self::I32,
+            "uint" | "uint32" => Self::U32,
+            "float" | "float32" => Self::F32,
+            "double" | "float64" => Self::F64,
+            _ => return Err(invalid(format!("unknown PLY type {}", name)))
+        })
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8
+        }
+    }
+
+    // Maximum of the integer types, colors are normalized by it.
+    fn range(&self) -> f64 {
+        match self {
+            Self::I8 => i8::MAX as f64,
+            Self::U8 => u8::MAX as f64,
+            Self::I16 => i16::MAX as f64,
+            Self::U16 => u16::MAX as f64,
+            Self::I32 => i32::MAX as f64,
+            Self::U32 => u32::MAX as f64,
+            Self::F32 | Self::F64 => 1.0
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Property {
+    name: String,
+    // count type for lists
+    list: Option<Scalar>,
+    scalar: Scalar
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>
+}
+
+struct Reader<R> {
+    inner: R,
+    format: Format,
+    tokens: Vec<String>
+}
+
+impl<R: BufRead> Reader<R> {
+    fn token(&mut self) -> io::Result<String> {
+        while self.tokens.is_empty() {
+            let mut line = String::new();
+            if self.inner.read_line(&mut line)? == 0 {
+                return Err(invalid("unexpected end of PLY data"));
+            }
+            self.tokens = line.split_whitespace().rev().map(str::to_string).collect();
+        }
+        Ok(self.tokens.pop().unwrap())
+    }
+
+    fn read(&mut self, scalar: Scalar) -> io::Result<f64> {
+        if self.format == Format::Ascii {
+            let token = self.token()?;
+            return token.parse().map_err(|_| invalid(format!("bad PLY value {}", token)));
+        }
+        let mut bytes = [0; 8];
+        let bytes = &mut bytes[.. scalar.size()];
+        self.inner.read_exact(bytes)?;
+        if self.format == Format::BigEndian {
+            bytes.reverse();
+        }
+        Ok(match scalar {
+            Scalar::I8 => bytes[0] as i8 as f64,
+            Scalar::U8 => bytes[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            Scalar::U32 => u32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            Scalar::F32 => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            Scalar::F64 => f64::from_le_bytes(bytes.try_into().unwrap())
+        })
+    }
+}
+
+fn header(reader: &mut impl BufRead) -> io::Result<(Format, Vec<Element>)> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if line.trim() != "ply" {
+        return Err(invalid("not a PLY file"));
+    }
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("PLY header isn't terminated"));
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["end_header"] => break,
+            ["format", kind, _] => format = Some(match *kind {
+                "ascii" => Format::Ascii,
+                "binary_little_endian" => Format::LittleEndian,
+                "binary_big_endian" => Format::BigEndian,
+                _ => return Err(invalid(format!("unknown PLY format {}", kind)))
+            }),
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| invalid("bad PLY element count"))?,
+                properties: Vec::new()
+            }),
+            ["property", "list", count, scalar, name] => {
+                let element = elements.last_mut().ok_or_else(|| invalid("property before element"))?;
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    list: Some(Scalar::parse(count)?),
+                    scalar: Scalar::parse(scalar)?
+                });
+            }
+            ["property", scalar, name] => {
+                let element = elements.last_mut().ok_or_else(|| invalid("property before element"))?;
+                element.properties.push(Property {
+                    name: name.to_string(), list: None, scalar: Scalar::parse(scalar)?
+                });
+            }
+            [] | ["comment", ..] | ["obj_info", ..] => {}
+            _ => return Err(invalid(format!("bad PLY header line {}", line.trim())))
+        }
+    }
+    Ok((format.ok_or_else(|| invalid("PLY format missing"))?, elements))
+}
+
+// Reads a PLY mesh in ASCII or binary form. Uses the `vertex` element's
+// x/y/z, nx/ny/nz, red/green/blue and u/v (or s/t) properties and the
+// faces' index lists, which are fan triangulated. 8 and 16 bit colors are
+// taken as sRGB encoded, float colors as linear; pair the mesh with
+// `Lambertian::textured(VertexColor)` to render them.
+pub fn parse(mut input: impl BufRead) -> io::Result<MeshData> {
+    let (format, elements) = header(&mut input)?;
+    let mut reader = Reader { inner: input, format, tokens: Vec::new() };
+    let mut data = MeshData::default();
+    for element in &elements {
+        let find = |names: &[&str]| {
+            element.properties.iter().position(|p| names.contains(&p.name.as_str()))
+        };
+        let position = [find(&["x"]), find(&["y"]), find(&["z"])];
+        let normal = [find(&["nx"]), find(&["ny"]), find(&["nz"])];
+        let color = [
+            find(&["red", "r", "diffuse_red"]),
+            find(&["green", "g", "diffuse_green"]),
+            find(&["blue", "b", "diffuse_blue"])
+        ];
+        let uv = [find(&["u", "s", "texture_u"]), find(&["v", "t", "texture_v"])];
+        let indices = find(&["vertex_indices", "vertex_index"]);
+        let mut values = vec![0.0; element.properties.len()];
+        for _ in 0 .. element.count {
+            let mut list = Vec::new();
+            for (i, property) in element.properties.iter().enumerate() {
+                match property.list {
+                    Some(count) => {
+                        let count = reader.read(count)? as usize;
+                        let items: io::Result<Vec<f64>> =
+                            (0 .. count).map(|_| reader.read(property.scalar)).collect();
+                        if Some(i) == indices {
+                            list = items?;
+                        } else {
+                            items?;
+                        }
+                    }
+                    None => values[i] = reader.read(property.scalar)?
+                }
+            }
+            let get = |p: [Option<usize>; 3]| {
+                p.iter().all(Option::is_some).then(|| {
+                    Vec3::new(values[p[0].unwrap()], values[p[1].unwrap()], values[p[2].unwrap()])
+                })
+            };
+            match element.name.as_str() {
+                "vertex" => {
+                    data.positions.push(get(position).ok_or_else(|| invalid("vertex without x/y/z"))?);
+                    if let Some(n) = get(normal) {
+                        data.normals.push(n);
+                    }
+                    if let Some(c) = get(color) {
+                        let scalar = element.properties[color[0].unwrap()].scalar;
+                        data.colors.push(match scalar {
+                            Scalar::F32 | Scalar::F64 => c,
+                            _ => {
+                                let decode = |v: f64| color::srgb_to_linear(v / scalar.range());
+                                Color::new(decode(c.x()), decode(c.y()), decode(c.z()))
+                            }
+                        });
+                    }
+                    if let [Some(u), Some(v)] = uv {
+                        // PLY texture coordinates start at the bottom
+                        data.uvs.push((values[u], 1.0 - values[v]));
+                    }
+                }
+                "face" => {
+                    let list: Vec<usize> = list.iter().map(|&i| i as usize).collect();
+                    for k in 1 .. list.len().saturating_sub(1) {
+                        data.indices.push([list[0], list[k], list[k + 1]]);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    data.validate();
+    Ok(data)
+}
+
+pub fn load(path: impl AsRef<Path>) -> io::Result<MeshData> {
+    parse(BufReader::new(File::open(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "ply\nformat FORMAT 1.0\ncomment test\nelement vertex 4\n\
+        property float x\nproperty float y\nproperty float z\n\
+        property uchar red\nproperty uchar green\nproperty uchar blue\n\
+        element face 1\nproperty list uchar int vertex_indices\nend_header\n";
+
+    fn check(data: &MeshData) {
+        assert_eq!(data.positions.len(), 4);
+        assert_eq!(data.indices, [[0, 1, 2], [0, 2, 3]]);
+        assert!((data.positions[2] - Vec3::new(1.0, 1.0, 0.0)).length() < 1e-9);
+        assert!((data.colors[1] - Color::new(1.0, 0.0, 0.0)).length() < 1e-9);
+    }
+
+    const VERTICES: [([f32; 3], [u8; 3]); 4] = [
+        ([0.0, 0.0, 0.0], [255, 255, 255]),
+        ([1.0, 0.0, 0.0], [255, 0, 0]),
+        ([1.0, 1.0, 0.0], [0, 255, 0]),
+        ([0.0, 1.0, 0.0], [0, 0, 255])
+    ];
+
+    #[test]
+    fn ascii() {
+        let mut text = HEADER.replace("FORMAT", "ascii");
+        for (p, c) in VERTICES {
+            text += &format!("{} {} {} {} {} {}\n", p[0], p[1], p[2], c[0], c[1], c[2]);
+        }
+        text += "4 0 1 2 3\n";
+        check(&parse(text.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn binary() {
+        for (format, big) in [("binary_little_endian", false), ("binary_big_endian", true)] {
+            let mut bytes = HEADER.replace("FORMAT", format).into_bytes();
+            for (p, c) in VERTICES {
+                for v in p {
+                    bytes.extend(if big { v.to_be_bytes() } else { v.to_le_bytes() });
+                }
+                bytes.extend(c);
+            }
+            bytes.push(4);
+            for i in [0i32, 1, 2, 3] {
+                bytes.extend(if big { i.to_be_bytes() } else { i.to_le_bytes() });
+            }
+            check(&parse(bytes.as_slice()).unwrap());
+        }
+    }
+}
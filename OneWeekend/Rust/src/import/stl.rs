@@ -0,0 +1,86 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::vector::{Vec3, Point};
+use crate::mesh::MeshData;
+use super::invalid;
+
+// Reads an ASCII or binary STL file. Facet normals are ignored in favour
+// of the winding, triangles don't share vertices.
+pub fn parse(bytes: &[u8]) -> io::Result<MeshData> {
+    let binary = bytes.len() >= 84 && {
+        let count = u32::from_le_bytes(bytes[80 .. 84].try_into().unwrap()) as usize;
+        bytes.len() == 84 + count * 50
+    };
+    let positions = if binary { binary_positions(bytes) } else { ascii_positions(bytes)? };
+    let indices = (0 .. positions.len() / 3).map(|i| [3 * i, 3 * i + 1, 3 * i + 2]).collect();
+    Ok(MeshData { positions, indices, ..MeshData::default() })
+}
+
+fn binary_positions(bytes: &[u8]) -> Vec<Point> {
+    let float = |b: &[u8]| f32::from_le_bytes(b.try_into().unwrap()) as f64;
+    bytes[84 ..].chunks_exact(50).flat_map(|facet| {
+        // skip the normal, then three vertices
+        (0 .. 3).map(move |v| {
+            let b = &facet[12 + v * 12 ..];
+            Vec3::new(float(&b[0 .. 4]), float(&b[4 .. 8]), float(&b[8 .. 12]))
+        })
+    }).collect()
+}
+
+fn ascii_positions(bytes: &[u8]) -> io::Result<Vec<Point>> {
+    let text = std::str::from_utf8(bytes).map_err(|_| invalid("STL is neither binary nor text"))?;
+    if !text.trim_start().starts_with("solid") {
+        return Err(invalid("not an STL file"));
+    }
+    let mut positions = Vec::new();
+    for line in text.lines() {
+        let mut words = line.split_whitespace();
+        if words.next() != Some("vertex") {
+            continue;
+        }
+        let v: Vec<f64> = words.map(str::parse).collect::<Result<_, _>>()
+            .map_err(|_| invalid(format!("bad STL vertex {}", line.trim())))?;
+        if v.len() != 3 {
+            return Err(invalid(format!("bad STL vertex {}", line.trim())));
+        }
+        positions.push(Vec3::new(v[0], v[1], v[2]));
+    }
+    if positions.len() % 3 != 0 {
+        return Err(invalid("STL facet with other than three vertices"));
+    }
+    Ok(positions)
+}
+
+pub fn load(path: impl AsRef<Path>) -> io::Result<MeshData> {
+    parse(&fs::read(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRIANGLE: [[f32; 3]; 3] = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+
+    #[test]
+    fn ascii_and_binary_agree() {
+        let mut text = String::from("solid test\n  facet normal 0 0 1\n    outer loop\n");
+        for p in TRIANGLE {
+            text += &format!("      vertex {} {} {}\n", p[0], p[1], p[2]);
+        }
+        text += "    endloop\n  endfacet\nendsolid test\n";
+
+        let mut bytes = vec![0; 80];
+        bytes.extend(1u32.to_le_bytes());
+        for v in [[0.0f32, 0.0, 1.0]].iter().chain(&TRIANGLE) {
+            v.iter().for_each(|c| bytes.extend(c.to_le_bytes()));
+        }
+        bytes.extend([0, 0]);
+
+        for data in [parse(text.as_bytes()).unwrap(), parse(&bytes).unwrap()] {
+            assert_eq!(data.indices, [[0, 1, 2]]);
+            assert!((data.positions[1] - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-9);
+        }
+    }
+}
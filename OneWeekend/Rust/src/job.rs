@@ -0,0 +1,95 @@
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::vector::Color;
+use super::camera::Camera;
+use super::sence::Sence;
+use super::color::ColorSpace;
+use super::output;
+use super::Renderer;
+
+type Configure = Box<dyn Fn(&mut Renderer)>;
+
+// Finished image of one view, in the renderer's working color space.
+#[derive(Debug, Clone)]
+pub struct RenderedView {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Color>
+}
+
+impl RenderedView {
+    pub fn save(&self, path: impl AsRef<Path>, space: ColorSpace) -> io::Result<()> {
+        output::save(path, self.width, self.height, &self.pixels, space)
+    }
+}
+
+// Renders several named cameras of one scene in a single invocation. The
+// scene is built once and shared by every view.
+pub struct RenderJob {
+    world: Arc<Sence>,
+    samples: u32,
+    views: Vec<(String, Camera)>,
+    configure: Option<Configure>
+}
+
+impl RenderJob {
+    pub fn new(world: Sence, samples: u32) -> Self {
+        Self::with_world(Arc::new(world), samples)
+    }
+
+    pub fn with_world(world: Arc<Sence>, samples: u32) -> Self {
+        Self { world, samples, views: Vec::new(), configure: None }
+    }
+
+    pub fn world(&self) -> &Arc<Sence> {
+        &self.world
+    }
+
+    // Views render at their camera's resolution.
+    pub fn add_view(&mut self, name: impl Into<String>, camera: Camera) {
+        self.views.push((name.into(), camera));
+    }
+
+    pub fn views(&self) -> impl Iterator<Item = (&str, &Camera)> {
+        self.views.iter().map(|(name, camera)| (name.as_str(), camera))
+    }
+
+    // Called on every view's renderer before it starts, to set the sampler,
+    // filter, threads and so on.
+    pub fn configure(&mut self, configure: impl Fn(&mut Renderer) + 'static) {
+        self.configure = Some(Box::new(configure));
+    }
+
+    fn renderer(&self, camera: &Camera) -> Renderer {
+        let mut renderer = Renderer::with_world(
+            camera.width(), camera.height(), self.samples, camera.clone(), self.world.clone()
+        );
+        if let Some(configure) = &self.configure {
+            configure(&mut renderer);
+        }
+        renderer
+    }
+
+    pub fn render(&self) -> io::Result<Vec<RenderedView>> {
+        self.views.iter().map(|(name, camera)| {
+            let pixels = self.renderer(camera).render_image()?;
+            Ok(RenderedView {
+                name: name.clone(), width: camera.width(), height: camera.height(), pixels
+            })
+        }).collect()
+    }
+
+    // Writes each view to `<dir>/<name>.<extension>`.
+    pub fn render_to_dir(&self, dir: impl AsRef<Path>, extension: &str) -> io::Result<()> {
+        let dir = dir.as_ref();
+        for (name, camera) in &self.views {
+            let mut renderer = self.renderer(camera);
+            renderer.render_image()?;
+            renderer.save(dir.join(format!("{}.{}", name, extension)))?;
+        }
+        Ok(())
+    }
+}
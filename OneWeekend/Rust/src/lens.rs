@@ -0,0 +1,312 @@
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+use super::vector::Vec3;
+use super::utils::PI;
+use super::serialize::{invalid, Record};
+
+// Scene units are taken to be meters when converting lens dimensions.
+const MM_PER_UNIT: f64 = 1000.0;
+
+// Physical camera body and lens. Lengths are in millimeters except the
+// focus distance, which is in scene units.
+#[derive(Debug, Clone)]
+pub struct Lens {
+    sensor: (f64, f64),
+    focal_length: f64,
+    f_number: f64,
+    focus_distance: f64,
+    aperture: Aperture,
+    cat_eye: f64
+}
+
+impl Lens {
+    // Full-frame 36x24 mm sensor with a circular aperture focused at 10
+    // units.
+    pub fn new(focal_length: f64, f_number: f64) -> Self {
+        Self {
+            sensor: (36.0, 24.0), focal_length, f_number, focus_distance: 10.0,
+            aperture: Aperture::Circular, cat_eye: 0.0
+        }
+    }
+
+    pub fn with_sensor(mut self, width: f64, height: f64) -> Self {
+        self.sensor = (width, height);
+        self
+    }
+
+    pub fn with_focus_distance(mut self, distance: f64) -> Self {
+        self.focus_distance = distance;
+        self
+    }
+
+    pub fn with_aperture(mut self, aperture: Aperture) -> Self {
+        self.aperture = aperture;
+        self
+    }
+
+    // Strength of the optical vignetting: 0 disables it, 1 clips the pupil
+    // by a disk shifted one aperture radius off axis in the image corners.
+    pub fn with_cat_eye(mut self, strength: f64) -> Self {
+        self.cat_eye = strength.max(0.0);
+        self
+    }
+
+    pub fn focal_length(&self) -> f64 {
+        self.focal_length
+    }
+
+    pub fn f_number(&self) -> f64 {
+        self.f_number
+    }
+
+    pub fn focus_distance(&self) -> f64 {
+        self.focus_distance
+    }
+
+    pub fn aperture(&self) -> &Aperture {
+        &self.aperture
+    }
+
+    pub fn cat_eye(&self) -> f64 {
+        self.cat_eye
+    }
+
+    pub fn set_focal_length(&mut self, focal_length: f64) {
+        self.focal_length = focal_length;
+    }
+
+    pub fn set_f_number(&mut self, f_number: f64) {
+        self.f_number = f_number;
+    }
+
+    pub fn set_focus_distance(&mut self, distance: f64) {
+        self.focus_distance = distance;
+    }
+
+    // Sensor height actually exposed for an image of the given aspect, the
+    // sensor is cropped to fill the frame.
+    fn film_height(&self, aspect: f64) -> f64 {
+        let (w, h) = self.sensor;
+        if aspect >= w / h { w / aspect } else { h }
+    }
+
+    // Vertical field of view in degrees.
+    pub fn fov(&self, aspect: f64) -> f64 {
+        2.0 * libm::atan(self.film_height(aspect) / (2.0 * self.focal_length)).to_degrees()
+    }
+
+    // Focal length giving a vertical field of view in degrees.
+    pub fn focal_length_for(&self, fov: f64, aspect: f64) -> f64 {
+        self.film_height(aspect) / (2.0 * libm::tan(fov.to_radians() / 2.0))
+    }
+
+    // Radius of the entrance pupil in scene units.
+    pub fn aperture_radius(&self) -> f64 {
+        self.focal_length / self.f_number / 2.0 / MM_PER_UNIT
+    }
+
+    pub(crate) fn to_record(&self) -> Record {
+        Record::new("lens")
+            .with("sensor", vec![self.sensor.0, self.sensor.1])
+            .with("focal_length", self.focal_length)
+            .with("f_number", self.f_number)
+            .with("focus_distance", self.focus_distance)
+            .with("aperture", self.aperture.to_record())
+            .with("cat_eye", self.cat_eye)
+    }
+
+    pub(crate) fn from_record(record: &Record) -> io::Result<Self> {
+        let [w, h] = record.numbers("sensor")?[..] else {
+            return Err(invalid("sensor of lens needs 2 values"));
+        };
+        Ok(Self {
+            sensor: (w, h),
+            focal_length: record.number("focal_length")?,
+            f_number: record.number("f_number")?,
+            focus_distance: record.number("focus_distance")?,
+            aperture: Aperture::from_record(record.record("aperture")?)?,
+            cat_eye: record.number("cat_eye")?
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub enum Aperture {
+    #[default]
+    Circular,
+    // Regular polygon inscribed in the unit circle, rotation in degrees.
+    Polygon { blades: u32, rotation: f64 },
+    Mask(Arc<ApertureMask>)
+}
+
+impl Aperture {
+    // Maps a uniform 2D sample to a point on the aperture, within the unit
+    // disk in the lens plane.
+    pub fn sample(&self, u: f64, v: f64) -> Vec3 {
+        match self {
+            Self::Circular => Vec3::in_unit_disk_from(u, v),
+            Self::Polygon { blades, rotation } => {
+                let n = (*blades).max(3) as f64;
+                let scaled = u * n;
+                let k = scaled.floor().min(n - 1.0);
+                let u = scaled - k;
+                let step = 2.0 * PI / n;
+                let a = rotation.to_radians() + k * step;
+                let b = a + step;
+                // uniform point in the triangle (center, a, b)
+                let r = libm::sqrt(u);
+                let (wa, wb) = (r * (1.0 - v), r * v);
+                Vec3::new(
+                    wa * libm::cos(a) + wb * libm::cos(b),
+                    wa * libm::sin(a) + wb * libm::sin(b),
+                    0.0
+                )
+            }
+            Self::Mask(mask) => mask.sample(u, v)
+        }
+    }
+
+    fn to_record(&self) -> Record {
+        match self {
+            Self::Circular => Record::new("circular"),
+            Self::Polygon { blades, rotation } => Record::new("polygon")
+                .with("blades", *blades)
+                .with("rotation", *rotation),
+            // the sampling tables, which is all the mask keeps
+            Self::Mask(mask) => Record::new("mask")
+                .with("width", mask.width)
+                .with("height", mask.height)
+                .with("rows", mask.rows.clone())
+                .with("columns", mask.columns.clone())
+        }
+    }
+
+    fn from_record(record: &Record) -> io::Result<Self> {
+        match record.kind() {
+            "circular" => Ok(Self::Circular),
+            "polygon" => Ok(Self::Polygon {
+                blades: record.integer("blades")? as u32,
+                rotation: record.number("rotation")?
+            }),
+            "mask" => {
+                let (width, height) = (record.integer("width")?, record.integer("height")?);
+                let (rows, columns) = (record.numbers("rows")?, record.numbers("columns")?);
+                if width == 0 || rows.len() != height || columns.len() != width * height {
+                    return Err(invalid("aperture mask size mismatch"));
+                }
+                Ok(Self::Mask(Arc::new(ApertureMask { width, height, rows, columns })))
+            }
+            other => Err(invalid(format!("unknown aperture {}", other)))
+        }
+    }
+}
+
+// Aperture given by a grayscale transmission image, stretched over the
+// square [-1, 1]^2 of the lens plane. Sampled proportionally to the
+// transmission.
+#[derive(Debug)]
+pub struct ApertureMask {
+    width: usize,
+    height: usize,
+    rows: Vec<f64>,
+    columns: Vec<f64>
+}
+
+impl ApertureMask {
+    // `weights` are row-major, top row first.
+    pub fn new(width: usize, height: usize, weights: &[f64]) -> Self {
+        assert_eq!(weights.len(), width * height, "mask size mismatch");
+        let mut columns = Vec::with_capacity(width * height);
+        let mut rows = Vec::with_capacity(height);
+        let mut total = 0.0;
+        for row in weights.chunks(width) {
+            let mut sum = 0.0;
+            for &w in row {
+                sum += w.max(0.0);
+                columns.push(sum);
+            }
+            total += sum;
+            rows.push(total);
+        }
+        assert!(total > 0.0, "aperture mask is fully opaque");
+        Self { width, height, rows, columns }
+    }
+
+    // Loads an 8 or 16 bit PNG, using the first channel as transmission.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(io::Error::other)?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).map_err(io::Error::other)?;
+        let channels = info.color_type.samples();
+        let weights: Vec<f64> = buffer[.. info.buffer_size()]
+            .chunks(channels)
+            .map(|pixel| pixel[0] as f64 / 255.0)
+            .collect();
+        if weights.iter().all(|&w| w <= 0.0) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "aperture mask is fully opaque"));
+        }
+        Ok(Self::new(info.width as usize, info.height as usize, &weights))
+    }
+
+    // Picks an index from a cumulative table and remaps `u` within it.
+    fn pick(cdf: &[f64], u: f64) -> (usize, f64) {
+        let total = cdf[cdf.len() - 1];
+        let target = (u * total).min(total * (1.0 - f64::EPSILON));
+        let i = cdf.partition_point(|&c| c <= target).min(cdf.len() - 1);
+        let low = if i == 0 { 0.0 } else { cdf[i - 1] };
+        let width = cdf[i] - low;
+        let u = if width > 0.0 { ((target - low) / width).clamp(0.0, 1.0) } else { 0.5 };
+        (i, u)
+    }
+
+    pub fn sample(&self, u: f64, v: f64) -> Vec3 {
+        let (row, v) = Self::pick(&self.rows, v);
+        let start = row * self.width;
+        let (column, u) = Self::pick(&self.columns[start .. start + self.width], u);
+        let x = (column as f64 + u) / self.width as f64;
+        let y = (row as f64 + v) / self.height as f64;
+        Vec3::new(2.0 * x - 1.0, 1.0 - 2.0 * y, 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn polygon_samples_stay_inside() {
+        let aperture = Aperture::Polygon { blades: 6, rotation: 0.0 };
+        let apothem = libm::cos(PI / 6.0);
+        for i in 0 .. 64 {
+            for j in 0 .. 64 {
+                let p = aperture.sample((i as f64 + 0.5) / 64.0, (j as f64 + 0.5) / 64.0);
+                let angle = libm::atan2(p.y(), p.x()).rem_euclid(PI / 3.0) - PI / 6.0;
+                let distance = libm::sqrt(p.x() * p.x() + p.y() * p.y()) * libm::cos(angle);
+                assert!(distance <= apothem + 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn mask_skips_opaque_pixels() {
+        // only the top-right pixel transmits
+        let mask = ApertureMask::new(2, 2, &[0.0, 1.0, 0.0, 0.0]);
+        for i in 0 .. 16 {
+            let p = mask.sample(i as f64 / 16.0, 1.0 - i as f64 / 16.0);
+            assert!(p.x() >= 0.0 && p.y() >= 0.0);
+        }
+    }
+
+    #[test]
+    fn fov_matches_focal_length() {
+        let lens = Lens::new(50.0, 2.8);
+        let fov = lens.fov(1.5);
+        assert!((fov - 26.99).abs() < 0.01);
+        assert!((lens.focal_length_for(fov, 1.5) - 50.0).abs() < 1e-9);
+    }
+}
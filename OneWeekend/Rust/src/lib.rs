@@ -1,58 +1,397 @@
-use std::iter;
+use std::io;
 use std::rc::Rc;
+use std::cell::{Cell, OnceCell};
+use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::ops::RangeInclusive;
 use std::num::NonZeroU32;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use utils::Interval;
 use vector::Vec3;
-use winit::event::{Event, WindowEvent};
+use winit::event::{Event, StartCause, WindowEvent, KeyEvent, ElementState};
+use winit::keyboard::{PhysicalKey, KeyCode};
 use winit::event_loop::{ControlFlow, EventLoop};
-use winit::window::{Window, WindowBuilder};
+use winit::window::WindowBuilder;
 use winit::dpi::PhysicalSize;
 
 pub mod vector;
 pub mod camera;
 pub mod sence;
+pub mod transform;
+pub mod bvh;
+pub mod texture;
+pub mod mesh;
+pub mod import;
 pub mod material;
 pub mod utils;
+pub mod color;
+pub mod output;
+pub mod spectrum;
+pub mod sampler;
+pub mod adaptive;
+pub mod film;
+pub mod controls;
+pub mod projection;
+pub mod lens;
+pub mod scheduler;
+pub mod progressive;
+pub mod job;
+pub mod rig;
+pub mod animation;
+pub mod checkpoint;
+pub mod serialize;
+pub mod scenes;
+pub mod sdf;
 use camera::Camera;
 use sence::Sence;
+use color::{ColorSpace, Encoding};
+use sampler::SamplerKind;
+use adaptive::{AdaptiveSampling, DisplayMode};
+use film::{Film, Filter};
+use controls::CameraController;
+use scheduler::{Tile, TileOrder};
+use progressive::{Progress, Shared, State, TileCallback};
+use checkpoint::Checkpoint;
+use animation::CameraTrack;
+
+const FRAME_INTERVAL: Duration = Duration::from_millis(100);
 
 pub struct Renderer {
     width: u32,
     height: u32,
-    count: u32,
     samples: u32,
-    buffer: Vec<Vec3>,
-    camera: Camera,
-    world: Sence
+    world: Arc<Sence>,
+    space: ColorSpace,
+    sampler: SamplerKind,
+    seed: u64,
+    threads: usize,
+    display: DisplayMode,
+    callback: Option<TileCallback>,
+    scene_hash: OnceCell<u64>,
+    checkpoint: Option<(PathBuf, Duration)>,
+    last_checkpoint: Cell<Instant>,
+    shared: Arc<Shared>
 }
 
 impl Renderer {
     pub fn new(width: u32, height: u32, samples: u32, camera: Camera, world: Sence) -> Self {
-        let buffer = Vec::new();
-        Self { width, height, count: 1, buffer, camera, world, samples }
+        Self::with_world(width, height, samples, camera, Arc::new(world))
+    }
+
+    // Renderer over a scene that other renderers may be using as well.
+    pub fn with_world(
+        width: u32, height: u32, samples: u32, camera: Camera, world: Arc<Sence>
+    ) -> Self {
+        let film = Film::new(width, height, Filter::default());
+        let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        Self {
+            width, height, samples,
+            world,
+            space: ColorSpace::default(),
+            sampler: SamplerKind::default(),
+            seed: 0,
+            threads,
+            display: DisplayMode::default(),
+            callback: None,
+            scene_hash: OnceCell::new(),
+            checkpoint: None,
+            last_checkpoint: Cell::new(Instant::now()),
+            shared: Arc::new(Shared::new(State::new(camera, film, samples), threads))
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.shared.update(|state| state.reset());
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.shared.update(|state| state.resize(width, height));
+    }
+
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.shared.update(|state| {
+            state.film.set_filter(filter);
+            state.reset();
+        });
+    }
+
+    pub fn set_tiles(&mut self, size: u32, order: TileOrder) {
+        self.shared.update(|state| state.set_tiles(size, order));
+    }
+
+    pub fn set_tile_callback(
+        &mut self, callback: impl Fn(&Tile, u32, &[Vec3]) + Send + Sync + 'static
+    ) {
+        self.callback = Some(Arc::new(callback));
+    }
+
+    pub fn camera(&self) -> Camera {
+        self.shared.state.lock().unwrap().camera.clone()
+    }
+
+    // Replaces the camera, keeping the current resolution, and restarts
+    // sampling.
+    pub fn set_camera(&mut self, mut camera: Camera) {
+        camera.resize(self.width, self.height);
+        camera.set_color_space(self.space);
+        self.shared.update(|state| {
+            state.camera = camera;
+            state.reset();
+        });
+    }
+
+    pub fn set_crop(&mut self, crop: Option<Tile>) {
+        self.shared.update(|state| state.set_crop(crop));
+    }
+
+    // Traces `samples` paths through pixel (x, y) and logs every bounce.
+    pub fn debug_pixel(&self, x: u32, y: u32, samples: u32) -> Vec3 {
+        let camera = self.shared.state.lock().unwrap().camera.clone();
+        let mut sampler = self.sampler.build(self.samples, self.seed);
+        let mut sum = Vec3::default();
+        for index in 0 .. samples {
+            sum += camera.debug_pixel(&self.world, y, x, sampler.as_mut(), index);
+        }
+        let mean = sum / samples.max(1) as f64;
+        eprintln!("pixel ({}, {}) mean of {} samples {:?}", x, y, samples, mean);
+        mean
+    }
+
+    pub fn set_adaptive(&mut self, adaptive: Option<AdaptiveSampling>) {
+        self.shared.update(|state| state.adaptive = adaptive);
+    }
+
+    pub fn set_display(&mut self, display: DisplayMode) {
+        self.display = display;
+    }
+
+    pub fn set_sampler(&mut self, kind: SamplerKind, seed: u64) {
+        self.sampler = kind;
+        self.seed = seed;
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    // Working space of the film; scene colors are converted into it while
+    // tracing and the output is converted back for saving and display.
+    pub fn set_color_space(&mut self, space: ColorSpace) {
+        self.space = space;
+        self.shared.update(|state| {
+            state.camera.set_color_space(space);
+            state.reset();
+        });
+    }
+
+    pub fn progress(&self) -> Progress {
+        self.shared.state.lock().unwrap().progress()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let pixels = self.shared.state.lock().unwrap().film.pixels();
+        output::save(path, self.width, self.height, &pixels, self.space)
+    }
+
+    fn spawn_workers(&self) -> Vec<thread::JoinHandle<()>> {
+        self.shared.update(|state| state.set_quit(false));
+        (0 .. self.threads).map(|id| {
+            let shared = self.shared.clone();
+            let world = self.world.clone();
+            let sampler = self.sampler.build(self.samples, self.seed);
+            let callback = self.callback.clone();
+            thread::spawn(move || progressive::worker(id, shared, world, sampler, callback))
+        }).collect()
+    }
+
+    fn stop_workers(&self, workers: Vec<thread::JoinHandle<()>>) {
+        self.shared.update(|state| state.set_quit(true));
+        for worker in workers {
+            worker.join().unwrap();
+        }
+    }
+
+    // Renders without a window until all passes are done, calling `tick`
+    // regularly so in-progress results can be written out.
+    fn render_blocking(
+        &mut self, mut tick: impl FnMut(&Self) -> io::Result<()>
+    ) -> io::Result<()> {
+        self.shared.update(|state| state.set_paused(false));
+        let workers = self.spawn_workers();
+        let mut result = Ok(());
+        loop {
+            let state = self.shared.state.lock().unwrap();
+            if state.idle() {
+                break;
+            }
+            drop(self.shared.wake.wait_timeout(state, FRAME_INTERVAL).unwrap());
+            result = tick(self).and_then(|_| self.autosave());
+            if result.is_err() {
+                break;
+            }
+        }
+        self.stop_workers(workers);
+        result
+    }
+
+    pub fn render_image(&mut self) -> io::Result<Vec<Vec3>> {
+        self.render_blocking(|_| Ok(()))?;
+        Ok(self.shared.state.lock().unwrap().film.pixels())
+    }
+
+    // Headless render to an image file, rewritten every `interval` so
+    // in-progress results can be inspected.
+    pub fn render_to_file(&mut self, path: impl AsRef<Path>, interval: Duration) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut next = Instant::now().checked_add(interval);
+        self.render_blocking(|renderer| {
+            if next.is_some_and(|next| Instant::now() >= next) {
+                next = Instant::now().checked_add(interval);
+                renderer.save(path)?;
+            }
+            Ok(())
+        })?;
+        self.save(path)
+    }
+
+    // Renders frames `frames` of the animation at `fps`, writing
+    // `<dir>/frame_0001.png` and so on. The scene is reused between frames.
+    pub fn render_sequence(
+        &mut self, track: &CameraTrack, frames: RangeInclusive<u32>, fps: f64,
+        dir: impl AsRef<Path>
+    ) -> io::Result<()> {
+        let dir = dir.as_ref();
+        let mut camera = self.camera();
+        for frame in frames {
+            track.apply(&mut camera, frame as f64 / fps);
+            self.set_camera(camera.clone());
+            self.render_blocking(|_| Ok(()))?;
+            self.save(dir.join(format!("frame_{:04}.png", frame)))?;
+        }
+        Ok(())
+    }
+
+    // Hashed on the first checkpoint, the scene can't change afterwards.
+    fn scene_hash(&self) -> u64 {
+        *self.scene_hash.get_or_init(|| self.world.fingerprint())
+    }
+
+    fn fingerprints(&self, state: &State) -> (u64, u64) {
+        let camera = utils::fnv1a(format!("{:?}", state.camera).as_bytes());
+        let settings = format!("{} {:?}", state.settings(), self.sampler);
+        (camera, utils::fnv1a(settings.as_bytes()))
+    }
+
+    pub fn save_checkpoint(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let checkpoint = {
+            let state = self.shared.state.lock().unwrap();
+            let (camera, settings) = self.fingerprints(&state);
+            state.checkpoint(self.scene_hash(), camera, settings, self.seed)
+        };
+        checkpoint.save(path.as_ref())
     }
 
-    fn render(&mut self, window: &Rc<Window>, width: u32, height: u32) {
-        if self.count <= self.samples {
-            let count = self.count as f64;
-            self.buffer.resize((width * height) as usize, Vec3::default());
-            let tex = self.camera.render(&self.world, width, height);
-            for (bc, tc) in iter::zip(&mut self.buffer, tex) {
-                *bc = *bc * ((count - 1.0) / count) + tc / count;
+    // Continues an interrupted render. Fails without touching the current
+    // state if the scene, camera or render settings differ.
+    pub fn load_checkpoint(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let checkpoint = Checkpoint::load(path.as_ref())?;
+        let invalid =
+            |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        if checkpoint.scene != self.scene_hash() {
+            return Err(invalid("checkpoint was made for a different scene"));
+        }
+        if checkpoint.seed != self.seed {
+            return Err(invalid("checkpoint was made with a different sampler seed"));
+        }
+        self.shared.update(|state| {
+            let (camera, settings) = self.fingerprints(state);
+            if checkpoint.camera != camera {
+                return Err(invalid("checkpoint was made with a different camera"));
+            }
+            if checkpoint.settings != settings || !state.restore(checkpoint) {
+                return Err(invalid("checkpoint was made with different render settings"));
+            }
+            Ok(())
+        })
+    }
+
+    // Periodically writes a checkpoint while rendering.
+    pub fn set_checkpoint(&mut self, path: impl AsRef<Path>, interval: Duration) {
+        self.checkpoint = Some((path.as_ref().to_path_buf(), interval));
+        self.last_checkpoint.set(Instant::now());
+    }
+
+    fn autosave(&self) -> io::Result<()> {
+        match &self.checkpoint {
+            Some((path, interval)) if self.last_checkpoint.get().elapsed() >= *interval => {
+                self.last_checkpoint.set(Instant::now());
+                self.save_checkpoint(path)
+            }
+            _ => Ok(())
+        }
+    }
+
+    // Snapshot of the image in linear sRGB, taken under the lock so the
+    // workers are only blocked for the copy.
+    fn frame(&self) -> (Vec<Vec3>, Progress) {
+        let state = self.shared.state.lock().unwrap();
+        let pixels = match self.display {
+            DisplayMode::Image => state.film.pixels()
+                .into_iter()
+                .map(|c| self.space.to_linear_srgb(c))
+                .collect(),
+            DisplayMode::SampleHeatmap => state.stats.iter()
+                .map(|s| adaptive::heatmap(s.count(), self.samples))
+                .collect()
+        };
+        (pixels, state.progress())
+    }
+
+    fn title(progress: &Progress) -> String {
+        let status = if progress.cancelled {
+            " [cancelled]"
+        } else if progress.paused {
+            " [paused]"
+        } else if progress.done {
+            " [done]"
+        } else {
+            ""
+        };
+        format!(
+            "rtl - {}/{} spp - {:.2} Msps{}",
+            progress.pass, progress.samples, progress.sps / 1e6, status
+        )
+    }
+
+    // Space pauses and resumes, Escape cancels, H toggles the heatmap.
+    // P (handled in `run`) logs the paths through the pixel under the cursor.
+    fn shortcut(&mut self, code: KeyCode) -> bool {
+        match code {
+            KeyCode::Space => self.shared.update(|state| {
+                let paused = state.paused();
+                state.set_paused(!paused)
+            }),
+            KeyCode::Escape => self.shared.update(|state| state.cancel()),
+            KeyCode::KeyH => {
+                self.display = match self.display {
+                    DisplayMode::Image => DisplayMode::SampleHeatmap,
+                    DisplayMode::SampleHeatmap => DisplayMode::Image
+                };
             }
-            println!("Samples: {}", self.count);
-            self.count += 1;
-            window.request_redraw();
+            _ => return false
         }
+        true
     }
 
     pub fn run(&mut self) {
         let event_loop = EventLoop::new().unwrap();
+        let mut controller = CameraController::new(&self.shared.state.lock().unwrap().camera);
         let window = Rc::new(
             WindowBuilder::new()
                 .with_inner_size(PhysicalSize::new(self.width, self.height))
-                .with_resizable(false)
                 .build(&event_loop)
                 .unwrap()
         );
@@ -63,46 +402,116 @@ impl Renderer {
             NonZeroU32::new(self.width).unwrap(),
             NonZeroU32::new(self.height).unwrap(),
         ).unwrap();
+
+        let workers = self.spawn_workers();
+        let mut drawn = None;
+        let mut cursor = None;
     
-        event_loop.run(move |event, elwt| {
-            elwt.set_control_flow(ControlFlow::Wait);
-    
+        event_loop.run(|event, elwt| {
             match event {
+                Event::NewEvents(StartCause::ResumeTimeReached { .. }) => {
+                    window.request_redraw();
+                }
+                Event::AboutToWait => {
+                    if let Err(err) = self.autosave() {
+                        eprintln!("checkpoint failed: {}", err);
+                    }
+                    let state = self.shared.state.lock().unwrap();
+                    if !state.idle() || drawn != Some(state.version()) {
+                        let next = Instant::now() + FRAME_INTERVAL;
+                        elwt.set_control_flow(ControlFlow::WaitUntil(next));
+                    } else {
+                        elwt.set_control_flow(ControlFlow::Wait);
+                    }
+                }
                 Event::WindowEvent {
                     window_id,
                     event: WindowEvent::RedrawRequested
                 } if window_id == window.id() => {
+                    drawn = Some(self.shared.state.lock().unwrap().version());
+                    let (pixels, progress) = self.frame();
+                    window.set_title(&Self::title(&progress));
                     let mut buffer = surface.buffer_mut().unwrap();
-                    self.render(&window, self.width, self.height);
-                    for index in 0..self.buffer.len() {
+                    if buffer.len() != pixels.len() {
+                        return;
+                    }
+                    for (index, color) in pixels.into_iter().enumerate() {
                         let (r, g, b) = {
-                            let color = self.buffer[index];
-                            let interval = Interval::new(0.000, 0.999);
-                            let (r, g, b) = (
-                                interval.clamp(color.x()),
-                                interval.clamp(color.y()),
-                                interval.clamp(color.z())
-                            );
-                            let (r, g , b) = (libm::sqrt(r), libm::sqrt(g), libm::sqrt(b));
-                            (
-                                (r * 255.999) as u32,
-                                (g * 255.999) as u32,
-                                (b * 255.999) as u32
-                            )
+                            let [r, g, b] = color::to_rgb8(color, Encoding::Srgb);
+                            (r as u32, g as u32, b as u32)
                         };
                         buffer[index] = b | (g << 8) | (r << 16);
                     }
                     buffer.present().unwrap();
                 }
+                Event::WindowEvent {
+                    event: WindowEvent::KeyboardInput {
+                        event: KeyEvent {
+                            physical_key: PhysicalKey::Code(KeyCode::KeyP),
+                            state: ElementState::Pressed,
+                            repeat: false,
+                            ..
+                        },
+                        ..
+                    },
+                    window_id,
+                } if window_id == window.id() => {
+                    if let Some((x, y)) = cursor {
+                        self.debug_pixel(x, y, 4);
+                    }
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::KeyboardInput {
+                        event: KeyEvent {
+                            physical_key: PhysicalKey::Code(code),
+                            state: ElementState::Pressed,
+                            repeat: false,
+                            ..
+                        },
+                        ..
+                    },
+                    window_id,
+                } if window_id == window.id() && self.shortcut(code) => {
+                    window.request_redraw();
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::Resized(size),
+                    window_id,
+                } if window_id == window.id() => {
+                    if let (Some(width), Some(height)) =
+                        (NonZeroU32::new(size.width), NonZeroU32::new(size.height))
+                    {
+                        surface.resize(width, height).unwrap();
+                        self.resize(size.width, size.height);
+                        window.request_redraw();
+                    }
+                }
                 Event::WindowEvent {
                     event: WindowEvent::CloseRequested,
                     window_id,
                 } if window_id == window.id() => {
                     elwt.exit();
                 }
+                Event::WindowEvent { event, window_id } if window_id == window.id() => {
+                    if let WindowEvent::CursorMoved { position, .. } = event {
+                        let (x, y) = (position.x as u32, position.y as u32);
+                        cursor = (x < self.width && y < self.height).then_some((x, y));
+                    }
+                    let changed = self.shared.update(|state| {
+                        let changed = controller.handle_event(&event, &mut state.camera);
+                        if changed {
+                            state.reset();
+                        }
+                        changed
+                    });
+                    if changed {
+                        window.request_redraw();
+                    }
+                }
                 _ => {}
             }
         }).unwrap();
+        self.stop_workers(workers);
     }
 }
 
@@ -119,4 +528,128 @@ mod tests {
         let result = add(2, 2);
         assert_eq!(result, 4);
     }
+
+    #[test]
+    fn gray_scene_matches_across_color_spaces() {
+        use sence::{Background, Sphere};
+        use material::Lambertian;
+        use vector::Point;
+
+        let render = |space: ColorSpace, background: Vec3, objects: bool| {
+            let mut world = Sence::new();
+            world.set_background(Background::Solid(background));
+            let gray = world.add_material("gray", Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+            if objects {
+                world.push(Sphere::new(Point::new(0.0, 0.0, -1.0), 0.5, gray));
+                world.push(Sphere::new(Point::new(0.0, -100.5, -1.0), 100.0, gray));
+            }
+            let camera = Camera::new(
+                Point::new(0.0, 0.0, 0.0), 1.0, 90.0, 8, Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, 0.0, -1.0), 0.0, 16, 12
+            );
+            let mut renderer = Renderer::new(16, 12, 4, camera, world);
+            renderer.set_color_space(space);
+            let pixels = renderer.render_image().unwrap();
+            pixels.into_iter().map(|c| space.to_linear_srgb(c)).collect::<Vec<_>>()
+        };
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        let srgb = render(ColorSpace::LinearSrgb, white, true);
+        let aces = render(ColorSpace::AcesCg, white, true);
+        assert!(srgb.iter().any(|c| c.x() < 0.9));
+        for (a, b) in srgb.iter().zip(&aces) {
+            assert!((*a - *b).length() < 1e-6, "{:?} vs {:?}", a, b);
+        }
+        // a color seen directly comes back unchanged
+        let blue = Vec3::new(0.2, 0.5, 0.8);
+        for c in render(ColorSpace::AcesCg, blue, false) {
+            assert!((c - blue).length() < 1e-6, "{:?}", c);
+        }
+    }
+
+    #[test]
+    fn resume_refuses_changed_renders() {
+        use sence::Sphere;
+        use material::Lambertian;
+        use sdf::{Sdf, Function};
+        use vector::Point;
+
+        fn renderer(albedo: f64, z: f64, blob: impl sdf::Field + 'static) -> Renderer {
+            let mut world = Sence::new();
+            let albedo = Vec3::new(albedo, albedo, albedo);
+            let gray = world.add_material("gray", Lambertian::new(albedo));
+            world.push(Sphere::new(Point::new(0.0, 0.0, -1.0), 0.5, gray));
+            world.push(Sdf::new(blob, gray));
+            let camera = Camera::new(
+                Point::new(0.0, 0.0, z), 1.0, 90.0, 4, Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, 0.0, -1.0), 0.0, 8, 6
+            );
+            Renderer::new(8, 6, 2, camera, world)
+        }
+        let center = Point::new(1.0, 0.0, -2.0);
+        let blob = |fingerprint: u64| {
+            Function::new("blob", move |p: Point| (p - center).length() - 0.3)
+                .with_fingerprint(fingerprint)
+        };
+        let path = std::env::temp_dir().join(format!("rtl-resume-{}.bin", std::process::id()));
+        let mut original = renderer(0.5, 0.0, blob(1));
+        original.render_image().unwrap();
+        original.save_checkpoint(&path).unwrap();
+
+        assert!(renderer(0.5, 0.0, blob(1)).load_checkpoint(&path).is_ok());
+        let changed = [
+            renderer(0.6, 0.0, blob(1)),
+            renderer(0.5, 0.1, blob(1)),
+            // the function with other parameters
+            renderer(0.5, 0.0, blob(2))
+        ];
+        for mut renderer in changed {
+            let error = renderer.load_checkpoint(&path).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn crop_matches_full_render() {
+        use sence::Sphere;
+        use material::{Lambertian, Metal};
+        use vector::Point;
+
+        let mut world = Sence::new();
+        let gray = world.add_material("gray", Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+        let metal = world.add_material("metal", Metal::new(Vec3::new(0.8, 0.6, 0.2), 0.3));
+        world.push(Sphere::new(Point::new(0.0, 0.0, -1.0), 0.5, metal));
+        world.push(Sphere::new(Point::new(0.0, -100.5, -1.0), 100.0, gray));
+        let world = Arc::new(world);
+        let camera = Camera::new(
+            Point::new(0.0, 0.0, 0.0), 1.0, 90.0, 8, Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0), 0.0, 16, 12
+        );
+        let render = |samples: u32, crop: Option<Tile>| {
+            let mut renderer = Renderer::with_world(16, 12, samples, camera.clone(), world.clone());
+            renderer.set_sampler(SamplerKind::Halton, 7);
+            renderer.set_threads(3);
+            renderer.set_crop(crop);
+            renderer.render_image().unwrap()
+        };
+        let crop = Tile { x0: 5, y0: 3, x1: 13, y1: 9 };
+        let inside = |i: usize| crop.contains(i as u32 % 16, i as u32 / 16);
+        let full = render(4, None);
+        let cropped = render(4, Some(crop));
+        for (i, (a, b)) in full.iter().zip(&cropped).enumerate() {
+            let expected = if inside(i) { *a } else { Vec3::default() };
+            assert!((expected - *b).length() < 1e-12, "pixel {}: {:?} vs {:?}", i, a, b);
+        }
+        assert!(full.iter().enumerate().any(|(i, c)| inside(i) && !c.near_zero()));
+
+        // one sample per pixel straight from the camera
+        let full = render(1, None);
+        let mut sampler = SamplerKind::Halton.build(1, 7);
+        let region = camera.render_region(&world, sampler.as_mut(), 0, crop);
+        let expected = full.iter().enumerate().filter(|(i, _)| inside(*i)).map(|(_, c)| c);
+        assert_eq!(region.len(), crop.area() as usize);
+        for (a, b) in expected.zip(&region) {
+            assert!((*a - *b).length() < 1e-12, "{:?} vs {:?}", a, b);
+        }
+    }
 }
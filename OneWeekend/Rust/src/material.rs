@@ -1,33 +1,290 @@
+use std::fmt::{self, Debug};
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
 use super::vector::{Vec3, Color};
 use super::camera::Ray;
 use super::sence::HitRecord;
-use super::utils;
+use super::spectrum::{SampledSpectrum, SampledWavelengths};
+use super::sampler::Sampler;
+use super::texture::{SolidColor, Texture};
+use super::serialize::{self, invalid, Record, Value};
+
+pub trait Material: Send + Sync + Debug {
+    fn scatter(
+        &self, ray: &Ray, record: &HitRecord, sampler: &mut dyn Sampler
+    ) -> Option<(Ray, Color)>;
+
+    fn scatter_spectral(
+        &self, ray: &Ray, record: &HitRecord, sampler: &mut dyn Sampler,
+        lambda: &mut SampledWavelengths
+    ) -> Option<(Ray, SampledSpectrum)> {
+        let (scatterd, attenuation) = self.scatter(ray, record, sampler)?;
+        Some((scatterd, SampledSpectrum::from_rgb(attenuation, lambda)))
+    }
+
+    // Radiance leaving the surface by itself.
+    fn emitted(&self, _ray: &Ray, _record: &HitRecord) -> Color {
+        Color::default()
+    }
 
-pub trait Material {
-    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Option<(Ray, Color)>;
+    // Scene file form, None for materials that can't be saved.
+    fn to_record(&self) -> Option<Record> {
+        None
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
+// Handle to a material registered in a `MaterialLibrary`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MaterialId(usize);
+
+impl MaterialId {
+    pub fn index(&self) -> usize {
+        self.0
+    }
+
+    pub(crate) fn from_index(index: usize) -> Self {
+        Self(index)
+    }
+}
+
+// How often a material was hit and what the paths did there.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MaterialStats {
+    pub hits: u64,
+    pub scattered: u64,
+    pub absorbed: u64
+}
+
+#[derive(Default)]
+struct Counters {
+    hits: AtomicU64,
+    scattered: AtomicU64,
+    absorbed: AtomicU64
+}
+
+struct Entry {
+    name: String,
+    material: Arc<dyn Material>,
+    counters: Counters
+}
+
+// Materials of a scene, shared by every primitive that refers to them by
+// ID. Swapping the material behind an ID changes all of its users.
+#[derive(Default)]
+pub struct MaterialLibrary {
+    entries: Vec<Entry>
+}
+
+impl MaterialLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, material: impl Material + 'static) -> MaterialId {
+        self.add_shared(name, Arc::new(material))
+    }
+
+    pub fn add_shared(&mut self, name: impl Into<String>, material: Arc<dyn Material>) -> MaterialId {
+        self.entries.push(Entry { name: name.into(), material, counters: Counters::default() });
+        MaterialId(self.entries.len() - 1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, id: MaterialId) -> Option<&Arc<dyn Material>> {
+        Some(&self.entries.get(id.0)?.material)
+    }
+
+    pub fn name(&self, id: MaterialId) -> Option<&str> {
+        Some(&self.entries.get(id.0)?.name)
+    }
+
+    // First material called `name`.
+    pub fn find(&self, name: &str) -> Option<MaterialId> {
+        self.entries.iter().position(|e| e.name == name).map(MaterialId)
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = MaterialId> {
+        (0 .. self.entries.len()).map(MaterialId)
+    }
+
+    // Replaces the material behind `id`, returning the old one.
+    pub fn set(
+        &mut self, id: MaterialId, material: impl Material + 'static
+    ) -> Option<Arc<dyn Material>> {
+        let entry = self.entries.get_mut(id.0)?;
+        Some(std::mem::replace(&mut entry.material, Arc::new(material)))
+    }
+
+    pub fn stats(&self, id: MaterialId) -> Option<MaterialStats> {
+        let counters = &self.entries.get(id.0)?.counters;
+        Some(MaterialStats {
+            hits: counters.hits.load(Ordering::Relaxed),
+            scattered: counters.scattered.load(Ordering::Relaxed),
+            absorbed: counters.absorbed.load(Ordering::Relaxed)
+        })
+    }
+
+    pub fn reset_stats(&self) {
+        for entry in &self.entries {
+            entry.counters.hits.store(0, Ordering::Relaxed);
+            entry.counters.scattered.store(0, Ordering::Relaxed);
+            entry.counters.absorbed.store(0, Ordering::Relaxed);
+        }
+    }
+
+    // Counts a hit on the material and whether the path continued.
+    pub fn record(&self, id: MaterialId, scattered: bool) {
+        if let Some(entry) = self.entries.get(id.0) {
+            let counters = &entry.counters;
+            counters.hits.fetch_add(1, Ordering::Relaxed);
+            match scattered {
+                true => counters.scattered.fetch_add(1, Ordering::Relaxed),
+                false => counters.absorbed.fetch_add(1, Ordering::Relaxed)
+            };
+        }
+    }
+}
+
+// Statistics are left out so the output only depends on the materials.
+impl Debug for MaterialLibrary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.entries.iter().map(|e| (&e.name, &e.material)))
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Lambertian {
-    albedo: Color
+    albedo: Arc<dyn Texture>
 }
 
 impl Lambertian {
     pub fn new(albedo: Color) -> Self {
-        Self { albedo }
+        Self::textured(SolidColor(albedo))
+    }
+
+    // Albedo looked up per hit, e.g. from an image or vertex colors.
+    pub fn textured(albedo: impl Texture + 'static) -> Self {
+        Self { albedo: Arc::new(albedo) }
+    }
+
+    pub(crate) fn from_record(record: &Record) -> io::Result<Self> {
+        Ok(Self { albedo: serialize::texture(record.record("albedo")?)? })
     }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _: &Ray, record: &HitRecord) -> Option<(Ray, Color)> {
-        let mut dir = record.normal() + Vec3::random_unit_vector();
+    fn scatter(
+        &self, _: &Ray, record: &HitRecord, sampler: &mut dyn Sampler
+    ) -> Option<(Ray, Color)> {
+        let (u, v) = sampler.get_2d();
+        let mut dir = record.normal() + Vec3::unit_vector_from(u, v);
         if dir.near_zero() {
             dir = record.normal();
         }
         let scatterd = Ray::new(record.point(), dir);
-        let attenuation = self.albedo;
+        let attenuation = self.albedo.value(record);
         Some((scatterd, attenuation))
     }
+
+    fn to_record(&self) -> Option<Record> {
+        Some(Record::new("lambertian").with("albedo", self.albedo.to_record()?))
+    }
+}
+
+// Emitter that doesn't reflect. One-sided lights only emit on the side the
+// normal points to.
+#[derive(Debug, Clone)]
+pub struct DiffuseLight {
+    emit: Arc<dyn Texture>,
+    two_sided: bool
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Self {
+        Self::textured(SolidColor(emit))
+    }
+
+    pub fn textured(emit: impl Texture + 'static) -> Self {
+        Self { emit: Arc::new(emit), two_sided: true }
+    }
+
+    pub fn with_two_sided(mut self, two_sided: bool) -> Self {
+        self.two_sided = two_sided;
+        self
+    }
+
+    pub(crate) fn from_record(record: &Record) -> io::Result<Self> {
+        Ok(Self {
+            emit: serialize::texture(record.record("emit")?)?,
+            two_sided: record.boolean("two_sided")?
+        })
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _: &Ray, _: &HitRecord, _: &mut dyn Sampler) -> Option<(Ray, Color)> {
+        None
+    }
+
+    fn emitted(&self, _: &Ray, record: &HitRecord) -> Color {
+        if record.front() || self.two_sided {
+            self.emit.value(record)
+        } else {
+            Color::default()
+        }
+    }
+
+    fn to_record(&self) -> Option<Record> {
+        Some(Record::new("diffuse_light")
+            .with("emit", self.emit.to_record()?)
+            .with("two_sided", self.two_sided))
+    }
+}
+
+// Scatters uniformly in all directions, the phase function of
+// `ConstantMedium`s.
+#[derive(Debug, Clone)]
+pub struct Isotropic {
+    albedo: Arc<dyn Texture>
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Self {
+        Self::textured(SolidColor(albedo))
+    }
+
+    pub fn textured(albedo: impl Texture + 'static) -> Self {
+        Self { albedo: Arc::new(albedo) }
+    }
+
+    pub(crate) fn from_record(record: &Record) -> io::Result<Self> {
+        Ok(Self { albedo: serialize::texture(record.record("albedo")?)? })
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(
+        &self, _: &Ray, record: &HitRecord, sampler: &mut dyn Sampler
+    ) -> Option<(Ray, Color)> {
+        let (u, v) = sampler.get_2d();
+        let scatterd = Ray::new(record.point(), Vec3::unit_vector_from(u, v));
+        Some((scatterd, self.albedo.value(record)))
+    }
+
+    fn to_record(&self) -> Option<Record> {
+        Some(Record::new("isotropic").with("albedo", self.albedo.to_record()?))
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -41,13 +298,20 @@ impl Metal {
         let fuzz = if fuzz < 1.0 { fuzz } else { 1.0 };
         Self { albedo, fuzz }
     }
+
+    pub(crate) fn from_record(record: &Record) -> io::Result<Self> {
+        Ok(Self::new(record.vector("albedo")?, record.number("fuzz")?))
+    }
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Option<(Ray, Color)> {
+    fn scatter(
+        &self, ray: &Ray, record: &HitRecord, sampler: &mut dyn Sampler
+    ) -> Option<(Ray, Color)> {
+        let (u, v) = sampler.get_2d();
         let reflected = ray.direction().unit().reflect(&record.normal());
         let scattered = Ray::new(
-            record.point(), reflected + self.fuzz * Vec3::random_unit_vector()
+            record.point(), reflected + self.fuzz * Vec3::unit_vector_from(u, v)
         );
         if scattered.direction().dot(&record.normal()) > 0.0 {
             Some((scattered, self.albedo))
@@ -55,41 +319,261 @@ impl Material for Metal {
             None
         }
     }
+
+    fn to_record(&self) -> Option<Record> {
+        Some(Record::new("metal").with("albedo", self.albedo).with("fuzz", self.fuzz))
+    }
+}
+
+// Index of refraction, wavelengths in nanometers. Cauchy and Sellmeier
+// coefficients use micrometers as in the usual glass catalogues.
+#[derive(Debug, Clone, Copy)]
+pub enum Ior {
+    Constant(f64),
+    Cauchy { a: f64, b: f64 },
+    Sellmeier { b: [f64; 3], c: [f64; 3] }
+}
+
+impl Ior {
+    pub const BK7: Ior = Ior::Sellmeier {
+        b: [1.03961212, 0.231792344, 1.01046945],
+        c: [0.00600069867, 0.0200179144, 103.560653]
+    };
+    pub const DIAMOND: Ior = Ior::Sellmeier {
+        b: [0.3306, 4.3356, 0.0],
+        c: [0.030625, 0.011236, 0.0]
+    };
+
+    // Helium d-line, used when rendering without wavelengths.
+    pub const REFERENCE_WAVELENGTH: f64 = 587.56;
+
+    pub fn at(&self, lambda: f64) -> f64 {
+        let l = lambda / 1000.0;
+        match self {
+            Self::Constant(ir) => *ir,
+            Self::Cauchy { a, b } => a + b / (l * l),
+            Self::Sellmeier { b, c } => {
+                let l2 = l * l;
+                let n2 = 1.0 + (0 .. 3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>();
+                libm::sqrt(n2)
+            }
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Self::Constant(_))
+    }
+
+    fn to_record(self) -> Record {
+        match self {
+            Self::Constant(ir) => Record::new("constant").with("ior", ir),
+            Self::Cauchy { a, b } => Record::new("cauchy").with("a", a).with("b", b),
+            Self::Sellmeier { b, c } => Record::new("sellmeier")
+                .with("b", b.to_vec())
+                .with("c", c.to_vec())
+        }
+    }
+
+    fn from_record(record: &Record) -> io::Result<Self> {
+        let triple = |name| -> io::Result<[f64; 3]> {
+            record.numbers(name)?.try_into()
+                .map_err(|_| invalid(format!("{} of sellmeier needs 3 values", name)))
+        };
+        match record.kind() {
+            "constant" => Ok(Self::Constant(record.number("ior")?)),
+            "cauchy" => Ok(Self::Cauchy { a: record.number("a")?, b: record.number("b")? }),
+            "sellmeier" => Ok(Self::Sellmeier { b: triple("b")?, c: triple("c")? }),
+            other => Err(invalid(format!("unknown ior {}", other)))
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct Dielectric {
-    ir: f64
+    ior: Ior
 }
 
 impl Dielectric {
     pub fn new(ir: f64) -> Self {
-        Self { ir }
+        Self { ior: Ior::Constant(ir) }
+    }
+
+    pub fn with_ior(ior: Ior) -> Self {
+        Self { ior }
+    }
+
+    pub(crate) fn from_record(record: &Record) -> io::Result<Self> {
+        Ok(Self::with_ior(Ior::from_record(record.record("ior")?)?))
     }
 
-    fn reflectance(cosine: f64, ref_idx: f64) -> bool {
+    fn reflectance(cosine: f64, ref_idx: f64, u: f64) -> bool {
         let r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
         let r0 = r0 * r0;
-        (r0 + (1.0 - r0) * libm::pow(1.0 - cosine, 5.0)) > utils::randomf(0.0, 1.0)
+        (r0 + (1.0 - r0) * libm::pow(1.0 - cosine, 5.0)) > u
     }
-}
 
-impl Material for Dielectric {
-    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Option<(Ray, Color)> {
-        let attenuation = Color::new(1.0, 1.0, 1.0);
-        let refraction_ratio = if record.front() { 1.0 / self.ir } else { self.ir };
+    fn direction(ray: &Ray, record: &HitRecord, ir: f64, u: f64) -> Vec3 {
+        let refraction_ratio = if record.front() { 1.0 / ir } else { ir };
         let unit_direction = ray.direction().unit();
         let cos_theta = libm::fmin((-unit_direction).dot(&record.normal()), 1.0);
         let sin_theta = libm::sqrt(1.0 - cos_theta * cos_theta);
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let direction = if cannot_refract || Self::reflectance(cos_theta, refraction_ratio) {
+        if cannot_refract || Self::reflectance(cos_theta, refraction_ratio, u) {
             unit_direction.reflect(&record.normal())
         } else {
             unit_direction.refract(&record.normal(), refraction_ratio)
-        };
-        
+        }
+    }
+}
+
+impl Material for Dielectric {
+    fn scatter(
+        &self, ray: &Ray, record: &HitRecord, sampler: &mut dyn Sampler
+    ) -> Option<(Ray, Color)> {
+        let attenuation = Color::new(1.0, 1.0, 1.0);
+        let ir = self.ior.at(Ior::REFERENCE_WAVELENGTH);
+        let direction = Self::direction(ray, record, ir, sampler.get_1d());
         let scatterd = Ray::new(record.point(), direction);
         Some((scatterd, attenuation))
     }
+
+    fn scatter_spectral(
+        &self, ray: &Ray, record: &HitRecord, sampler: &mut dyn Sampler,
+        lambda: &mut SampledWavelengths
+    ) -> Option<(Ray, SampledSpectrum)> {
+        if self.ior.is_dispersive() {
+            lambda.terminate_secondary();
+        }
+        let ir = self.ior.at(lambda.hero());
+        let direction = Self::direction(ray, record, ir, sampler.get_1d());
+        let scatterd = Ray::new(record.point(), direction);
+        Some((scatterd, SampledSpectrum::constant(1.0)))
+    }
+
+    fn to_record(&self) -> Option<Record> {
+        Some(Record::new("dielectric").with("ior", self.ior.to_record()))
+    }
+}
+
+// glTF style metallic-roughness surface. Picks a specular lobe (fuzzy
+// reflection with fuzz = roughness^2) with probability given by the
+// metalness and a Schlick Fresnel term, otherwise a diffuse bounce.
+// The metallic-roughness texture stores roughness in green and metalness
+// in blue, both multiplied with the factors.
+#[derive(Debug, Clone)]
+pub struct Pbr {
+    base_color: Arc<dyn Texture>,
+    metallic: f64,
+    roughness: f64,
+    metallic_roughness: Option<Arc<dyn Texture>>,
+    normal_map: Option<(Arc<dyn Texture>, f64)>
+}
+
+impl Pbr {
+    pub fn new(base_color: impl Texture + 'static, metallic: f64, roughness: f64) -> Self {
+        Self {
+            base_color: Arc::new(base_color), metallic, roughness,
+            metallic_roughness: None, normal_map: None
+        }
+    }
+
+    pub fn with_metallic_roughness(mut self, texture: impl Texture + 'static) -> Self {
+        self.metallic_roughness = Some(Arc::new(texture));
+        self
+    }
+
+    // Tangent-space normal map, `scale` multiplies its x and y.
+    pub fn with_normal_map(mut self, texture: impl Texture + 'static, scale: f64) -> Self {
+        self.normal_map = Some((Arc::new(texture), scale));
+        self
+    }
+
+    pub(crate) fn from_record(record: &Record) -> io::Result<Self> {
+        let optional = |name| record.get(name).map(Value::as_record).transpose();
+        let normal_map = match optional("normal_map")? {
+            Some(map) => Some((serialize::texture(map.record("texture")?)?, map.number("scale")?)),
+            None => None
+        };
+        Ok(Self {
+            base_color: serialize::texture(record.record("base_color")?)?,
+            metallic: record.number("metallic")?,
+            roughness: record.number("roughness")?,
+            metallic_roughness: optional("metallic_roughness")?
+                .map(serialize::texture)
+                .transpose()?,
+            normal_map
+        })
+    }
+
+    fn shading_normal(&self, record: &HitRecord) -> Vec3 {
+        let n = record.normal();
+        let Some((texture, scale)) = &self.normal_map else {
+            return n;
+        };
+        let t = record.tangent() - n * n.dot(&record.tangent());
+        if t.near_zero() {
+            return n;
+        }
+        let t = t.unit();
+        let b = n.cross(&t);
+        let m = texture.value(record) * 2.0 - Vec3::new(1.0, 1.0, 1.0);
+        let perturbed = t * (m.x() * scale) + b * (m.y() * scale) + n * m.z();
+        if perturbed.near_zero() { n } else { perturbed.unit() }
+    }
+}
+
+impl Material for Pbr {
+    fn scatter(
+        &self, ray: &Ray, record: &HitRecord, sampler: &mut dyn Sampler
+    ) -> Option<(Ray, Color)> {
+        let base = self.base_color.value(record);
+        let (metallic, roughness) = match &self.metallic_roughness {
+            Some(texture) => {
+                let mr = texture.value(record);
+                (self.metallic * mr.z(), self.roughness * mr.y())
+            }
+            None => (self.metallic, self.roughness)
+        };
+        let normal = self.shading_normal(record);
+        let direction = ray.direction().unit();
+        let cos = (-direction).dot(&normal).clamp(0.0, 1.0);
+        let fresnel = 0.04 + 0.96 * libm::pow(1.0 - cos, 5.0);
+        let specular = metallic + (1.0 - metallic) * fresnel;
+        let choice = sampler.get_1d();
+        let (u, v) = sampler.get_2d();
+        if choice < specular {
+            let reflected = direction.reflect(&normal)
+                + roughness * roughness * Vec3::unit_vector_from(u, v);
+            if reflected.dot(&record.normal()) <= 0.0 {
+                return None;
+            }
+            // metal tints the reflection, the dielectric coat doesn't
+            let tint = base * metallic + Color::new(1.0, 1.0, 1.0) * ((1.0 - metallic) * fresnel);
+            Some((Ray::new(record.point(), reflected), tint / specular))
+        } else {
+            let mut scattered = normal + Vec3::unit_vector_from(u, v);
+            if scattered.near_zero() || scattered.dot(&record.normal()) <= 0.0 {
+                scattered = record.normal();
+            }
+            Some((Ray::new(record.point(), scattered), base))
+        }
+    }
+
+    fn to_record(&self) -> Option<Record> {
+        let mut record = Record::new("pbr")
+            .with("base_color", self.base_color.to_record()?)
+            .with("metallic", self.metallic)
+            .with("roughness", self.roughness);
+        if let Some(texture) = &self.metallic_roughness {
+            record = record.with("metallic_roughness", texture.to_record()?);
+        }
+        if let Some((texture, scale)) = &self.normal_map {
+            let map = Record::new("normal_map")
+                .with("texture", texture.to_record()?)
+                .with("scale", *scale);
+            record = record.with("normal_map", map);
+        }
+        Some(record)
+    }
 }
@@ -0,0 +1,215 @@
+use std::fmt;
+use std::io;
+
+use super::vector::{Vec3, Point, Color};
+use super::camera::Ray;
+use super::sence::{Hittable, HitRecord};
+use super::material::MaterialId;
+use super::bvh::{Aabb, Bvh};
+use super::utils::{self, Interval};
+use super::serialize::{invalid, Record};
+
+// Indexed triangle list. The optional attributes are either empty or have
+// one entry per position.
+#[derive(Debug, Clone, Default)]
+pub struct MeshData {
+    pub positions: Vec<Point>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub colors: Vec<Color>,
+    pub indices: Vec<[usize; 3]>
+}
+
+impl MeshData {
+    // Drops attributes whose length doesn't match and triangles with
+    // out-of-range indices.
+    pub fn validate(&mut self) {
+        let n = self.positions.len();
+        if self.normals.len() != n {
+            self.normals.clear();
+        }
+        if self.uvs.len() != n {
+            self.uvs.clear();
+        }
+        if self.colors.len() != n {
+            self.colors.clear();
+        }
+        self.indices.retain(|t| t.iter().all(|&i| i < n));
+    }
+
+    fn triangle_box(&self, t: &[usize; 3]) -> Aabb {
+        t.iter().fold(Aabb::empty(), |b, &i| b.grow(self.positions[i])).pad(1e-9)
+    }
+}
+
+// Triangle mesh with its own BVH.
+pub struct TriangleMesh {
+    data: MeshData,
+    material: MaterialId,
+    bvh: Bvh
+}
+
+impl TriangleMesh {
+    pub fn new(mut data: MeshData, material: MaterialId) -> Self {
+        data.validate();
+        let boxes: Vec<_> = data.indices.iter().map(|t| data.triangle_box(t)).collect();
+        Self { bvh: Bvh::new(&boxes), data, material }
+    }
+
+    pub(crate) fn from_record(record: &Record) -> io::Result<Self> {
+        let vectors = |name| -> io::Result<Vec<Vec3>> {
+            let v = record.numbers(name)?;
+            if v.len() % 3 != 0 {
+                return Err(invalid(format!("{} of mesh isn't a list of vectors", name)));
+            }
+            Ok(v.chunks(3).map(|c| Vec3::new(c[0], c[1], c[2])).collect())
+        };
+        let indices = record.numbers("indices")?;
+        if indices.len() % 3 != 0 || indices.iter().any(|&i| i < 0.0 || i.fract() != 0.0) {
+            return Err(invalid("bad mesh indices"));
+        }
+        let data = MeshData {
+            positions: vectors("positions")?,
+            normals: vectors("normals")?,
+            uvs: record.numbers("uvs")?.chunks_exact(2).map(|c| (c[0], c[1])).collect(),
+            colors: vectors("colors")?,
+            indices: indices.chunks(3)
+                .map(|c| [c[0] as usize, c[1] as usize, c[2] as usize])
+                .collect()
+        };
+        Ok(Self::new(data, MaterialId::from_index(record.integer("material")?)))
+    }
+
+    pub fn data(&self) -> &MeshData {
+        &self.data
+    }
+
+    pub fn material(&self) -> MaterialId {
+        self.material
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.indices.is_empty()
+    }
+
+    // Moller-Trumbore.
+    fn hit_triangle(&self, index: usize, ray: &Ray, interval: Interval) -> Option<HitRecord> {
+        let [i0, i1, i2] = self.data.indices[index];
+        let (p0, p1, p2) = (self.data.positions[i0], self.data.positions[i1], self.data.positions[i2]);
+        let (e1, e2) = (p1 - p0, p2 - p0);
+        let pvec = ray.direction().cross(&e2);
+        let det = e1.dot(&pvec);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let inv = 1.0 / det;
+        let tvec = ray.origin() - p0;
+        let b1 = tvec.dot(&pvec) * inv;
+        if !(0.0 ..= 1.0).contains(&b1) {
+            return None;
+        }
+        let qvec = tvec.cross(&e1);
+        let b2 = ray.direction().dot(&qvec) * inv;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+        let time = e2.dot(&qvec) * inv;
+        if !interval.surrounds(time) {
+            return None;
+        }
+        let b0 = 1.0 - b1 - b2;
+        let lerp = |a: Vec3, b: Vec3, c: Vec3| a * b0 + b * b1 + c * b2;
+
+        let geometric = e1.cross(&e2).unit();
+        let front = geometric.dot(&ray.direction()) < 0.0;
+        let mut normal = if self.data.normals.is_empty() {
+            geometric
+        } else {
+            let n = &self.data.normals;
+            let shading = lerp(n[i0], n[i1], n[i2]);
+            if shading.near_zero() { geometric } else { shading.unit() }
+        };
+        // winding decides the outside, the normal then faces the ray
+        if normal.dot(&geometric) < 0.0 {
+            normal = -normal;
+        }
+        if !front {
+            normal = -normal;
+        }
+
+        let (uv, tangent) = if self.data.uvs.is_empty() {
+            ((b1, b2), e1)
+        } else {
+            let uv = &self.data.uvs;
+            let (uv0, uv1, uv2) = (uv[i0], uv[i1], uv[i2]);
+            let (du1, dv1) = (uv1.0 - uv0.0, uv1.1 - uv0.1);
+            let (du2, dv2) = (uv2.0 - uv0.0, uv2.1 - uv0.1);
+            let d = du1 * dv2 - dv1 * du2;
+            let tangent = if d.abs() < 1e-12 { e1 } else { (e1 * dv2 - e2 * dv1) / d };
+            let u = b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0;
+            let v = b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1;
+            ((u, v), tangent)
+        };
+        let mut record = HitRecord::new(ray.at(time), normal, front, self.material, time)
+            .with_uv(uv.0, uv.1)
+            .with_tangent(tangent);
+        if !self.data.colors.is_empty() {
+            let c = &self.data.colors;
+            record = record.with_color(lerp(c[i0], c[i1], c[i2]));
+        }
+        Some(record)
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
+        self.bvh.hit(ray, interval, |i, interval| self.hit_triangle(i, ray, interval))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bvh.bounds())
+    }
+
+    fn to_record(&self) -> Option<Record> {
+        let d = &self.data;
+        let flat = |v: &[Vec3]| -> Vec<f64> {
+            v.iter().flat_map(|p| [p.x(), p.y(), p.z()]).collect()
+        };
+        Some(Record::new("mesh")
+            .with("positions", flat(&d.positions))
+            .with("normals", flat(&d.normals))
+            .with("uvs", d.uvs.iter().flat_map(|&(u, v)| [u, v]).collect::<Vec<f64>>())
+            .with("colors", flat(&d.colors))
+            .with("indices", d.indices.iter().flatten().map(|&i| i as f64).collect::<Vec<f64>>())
+            .with("material", self.material.index()))
+    }
+}
+
+// Vertex data is summarized by a hash to keep scene dumps small.
+impl fmt::Debug for TriangleMesh {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let d = &self.data;
+        let mut bytes = Vec::new();
+        let mut push = |v: f64| bytes.extend_from_slice(&v.to_le_bytes());
+        for p in d.positions.iter().chain(&d.normals).chain(&d.colors) {
+            (0 .. 3).for_each(|a| push(p.axis(a)));
+        }
+        for &(u, v) in &d.uvs {
+            push(u);
+            push(v);
+        }
+        for t in &d.indices {
+            t.iter().for_each(|&i| bytes.extend_from_slice(&(i as u64).to_le_bytes()));
+        }
+        f.debug_struct("TriangleMesh")
+            .field("triangles", &d.indices.len())
+            .field("vertices", &d.positions.len())
+            .field("material", &self.material)
+            .field("hash", &utils::fnv1a(&bytes))
+            .finish()
+    }
+}
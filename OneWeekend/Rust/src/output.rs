@@ -0,0 +1,93 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use super::vector::Color;
+use super::color::{self, ColorSpace, Encoding};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Ppm,
+    Pfm
+}
+
+impl ImageFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "png" => Some(Self::Png),
+            "ppm" => Some(Self::Ppm),
+            "pfm" => Some(Self::Pfm),
+            _ => None
+        }
+    }
+
+    // 8-bit formats are display referred and get the sRGB curve, float
+    // formats keep scene-linear values.
+    pub fn encoding(&self) -> Encoding {
+        match self {
+            Self::Png | Self::Ppm => Encoding::Srgb,
+            Self::Pfm => Encoding::Linear
+        }
+    }
+}
+
+pub fn save(
+    path: impl AsRef<Path>, width: u32, height: u32, pixels: &[Color], space: ColorSpace
+) -> io::Result<()> {
+    let path = path.as_ref();
+    let format = ImageFormat::from_path(path).ok_or_else(|| io::Error::new(
+        io::ErrorKind::InvalidInput, format!("unsupported image format: {}", path.display())
+    ))?;
+    if pixels.len() != (width * height) as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "pixel count mismatch"));
+    }
+    let pixels: Vec<Color> = pixels.iter().map(|c| space.to_linear_srgb(*c)).collect();
+    let mut out = BufWriter::new(File::create(path)?);
+    match format {
+        ImageFormat::Png => write_png(&mut out, width, height, &pixels, format.encoding())?,
+        ImageFormat::Ppm => write_ppm(&mut out, width, height, &pixels, format.encoding())?,
+        ImageFormat::Pfm => write_pfm(&mut out, width, height, &pixels, format.encoding())?
+    }
+    out.flush()
+}
+
+fn write_png(
+    out: &mut impl Write, width: u32, height: u32, pixels: &[Color], encoding: Encoding
+) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(out, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+    let mut writer = encoder.write_header()?;
+    let data: Vec<u8> = pixels.iter().flat_map(|c| color::to_rgb8(*c, encoding)).collect();
+    writer.write_image_data(&data)?;
+    Ok(())
+}
+
+fn write_ppm(
+    out: &mut impl Write, width: u32, height: u32, pixels: &[Color], encoding: Encoding
+) -> io::Result<()> {
+    write!(out, "P6\n{} {}\n255\n", width, height)?;
+    for c in pixels {
+        out.write_all(&color::to_rgb8(*c, encoding))?;
+    }
+    Ok(())
+}
+
+fn write_pfm(
+    out: &mut impl Write, width: u32, height: u32, pixels: &[Color], encoding: Encoding
+) -> io::Result<()> {
+    // Negative scale marks little endian; rows are stored bottom to top.
+    write!(out, "PF\n{} {}\n-1.0\n", width, height)?;
+    for row in pixels.chunks(width as usize).rev() {
+        for c in row {
+            let c = encoding.encode_color(*c);
+            for x in [c.x(), c.y(), c.z()] {
+                out.write_all(&(x as f32).to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,352 @@
+use std::sync::{Arc, Mutex, Condvar};
+use std::time::Instant;
+
+use super::vector::Color;
+use super::camera::Camera;
+use super::sence::Sence;
+use super::sampler::Sampler;
+use super::adaptive::{AdaptiveSampling, PixelStats};
+use super::film::Film;
+use super::checkpoint::Checkpoint;
+use super::scheduler::{self, Tile, TileOrder, TileQueue};
+
+// Called with every finished tile, its pass and the tile's current pixels
+// (row by row, in the working color space).
+pub type TileCallback = Arc<dyn Fn(&Tile, u32, &[Color]) + Send + Sync>;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    pub pass: u32,
+    pub samples: u32,
+    pub sps: f64,
+    pub paused: bool,
+    pub cancelled: bool,
+    pub done: bool
+}
+
+// Accumulation state shared between the window and the render workers.
+// Each pass is split into tiles; results are published as soon as a tile is
+// finished so the preview fills in progressively.
+pub(crate) struct State {
+    pub camera: Camera,
+    pub film: Film,
+    pub stats: Vec<PixelStats>,
+    pub active: Vec<bool>,
+    pub adaptive: Option<AdaptiveSampling>,
+    samples: u32,
+    tile_size: u32,
+    order: TileOrder,
+    tiles: Vec<Tile>,
+    crop: Option<Tile>,
+    finished: Vec<bool>,
+    scheduled: Option<(u32, u64)>,
+    pass: u32,
+    generation: u64,
+    version: u64,
+    traced: u64,
+    started: Instant,
+    paused: bool,
+    cancelled: bool,
+    quit: bool
+}
+
+struct Job {
+    index: usize,
+    tile: Tile,
+    pass: u32,
+    generation: u64
+}
+
+impl State {
+    pub fn new(camera: Camera, film: Film, samples: u32) -> Self {
+        let size = (film.width() * film.height()) as usize;
+        let (tile_size, order) = (32, TileOrder::default());
+        let tiles = scheduler::tiles(film.width(), film.height(), tile_size, order);
+        Self {
+            camera, film, samples, tile_size, order, crop: None,
+            stats: vec![PixelStats::default(); size],
+            active: vec![true; size],
+            adaptive: None,
+            finished: vec![false; tiles.len()], tiles, scheduled: None,
+            pass: 0, generation: 0, version: 0, traced: 0,
+            started: Instant::now(),
+            paused: false, cancelled: false, quit: false
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.film.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.film.height()
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    // Throws away all accumulated samples; tiles still in flight are
+    // discarded when they come back with an old generation.
+    pub fn reset(&mut self) {
+        self.film.clear();
+        self.stats.iter_mut().for_each(|s| *s = PixelStats::default());
+        let (width, crop) = (self.width(), self.crop);
+        for (i, active) in self.active.iter_mut().enumerate() {
+            let (x, y) = (i as u32 % width, i as u32 / width);
+            *active = crop.is_none_or(|crop| crop.contains(x, y));
+        }
+        self.pass = 0;
+        self.finished = vec![false; self.tiles.len()];
+        self.generation += 1;
+        self.version += 1;
+        self.traced = 0;
+        self.started = Instant::now();
+        self.cancelled = false;
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.camera.resize(width, height);
+        self.film.resize(width, height);
+        let size = (width * height) as usize;
+        self.stats = vec![PixelStats::default(); size];
+        self.active = vec![true; size];
+        self.crop = None;
+        self.layout();
+    }
+
+    pub fn set_tiles(&mut self, size: u32, order: TileOrder) {
+        self.tile_size = size;
+        self.order = order;
+        self.layout();
+    }
+
+    // Only tiles overlapping the crop window are traced; pixels outside it
+    // stay black.
+    pub fn set_crop(&mut self, crop: Option<Tile>) {
+        self.crop = crop;
+        self.layout();
+    }
+
+    fn layout(&mut self) {
+        let tiles = scheduler::tiles(self.width(), self.height(), self.tile_size, self.order);
+        self.tiles = match self.crop {
+            Some(crop) => tiles.iter().filter_map(|tile| tile.intersect(&crop)).collect(),
+            None => tiles
+        };
+        self.reset();
+    }
+
+    pub fn done(&self) -> bool {
+        self.pass >= self.samples || !self.active.contains(&true)
+    }
+
+    pub fn idle(&self) -> bool {
+        self.paused || self.cancelled || self.done()
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.version += 1;
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn cancel(&mut self) {
+        self.cancelled = true;
+        self.version += 1;
+    }
+
+    pub fn set_quit(&mut self, quit: bool) {
+        self.quit = quit;
+    }
+
+    pub fn progress(&self) -> Progress {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        Progress {
+            pass: self.pass,
+            samples: self.samples,
+            sps: if elapsed > 0.0 { self.traced as f64 / elapsed } else { 0.0 },
+            paused: self.paused,
+            cancelled: self.cancelled,
+            done: self.done()
+        }
+    }
+
+    // Everything besides camera and scene that changes the rendered image.
+    pub fn settings(&self) -> String {
+        format!(
+            "{} {:?} {:?} {} {:?} {:?}",
+            self.samples, self.film.filter(), self.crop, self.tile_size, self.order, self.adaptive
+        )
+    }
+
+    pub fn checkpoint(&self, scene: u64, camera: u64, settings: u64, seed: u64) -> Checkpoint {
+        let (sum, weight) = self.film.raw();
+        Checkpoint {
+            scene, camera, settings, seed,
+            width: self.width(),
+            height: self.height(),
+            pass: self.pass,
+            sum: sum.to_vec(),
+            weight: weight.to_vec(),
+            stats: self.stats.clone(),
+            active: self.active.clone(),
+            finished: self.finished.clone()
+        }
+    }
+
+    // The caller has checked that the checkpoint matches this render.
+    pub fn restore(&mut self, checkpoint: Checkpoint) -> bool {
+        let size = (self.width() * self.height()) as usize;
+        if checkpoint.width != self.width() || checkpoint.height != self.height()
+            || checkpoint.finished.len() != self.tiles.len() || checkpoint.active.len() != size
+        {
+            return false;
+        }
+        self.reset();
+        self.film.restore(checkpoint.sum, checkpoint.weight);
+        self.stats = checkpoint.stats;
+        self.active = checkpoint.active;
+        self.finished = checkpoint.finished;
+        self.pass = checkpoint.pass;
+        true
+    }
+
+    fn tile_indices(&self, tile: &Tile) -> impl Iterator<Item = usize> {
+        let (width, tile) = (self.width(), *tile);
+        (tile.y0 .. tile.y1)
+            .flat_map(move |y| (tile.x0 .. tile.x1).map(move |x| (y * width + x) as usize))
+    }
+
+    fn tile_active(&self, tile: &Tile) -> Vec<bool> {
+        self.tile_indices(tile).map(|i| self.active[i]).collect()
+    }
+
+    fn tile_pixels(&self, tile: &Tile) -> Vec<Color> {
+        self.tile_indices(tile).map(|i| self.film.pixel(i)).collect()
+    }
+
+    // Returns None for stale tiles, otherwise whether the pass completed.
+    fn publish(&mut self, job: &Job, samples: Vec<(u32, Color, (f64, f64))>) -> Option<bool> {
+        if job.generation != self.generation || job.pass != self.pass {
+            return None;
+        }
+        self.traced += samples.len() as u64;
+        for (index, color, (x, y)) in samples {
+            self.stats[index as usize].add(color);
+            self.film.add_sample(x, y, color);
+        }
+        self.finished[job.index] = true;
+        self.version += 1;
+        if self.finished.contains(&false) {
+            return Some(false);
+        }
+        if let Some(adaptive) = &self.adaptive {
+            adaptive.update(&self.stats, self.width(), self.height(), &mut self.active);
+        }
+        self.pass += 1;
+        self.finished.iter_mut().for_each(|f| *f = false);
+        let active = self.active.iter().filter(|a| **a).count();
+        println!("Samples: {}, active pixels: {}", self.pass, active);
+        Some(true)
+    }
+}
+
+pub(crate) struct Shared {
+    pub state: Mutex<State>,
+    pub wake: Condvar,
+    queue: TileQueue<Job>
+}
+
+impl Shared {
+    pub fn new(state: State, workers: usize) -> Self {
+        Self { state: Mutex::new(state), wake: Condvar::new(), queue: TileQueue::new(workers) }
+    }
+
+    // Applies `f` to the state and wakes the workers, e.g. after a reset
+    // or when resuming.
+    pub fn update<R>(&self, f: impl FnOnce(&mut State) -> R) -> R {
+        let mut state = self.state.lock().unwrap();
+        let result = f(&mut state);
+        self.schedule(&mut state);
+        self.wake.notify_all();
+        result
+    }
+
+    // Deals out the tiles of the current pass unless already queued.
+    fn schedule(&self, state: &mut State) {
+        let current = (state.pass, state.generation);
+        if state.idle() || state.scheduled == Some(current) {
+            return;
+        }
+        self.queue.clear();
+        let unfinished = state.tiles.iter().enumerate().filter(|(i, _)| !state.finished[*i]);
+        self.queue.fill(unfinished.map(|(index, tile)| Job {
+            index, tile: *tile, pass: state.pass, generation: state.generation
+        }));
+        state.scheduled = Some(current);
+    }
+}
+
+pub(crate) fn worker(
+    id: usize, shared: Arc<Shared>, world: Arc<Sence>, mut sampler: Box<dyn Sampler>,
+    callback: Option<TileCallback>
+) {
+    loop {
+        let (job, camera, width, active) = {
+            let mut state = shared.state.lock().unwrap();
+            loop {
+                if state.quit {
+                    return;
+                }
+                shared.schedule(&mut state);
+                if state.idle() {
+                    state = shared.wake.wait(state).unwrap();
+                    continue;
+                }
+                // Pop without the state lock so workers only contend on the
+                // deques, then check the job is still current.
+                let scheduled = state.scheduled;
+                drop(state);
+                let job = shared.queue.next(id);
+                state = shared.state.lock().unwrap();
+                match job {
+                    Some(job) if job.generation == state.generation && job.pass == state.pass => {
+                        let active = state.tile_active(&job.tile);
+                        break (job, state.camera.clone(), state.width(), active);
+                    }
+                    // stale, or the queue was refilled while unlocked
+                    Some(_) => continue,
+                    None if state.scheduled != scheduled => continue,
+                    None => state = shared.wake.wait(state).unwrap()
+                }
+            }
+        };
+        let tile = job.tile;
+        let mut samples = Vec::with_capacity(tile.area() as usize);
+        let pixels = (tile.y0 .. tile.y1).flat_map(|y| (tile.x0 .. tile.x1).map(move |x| (x, y)));
+        for ((x, y), active) in pixels.zip(active) {
+            if active {
+                let (color, position) =
+                    camera.sample_pixel(&world, y, x, sampler.as_mut(), job.pass);
+                samples.push((y * width + x, color, position));
+            }
+        }
+        let mut state = shared.state.lock().unwrap();
+        let Some(completed) = state.publish(&job, samples) else {
+            continue;
+        };
+        let pixels = callback.as_ref().map(|_| state.tile_pixels(&tile));
+        shared.schedule(&mut state);
+        drop(state);
+        if completed {
+            shared.wake.notify_all();
+        }
+        if let (Some(callback), Some(pixels)) = (&callback, pixels) {
+            callback(&tile, job.pass, &pixels);
+        }
+    }
+}
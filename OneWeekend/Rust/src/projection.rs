@@ -0,0 +1,241 @@
+use std::fmt::Debug;
+use std::io;
+
+use super::vector::{Vec3, Point};
+use super::camera::Ray;
+use super::utils::{self, PI};
+use super::serialize::{invalid, Record};
+
+// Camera basis and lens setup handed to the projections.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Frame {
+    pub origin: Point,
+    pub right: Vec3,
+    pub up: Vec3,
+    pub front: Vec3,
+    pub focus: f64,
+    pub fov: f64,
+    pub lens_radius: f64,
+    pub aspect: f64,
+    // Lens shift as tangents of the view angle, right and up.
+    pub shift: (f64, f64)
+}
+
+// Maps a film position to a primary ray. `s` runs left to right and `t`
+// top to bottom over [0, 1]; `lens` is a point on the unit disk. Returns
+// None where the projection doesn't cover the film (e.g. outside the
+// fisheye circle).
+pub trait Projection: Debug + Send + Sync {
+    fn generate_ray(&self, frame: &Frame, s: f64, t: f64, lens: Vec3) -> Option<Ray>;
+
+    // Scene file form, None for projections that can't be saved.
+    fn to_record(&self) -> Option<Record> {
+        None
+    }
+}
+
+// Thin-lens perspective using the camera's vertical field of view and
+// focus distance. Honors the frame's lens shift, giving asymmetric frusta.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Perspective;
+
+impl Projection for Perspective {
+    fn generate_ray(&self, frame: &Frame, s: f64, t: f64, lens: Vec3) -> Option<Ray> {
+        let height = frame.focus * libm::tan(utils::degrees_to_radians(frame.fov / 2.0)) * 2.0;
+        let width = height * frame.aspect;
+        let (sx, sy) = frame.shift;
+        let target = frame.origin + frame.focus * (frame.front + sx * frame.right + sy * frame.up)
+            + (s - 0.5) * width * frame.right - (t - 0.5) * height * frame.up;
+        let origin = frame.origin
+            + lens.x() * frame.lens_radius * frame.right
+            + lens.y() * frame.lens_radius * frame.up;
+        Some(Ray::new(origin, target - origin))
+    }
+
+    fn to_record(&self) -> Option<Record> {
+        Some(Record::new("perspective"))
+    }
+}
+
+// Parallel rays; `height` is the visible extent in world units.
+#[derive(Debug, Clone, Copy)]
+pub struct Orthographic {
+    height: f64
+}
+
+impl Orthographic {
+    pub fn new(height: f64) -> Self {
+        Self { height }
+    }
+
+    pub(crate) fn from_record(record: &Record) -> io::Result<Self> {
+        Ok(Self::new(record.number("height")?))
+    }
+}
+
+impl Projection for Orthographic {
+    fn generate_ray(&self, frame: &Frame, s: f64, t: f64, _: Vec3) -> Option<Ray> {
+        let width = self.height * frame.aspect;
+        let origin = frame.origin
+            + (s - 0.5) * width * frame.right - (t - 0.5) * self.height * frame.up;
+        Some(Ray::new(origin, frame.front))
+    }
+
+    fn to_record(&self) -> Option<Record> {
+        Some(Record::new("orthographic").with("height", self.height))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FisheyeMapping {
+    Equidistant,
+    Equisolid
+}
+
+// Circular fisheye inscribed in the film height, `fov` in degrees across
+// the circle.
+#[derive(Debug, Clone, Copy)]
+pub struct Fisheye {
+    mapping: FisheyeMapping,
+    fov: f64
+}
+
+impl Fisheye {
+    pub fn new(mapping: FisheyeMapping, fov: f64) -> Self {
+        Self { mapping, fov }
+    }
+
+    pub(crate) fn from_record(record: &Record) -> io::Result<Self> {
+        let mapping = match record.text("mapping")? {
+            "equidistant" => FisheyeMapping::Equidistant,
+            "equisolid" => FisheyeMapping::Equisolid,
+            other => return Err(invalid(format!("unknown fisheye mapping {}", other)))
+        };
+        Ok(Self::new(mapping, record.number("fov")?))
+    }
+}
+
+impl Projection for Fisheye {
+    fn generate_ray(&self, frame: &Frame, s: f64, t: f64, _: Vec3) -> Option<Ray> {
+        let x = (s - 0.5) * 2.0 * frame.aspect;
+        let y = (0.5 - t) * 2.0;
+        let r = libm::sqrt(x * x + y * y);
+        if r > 1.0 {
+            return None;
+        }
+        let theta_max = utils::degrees_to_radians(self.fov / 2.0);
+        let theta = match self.mapping {
+            FisheyeMapping::Equidistant => r * theta_max,
+            FisheyeMapping::Equisolid => 2.0 * libm::asin(r * libm::sin(theta_max / 2.0))
+        };
+        let phi = libm::atan2(y, x);
+        let side = libm::cos(phi) * frame.right + libm::sin(phi) * frame.up;
+        let direction = libm::cos(theta) * frame.front + libm::sin(theta) * side;
+        Some(Ray::new(frame.origin, direction))
+    }
+
+    fn to_record(&self) -> Option<Record> {
+        let mapping = match self.mapping {
+            FisheyeMapping::Equidistant => "equidistant",
+            FisheyeMapping::Equisolid => "equisolid"
+        };
+        Some(Record::new("fisheye").with("mapping", mapping).with("fov", self.fov))
+    }
+}
+
+// Full 360 x 180 degree latitude-longitude panorama centered on the view
+// direction.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Equirectangular;
+
+impl Projection for Equirectangular {
+    fn generate_ray(&self, frame: &Frame, s: f64, t: f64, _: Vec3) -> Option<Ray> {
+        let longitude = (s - 0.5) * 2.0 * PI;
+        let latitude = (0.5 - t) * PI;
+        let (cos_lat, sin_lat) = (libm::cos(latitude), libm::sin(latitude));
+        let direction = cos_lat * libm::cos(longitude) * frame.front
+            + cos_lat * libm::sin(longitude) * frame.right
+            + sin_lat * frame.up;
+        Some(Ray::new(frame.origin, direction))
+    }
+
+    fn to_record(&self) -> Option<Record> {
+        Some(Record::new("equirectangular"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame() -> Frame {
+        Frame {
+            origin: Point::new(1.0, 2.0, 3.0),
+            right: Vec3::new(1.0, 0.0, 0.0),
+            up: Vec3::new(0.0, 1.0, 0.0),
+            front: Vec3::new(0.0, 0.0, -1.0),
+            focus: 1.0,
+            fov: 90.0,
+            aspect: 2.0,
+            ..Frame::default()
+        }
+    }
+
+    fn direction(projection: &dyn Projection, s: f64, t: f64) -> Vec3 {
+        projection.generate_ray(&frame(), s, t, Vec3::default()).unwrap().direction().unit()
+    }
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-9
+    }
+
+    #[test]
+    fn center_looks_ahead() {
+        let frame = frame();
+        let projections: [&dyn Projection; 5] = [
+            &Perspective,
+            &Orthographic::new(2.0),
+            &Fisheye::new(FisheyeMapping::Equidistant, 180.0),
+            &Fisheye::new(FisheyeMapping::Equisolid, 180.0),
+            &Equirectangular
+        ];
+        for projection in projections {
+            assert!(close(direction(projection, 0.5, 0.5), frame.front), "{:?}", projection);
+        }
+        // orthographic rays start across the film, not at the eye
+        let ray = Orthographic::new(2.0).generate_ray(&frame, 1.0, 0.0, Vec3::default()).unwrap();
+        assert!(close(ray.origin(), frame.origin + Vec3::new(2.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn fisheye_edge_is_half_the_fov() {
+        let frame = frame();
+        for mapping in [FisheyeMapping::Equidistant, FisheyeMapping::Equisolid] {
+            let fisheye = Fisheye::new(mapping, 120.0);
+            // the circle touches the top and, at aspect 2, a quarter in from the sides
+            for (s, t) in [(0.5, 0.0), (0.75, 0.5), (0.25, 0.5), (0.5, 1.0)] {
+                let cos = direction(&fisheye, s, t).dot(&frame.front);
+                assert!((cos - 0.5).abs() < 1e-9, "{:?} at {}, {}", mapping, s, t);
+            }
+            assert!(fisheye.generate_ray(&frame, 0.0, 0.0, Vec3::default()).is_none());
+        }
+        // equidistant halves the angle at half the radius
+        let fisheye = Fisheye::new(FisheyeMapping::Equidistant, 180.0);
+        let cos = direction(&fisheye, 0.5, 0.25).dot(&frame.front);
+        assert!((cos - libm::cos(PI / 4.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn equirectangular_corners() {
+        let frame = frame();
+        // top corners look straight up, bottom corners straight down
+        assert!(close(direction(&Equirectangular, 0.0, 0.0), frame.up));
+        assert!(close(direction(&Equirectangular, 1.0, 0.0), frame.up));
+        assert!(close(direction(&Equirectangular, 0.0, 1.0), -frame.up));
+        assert!(close(direction(&Equirectangular, 1.0, 1.0), -frame.up));
+        // the side edges meet behind the camera, the quarters look sideways
+        assert!(close(direction(&Equirectangular, 0.0, 0.5), -frame.front));
+        assert!(close(direction(&Equirectangular, 0.75, 0.5), frame.right));
+        assert!(close(direction(&Equirectangular, 0.25, 0.5), -frame.right));
+    }
+}
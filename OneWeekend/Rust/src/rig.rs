@@ -0,0 +1,159 @@
+use std::io;
+use std::sync::Arc;
+
+use super::camera::Camera;
+use super::sence::Sence;
+use super::job::{RenderJob, RenderedView};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Convergence {
+    // Parallel view axes, everything appears in front of the screen.
+    Parallel,
+    // Both eyes rotated towards the convergence point; introduces vertical
+    // parallax in the corners.
+    ToeIn,
+    // Parallel axes with opposite lens shifts so the frusta meet at the
+    // convergence distance.
+    #[default]
+    OffAxis
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StereoLayout {
+    #[default]
+    SideBySide,
+    OverUnder
+}
+
+// Pair of eyes built around a center camera.
+#[derive(Debug, Clone)]
+pub struct StereoRig {
+    camera: Camera,
+    interaxial: f64,
+    convergence: Convergence,
+    distance: f64
+}
+
+impl StereoRig {
+    // Converges off-axis at the camera's focus distance.
+    pub fn new(camera: Camera, interaxial: f64) -> Self {
+        let distance = camera.focus_distance();
+        Self { camera, interaxial, convergence: Convergence::default(), distance }
+    }
+
+    pub fn with_convergence(mut self, convergence: Convergence, distance: f64) -> Self {
+        self.convergence = convergence;
+        self.distance = distance;
+        self
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    pub fn interaxial(&self) -> f64 {
+        self.interaxial
+    }
+
+    fn eye(&self, side: f64) -> Camera {
+        let mut eye = self.camera.clone();
+        let offset = self.camera.right() * (side * self.interaxial / 2.0);
+        let origin = self.camera.origin() + offset;
+        match self.convergence {
+            Convergence::Parallel => eye.set_view(origin, self.camera.front()),
+            Convergence::ToeIn => {
+                let target = self.camera.origin() + self.camera.front() * self.distance;
+                eye.set_view(origin, target - origin);
+            }
+            Convergence::OffAxis => {
+                eye.set_view(origin, self.camera.front());
+                let (x, y) = self.camera.shift();
+                eye.set_shift(x - side * self.interaxial / 2.0 / self.distance, y);
+            }
+        }
+        eye
+    }
+
+    // Left and right eye cameras.
+    pub fn eyes(&self) -> (Camera, Camera) {
+        (self.eye(-1.0), self.eye(1.0))
+    }
+
+    // Adds the eyes to a job as `<name>_left` and `<name>_right`.
+    pub fn add_to(&self, job: &mut RenderJob, name: &str) {
+        let (left, right) = self.eyes();
+        job.add_view(format!("{}_left", name), left);
+        job.add_view(format!("{}_right", name), right);
+    }
+
+    // Packs both eyes into one frame, left eye on the left or on top.
+    pub fn compose(
+        name: &str, layout: StereoLayout, left: &RenderedView, right: &RenderedView
+    ) -> RenderedView {
+        assert!(
+            left.width == right.width && left.height == right.height,
+            "stereo eyes differ in size"
+        );
+        let (width, height) = match layout {
+            StereoLayout::SideBySide => (left.width * 2, left.height),
+            StereoLayout::OverUnder => (left.width, left.height * 2)
+        };
+        let pixels = match layout {
+            StereoLayout::SideBySide => left.pixels
+                .chunks(left.width as usize)
+                .zip(right.pixels.chunks(right.width as usize))
+                .flat_map(|(l, r)| l.iter().chain(r))
+                .copied()
+                .collect(),
+            StereoLayout::OverUnder => left.pixels.iter().chain(&right.pixels).copied().collect()
+        };
+        RenderedView { name: name.to_string(), width, height, pixels }
+    }
+
+    pub fn render(
+        &self, world: Arc<Sence>, samples: u32, layout: StereoLayout
+    ) -> io::Result<RenderedView> {
+        let mut job = RenderJob::with_world(world, samples);
+        self.add_to(&mut job, "stereo");
+        let views = job.render()?;
+        Ok(Self::compose("stereo", layout, &views[0], &views[1]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector::{Point, Vec3};
+
+    fn camera() -> Camera {
+        Camera::new(
+            Point::new(0.0, 0.0, 0.0), 5.0, 40.0, 8,
+            Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0, 8, 4
+        )
+    }
+
+    #[test]
+    fn eyes_meet_at_convergence_distance() {
+        let target = Point::new(0.0, 0.0, -5.0);
+        for convergence in [Convergence::ToeIn, Convergence::OffAxis] {
+            let rig = StereoRig::new(camera(), 0.065).with_convergence(convergence, 5.0);
+            let (left, right) = rig.eyes();
+            // the ray through the center of the frame hits the same point
+            for eye in [left, right] {
+                let center = eye.front() + eye.right() * eye.shift().0;
+                assert!((target - eye.origin()).cross(&center).length() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn side_by_side_interleaves_rows() {
+        let view = |name: &str, value: f64| RenderedView {
+            name: name.to_string(), width: 2, height: 2, pixels: vec![Vec3::new(value, 0.0, 0.0); 4]
+        };
+        let sbs = StereoRig::compose("s", StereoLayout::SideBySide, &view("l", 0.0), &view("r", 1.0));
+        let red: Vec<f64> = sbs.pixels.iter().map(|p| p.x()).collect();
+        assert_eq!((sbs.width, sbs.height), (4, 2));
+        assert_eq!(red, [0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 1.0]);
+    }
+}
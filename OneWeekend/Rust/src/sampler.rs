@@ -0,0 +1,340 @@
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use super::utils;
+
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+const PRIMES: [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131
+];
+
+// Source of sample values for one path. `start_pixel` is called before each
+// camera sample, then the camera and materials draw dimensions in order
+// (pixel, lens, wavelength, one per bounce).
+pub trait Sampler: Send {
+    fn start_pixel(&mut self, x: u32, y: u32, index: u32);
+    fn get_1d(&mut self) -> f64;
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SamplerKind {
+    #[default]
+    Independent,
+    Stratified,
+    Halton,
+    Sobol
+}
+
+impl SamplerKind {
+    pub fn build(&self, samples: u32, seed: u64) -> Box<dyn Sampler> {
+        match self {
+            Self::Independent => Box::new(IndependentSampler::new(seed)),
+            Self::Stratified => Box::new(StratifiedSampler::new(samples, seed)),
+            Self::Halton => Box::new(HaltonSampler::new(seed)),
+            Self::Sobol => Box::new(SobolSampler::new(seed))
+        }
+    }
+}
+
+fn pixel_rng(seed: u64, x: u32, y: u32, index: u32) -> StdRng {
+    StdRng::seed_from_u64(utils::hash(&[seed, x as u64, y as u64, index as u64]))
+}
+
+pub struct IndependentSampler {
+    seed: u64,
+    rng: StdRng
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, rng: StdRng::seed_from_u64(seed) }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel(&mut self, x: u32, y: u32, index: u32) {
+        self.rng = pixel_rng(self.seed, x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.rng.gen_range(0.0..1.0)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+// Jittered grid: the pixel's sample index picks a stratum through a
+// per-pixel, per-dimension permutation so dimensions stay uncorrelated.
+// Samples beyond the grid size fall back to independent values.
+pub struct StratifiedSampler {
+    seed: u64,
+    samples: u32,
+    grid: (u32, u32),
+    pixel: u64,
+    index: u32,
+    dimension: u64,
+    rng: StdRng
+}
+
+impl StratifiedSampler {
+    pub fn new(samples: u32, seed: u64) -> Self {
+        let samples = samples.max(1);
+        let nx = (libm::sqrt(samples as f64) as u32).max(1);
+        let ny = samples.div_ceil(nx);
+        Self {
+            seed, samples, grid: (nx, ny), pixel: 0, index: 0, dimension: 0,
+            rng: StdRng::seed_from_u64(seed)
+        }
+    }
+
+    fn stratum(&mut self, count: u32) -> Option<u32> {
+        let hash = utils::hash(&[self.seed, self.pixel, self.dimension]);
+        self.dimension += 1;
+        if self.index < count {
+            Some(permutation_element(self.index, count, hash as u32))
+        } else {
+            None
+        }
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel(&mut self, x: u32, y: u32, index: u32) {
+        self.pixel = ((x as u64) << 32) | y as u64;
+        self.index = index;
+        self.dimension = 0;
+        self.rng = pixel_rng(self.seed, x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let jitter = self.rng.gen_range(0.0..1.0);
+        match self.stratum(self.samples) {
+            Some(s) => (s as f64 + jitter) / self.samples as f64,
+            None => jitter
+        }
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (nx, ny) = self.grid;
+        let (jx, jy) = (self.rng.gen_range(0.0..1.0), self.rng.gen_range(0.0..1.0));
+        match self.stratum(nx * ny) {
+            Some(s) => (((s % nx) as f64 + jx) / nx as f64, ((s / nx) as f64 + jy) / ny as f64),
+            None => (jx, jy)
+        }
+    }
+}
+
+// Halton sequence with Owen-scrambled digits, decorrelated per pixel.
+pub struct HaltonSampler {
+    seed: u64,
+    pixel: u64,
+    index: u32,
+    dimension: usize
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, pixel: 0, index: 0, dimension: 0 }
+    }
+
+    fn sample(&mut self) -> f64 {
+        let base = PRIMES[self.dimension % PRIMES.len()];
+        let hash = utils::hash(&[self.seed, self.pixel, self.dimension as u64]);
+        self.dimension += 1;
+        owen_scrambled_radical_inverse(base, self.index as u64, hash)
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel(&mut self, x: u32, y: u32, index: u32) {
+        self.pixel = ((x as u64) << 32) | y as u64;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.sample()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.sample(), self.sample())
+    }
+}
+
+// Owen-scrambled (0,2)-sequence padded across dimensions: every 2D draw
+// uses the first two Sobol dimensions with its own index shuffle and
+// scramble (Burley 2020).
+pub struct SobolSampler {
+    seed: u64,
+    pixel: u64,
+    index: u32,
+    dimension: u64
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, pixel: 0, index: 0, dimension: 0 }
+    }
+
+    fn seeds(&mut self) -> (u32, u32, u32) {
+        let hash = utils::hash(&[self.seed, self.pixel, self.dimension]);
+        self.dimension += 1;
+        (hash as u32, (hash >> 32) as u32, utils::mix_bits(hash) as u32)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel(&mut self, x: u32, y: u32, index: u32) {
+        self.pixel = ((x as u64) << 32) | y as u64;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (shuffle, scramble, _) = self.seeds();
+        let index = nested_uniform_scramble(self.index, shuffle);
+        to_unit(nested_uniform_scramble(index.reverse_bits(), scramble))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (shuffle, sx, sy) = self.seeds();
+        let index = nested_uniform_scramble(self.index, shuffle);
+        (
+            to_unit(nested_uniform_scramble(index.reverse_bits(), sx)),
+            to_unit(nested_uniform_scramble(sobol_second(index), sy))
+        )
+    }
+}
+
+fn to_unit(x: u32) -> f64 {
+    (x as f64 / 4294967296.0).min(ONE_MINUS_EPSILON)
+}
+
+// Second dimension of the Sobol sequence.
+fn sobol_second(mut index: u32) -> u32 {
+    let mut v = 1u32 << 31;
+    let mut result = 0;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= v;
+        }
+        index >>= 1;
+        v ^= v >> 1;
+    }
+    result
+}
+
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+fn owen_scrambled_radical_inverse(base: u64, mut a: u64, hash: u64) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_m = 1.0;
+    let mut reversed = 0u64;
+    while 1.0 - inv_base_m < 1.0 {
+        let next = a / base;
+        let digit = (a - next * base) as u32;
+        let digit_hash = utils::mix_bits(hash ^ reversed) as u32;
+        let digit = permutation_element(digit, base as u32, digit_hash);
+        reversed = reversed * base + digit as u64;
+        inv_base_m *= inv_base;
+        a = next;
+    }
+    (inv_base_m * reversed as f64).min(ONE_MINUS_EPSILON)
+}
+
+// Kensler's hash-based permutation of `i` within [0, l).
+fn permutation_element(mut i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l.wrapping_sub(1);
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+    i.wrapping_add(p) % l
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Mean squared error of estimating the integral of a smooth function
+    // over [0,1]^2 from the second 2D dimension, across many pixels.
+    fn mse(kind: SamplerKind, samples: u32) -> f64 {
+        let f = |u: f64, v: f64| libm::sin(utils::PI * u) * v * v;
+        let exact = 2.0 / utils::PI / 3.0;
+        let mut sampler = kind.build(samples, 7);
+        let pixels = 256;
+        let mut error = 0.0;
+        for p in 0 .. pixels {
+            let mut sum = 0.0;
+            for index in 0 .. samples {
+                sampler.start_pixel(p, 0, index);
+                sampler.get_2d();
+                sampler.get_1d();
+                let (u, v) = sampler.get_2d();
+                assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v));
+                sum += f(u, v);
+            }
+            let estimate = sum / samples as f64;
+            error += (estimate - exact) * (estimate - exact);
+        }
+        error / pixels as f64
+    }
+
+    #[test]
+    fn better_than_independent() {
+        let independent = mse(SamplerKind::Independent, 64);
+        for kind in [SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol] {
+            let error = mse(kind, 64);
+            assert!(error * 4.0 < independent, "{:?}: {} vs {}", kind, error, independent);
+        }
+    }
+
+    #[test]
+    fn permutation_is_bijective() {
+        for l in [1, 5, 16, 49] {
+            let mut seen: Vec<u32> = (0 .. l).map(|i| permutation_element(i, l, 0x1234)).collect();
+            seen.sort();
+            assert_eq!(seen, (0 .. l).collect::<Vec<_>>());
+        }
+    }
+}
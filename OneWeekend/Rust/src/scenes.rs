@@ -0,0 +1,368 @@
+// Built-in test scenes, mostly from the "Ray Tracing in One Weekend" books.
+// Each builds the scene and a camera for the given image size.
+
+use std::io;
+use std::sync::Arc;
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use super::vector::{Vec3, Point, Color};
+use super::camera::Camera;
+use super::sence::{
+    Background, ConstantMedium, Hittable, Node, NodeId, Quad, Sence, Sphere
+};
+use super::material::{
+    Dielectric, DiffuseLight, Isotropic, Lambertian, MaterialId, Metal, Pbr
+};
+use super::texture::{CheckerTexture, Image, ImageTexture, NoiseTexture, SolidColor};
+use super::transform::Transform;
+
+pub type Builder = fn(u32, u32) -> io::Result<(Sence, Camera)>;
+
+pub const SCENES: [(&str, Builder); 8] = [
+    ("random-spheres", random_spheres),
+    ("checkered-spheres", checkered_spheres),
+    ("earth", earth),
+    ("perlin-spheres", perlin_spheres),
+    ("simple-light", simple_light),
+    ("cornell-box", cornell_box),
+    ("final-scene", final_scene),
+    ("material-grid", material_grid)
+];
+
+// Seed of the random placements, so every build gives the same scene.
+const SEED: u64 = 2023;
+const DEPTH: u32 = 50;
+// Looked up relative to the working directory; the scenes using it fail
+// when it's missing.
+const EARTH_MAP: &str = "Image/earthmap.png";
+
+pub fn names() -> impl Iterator<Item = &'static str> {
+    SCENES.iter().map(|(name, _)| *name)
+}
+
+// None for unknown names, an error when a scene's assets can't be loaded.
+pub fn build(name: &str, width: u32, height: u32) -> Option<io::Result<(Sence, Camera)>> {
+    let (_, builder) = SCENES.iter().find(|(n, _)| *n == name)?;
+    Some(builder(width, height))
+}
+
+// Pinhole camera at `from` looking at `at`, `fov` vertical in degrees.
+fn look_at(from: Point, at: Point, fov: f64, width: u32, height: u32) -> Camera {
+    let front = at - from;
+    let up = Vec3::new(0.0, 1.0, 0.0);
+    Camera::new(from, front.length(), fov, DEPTH, up, front, 0.0, width, height)
+}
+
+// Adds a box as a node of six quads.
+fn cuboid(
+    sence: &mut Sence, parent: Option<NodeId>, name: &str, a: Point, b: Point,
+    material: MaterialId
+) -> NodeId {
+    let node = sence.insert(parent, Node::new(name));
+    for face in Quad::cuboid(a, b, material) {
+        sence.insert(Some(node), Node::new("").with_object(face));
+    }
+    node
+}
+
+fn earth_texture() -> io::Result<ImageTexture> {
+    let image = Image::load(EARTH_MAP, true).map_err(|error| {
+        io::Error::new(error.kind(), format!("can't load {}: {}", EARTH_MAP, error))
+    })?;
+    Ok(ImageTexture::new(Arc::new(image)).with_flip_v(true))
+}
+
+// Vector with each component drawn from `range`.
+fn random_vec(rng: &mut StdRng, range: std::ops::Range<f64>) -> Vec3 {
+    Vec3::new(rng.gen_range(range.clone()), rng.gen_range(range.clone()), rng.gen_range(range))
+}
+
+// Cover of the first book.
+pub fn random_spheres(width: u32, height: u32) -> io::Result<(Sence, Camera)> {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let mut sence = Sence::new();
+    let ground = sence.add_material("ground", Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    sence.push(Sphere::new(Point::new(0.0, -1000.0, 0.0), 1000.0, ground));
+    let glass = sence.add_material("glass", Dielectric::new(1.5));
+
+    for a in -11 .. 11 {
+        for b in -11 .. 11 {
+            let choose_mat = rng.gen_range(0.0 .. 1.0);
+            let center = Point::new(
+                a as f64 + 0.9 * rng.gen_range(0.0 .. 1.0),
+                0.2,
+                b as f64 + 0.9 * rng.gen_range(0.0 .. 1.0)
+            );
+
+            if (center - Point::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                if choose_mat < 0.8 {
+                    let albedo =
+                        random_vec(&mut rng, 0.0 .. 1.0) * random_vec(&mut rng, 0.0 .. 1.0);
+                    let mat = sence.add_material(
+                        format!("diffuse {} {}", a, b), Lambertian::new(albedo)
+                    );
+                    sence.push(Sphere::new(center, 0.2, mat));
+                } else if choose_mat < 0.95 {
+                    let albedo = random_vec(&mut rng, 0.5 .. 1.0);
+                    let fuzz = rng.gen_range(0.0 .. 0.5);
+                    let mat = sence.add_material(
+                        format!("metal {} {}", a, b), Metal::new(albedo, fuzz)
+                    );
+                    sence.push(Sphere::new(center, 0.2, mat));
+                } else {
+                    sence.push(Sphere::new(center, 0.2, glass));
+                }
+            }
+        }
+    }
+
+    sence.push(Sphere::new(Point::new(0.0, 1.0, 0.0), 1.0, glass));
+
+    let mat2 = sence.add_material("brown", Lambertian::new(Color::new(0.4, 0.2, 0.1)));
+    sence.push(Sphere::new(Point::new(-4.0, 1.0, 0.0), 1.0, mat2));
+
+    let mat3 = sence.add_material("mirror", Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
+    sence.push(Sphere::new(Point::new(4.0, 1.0, 0.0), 1.0, mat3));
+
+    // focused at 10 units, a little in front of the look-at point
+    let front = Vec3::new(-13.0, -2.0, -3.0);
+    let up = Vec3::new(0.0, 1.0, 0.0);
+    let camera = Camera::new(
+        Point::new(13.0, 2.0, 3.0), 10.0, 20.0, DEPTH, up, front, 0.6, width, height
+    );
+    Ok((sence, camera))
+}
+
+pub fn checkered_spheres(width: u32, height: u32) -> io::Result<(Sence, Camera)> {
+    let mut sence = Sence::new();
+    let checker = CheckerTexture::new(
+        0.32, Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9)
+    );
+    let checker = sence.add_material("checker", Lambertian::textured(checker));
+    sence.push(Sphere::new(Point::new(0.0, -10.0, 0.0), 10.0, checker));
+    sence.push(Sphere::new(Point::new(0.0, 10.0, 0.0), 10.0, checker));
+    let camera = look_at(
+        Point::new(13.0, 2.0, 3.0), Point::new(0.0, 0.0, 0.0), 20.0, width, height
+    );
+    Ok((sence, camera))
+}
+
+pub fn earth(width: u32, height: u32) -> io::Result<(Sence, Camera)> {
+    let mut sence = Sence::new();
+    let surface = sence.add_material("earth", Lambertian::textured(earth_texture()?));
+    sence.insert(None, Node::new("globe").with_object(
+        Sphere::new(Point::new(0.0, 0.0, 0.0), 2.0, surface)
+    ));
+    let camera = look_at(
+        Point::new(0.0, 0.0, 12.0), Point::new(0.0, 0.0, 0.0), 20.0, width, height
+    );
+    Ok((sence, camera))
+}
+
+pub fn perlin_spheres(width: u32, height: u32) -> io::Result<(Sence, Camera)> {
+    let mut sence = Sence::new();
+    let marble = Lambertian::textured(NoiseTexture::new(SEED, 4.0));
+    let marble = sence.add_material("marble", marble);
+    sence.push(Sphere::new(Point::new(0.0, -1000.0, 0.0), 1000.0, marble));
+    sence.push(Sphere::new(Point::new(0.0, 2.0, 0.0), 2.0, marble));
+    let camera = look_at(
+        Point::new(13.0, 2.0, 3.0), Point::new(0.0, 0.0, 0.0), 20.0, width, height
+    );
+    Ok((sence, camera))
+}
+
+pub fn simple_light(width: u32, height: u32) -> io::Result<(Sence, Camera)> {
+    let (mut sence, _) = perlin_spheres(width, height)?;
+    sence.set_background(Background::Solid(Color::default()));
+    let light = sence.add_material("light", DiffuseLight::new(Color::new(4.0, 4.0, 4.0)));
+    sence.insert(None, Node::new("panel light").with_object(Quad::new(
+        Point::new(3.0, 1.0, -2.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0), light
+    )));
+    sence.insert(None, Node::new("sphere light").with_object(
+        Sphere::new(Point::new(0.0, 7.0, 0.0), 2.0, light)
+    ));
+    let camera = look_at(
+        Point::new(26.0, 3.0, 6.0), Point::new(0.0, 2.0, 0.0), 20.0, width, height
+    );
+    Ok((sence, camera))
+}
+
+pub fn cornell_box(width: u32, height: u32) -> io::Result<(Sence, Camera)> {
+    let mut sence = Sence::new();
+    sence.set_background(Background::Solid(Color::default()));
+    let red = sence.add_material("red", Lambertian::new(Color::new(0.65, 0.05, 0.05)));
+    let white = sence.add_material("white", Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let green = sence.add_material("green", Lambertian::new(Color::new(0.12, 0.45, 0.15)));
+    let light = sence.add_material("light", DiffuseLight::new(Color::new(15.0, 15.0, 15.0)));
+
+    let x = Vec3::new(555.0, 0.0, 0.0);
+    let y = Vec3::new(0.0, 555.0, 0.0);
+    let z = Vec3::new(0.0, 0.0, 555.0);
+    let origin = Point::new(0.0, 0.0, 0.0);
+    let walls = [
+        ("left", Quad::new(x, y, z, green)),
+        ("right", Quad::new(origin, y, z, red)),
+        ("floor", Quad::new(origin, x, z, white)),
+        ("ceiling", Quad::new(x + y + z, -x, -z, white)),
+        ("back", Quad::new(z, x, y, white))
+    ];
+    for (name, wall) in walls {
+        sence.insert(None, Node::new(name).with_object(wall));
+    }
+    sence.insert(None, Node::new("light").with_object(Quad::new(
+        Point::new(343.0, 554.0, 332.0), Vec3::new(-130.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -105.0),
+        light
+    )));
+
+    let up = Vec3::new(0.0, 1.0, 0.0);
+    let tall = cuboid(
+        &mut sence, None, "tall box", origin, Point::new(165.0, 330.0, 165.0), white
+    );
+    sence.set_transform(
+        tall, Transform::translate(Vec3::new(265.0, 0.0, 295.0)) * Transform::rotate(up, 15.0)
+    );
+    let short = cuboid(
+        &mut sence, None, "short box", origin, Point::new(165.0, 165.0, 165.0), white
+    );
+    sence.set_transform(
+        short, Transform::translate(Vec3::new(130.0, 0.0, 65.0)) * Transform::rotate(up, -18.0)
+    );
+
+    let camera = look_at(
+        Point::new(278.0, 278.0, -800.0), Point::new(278.0, 278.0, 0.0), 40.0, width, height
+    );
+    Ok((sence, camera))
+}
+
+// Final scene of the second book. The moving sphere stands still, rays
+// carry no time.
+pub fn final_scene(width: u32, height: u32) -> io::Result<(Sence, Camera)> {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let mut sence = Sence::new();
+    sence.set_background(Background::Solid(Color::default()));
+
+    let ground = sence.add_material("ground", Lambertian::new(Color::new(0.48, 0.83, 0.53)));
+    let floor = sence.insert(None, Node::new("floor"));
+    for i in 0 .. 20 {
+        for j in 0 .. 20 {
+            let w = 100.0;
+            let (x0, z0) = (-1000.0 + i as f64 * w, -1000.0 + j as f64 * w);
+            let y1 = rng.gen_range(1.0 .. 101.0);
+            let (a, b) = (Point::new(x0, 0.0, z0), Point::new(x0 + w, y1, z0 + w));
+            cuboid(&mut sence, Some(floor), "", a, b, ground);
+        }
+    }
+
+    let light = sence.add_material("light", DiffuseLight::new(Color::new(7.0, 7.0, 7.0)));
+    sence.insert(None, Node::new("light").with_object(Quad::new(
+        Point::new(123.0, 554.0, 147.0), Vec3::new(300.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 265.0),
+        light
+    )));
+
+    let orange = sence.add_material("orange", Lambertian::new(Color::new(0.7, 0.3, 0.1)));
+    sence.push(Sphere::new(Point::new(400.0, 400.0, 200.0), 50.0, orange));
+    let glass = sence.add_material("glass", Dielectric::new(1.5));
+    sence.push(Sphere::new(Point::new(260.0, 150.0, 45.0), 50.0, glass));
+    let metal = sence.add_material("metal", Metal::new(Color::new(0.8, 0.8, 0.9), 1.0));
+    sence.push(Sphere::new(Point::new(0.0, 150.0, 145.0), 50.0, metal));
+
+    // glass ball filled with blue smoke, and a thin mist over everything
+    let boundary: Arc<dyn Hittable> =
+        Arc::new(Sphere::new(Point::new(360.0, 150.0, 145.0), 70.0, glass));
+    sence.insert(None, Node::new("smoke shell").with_shared(boundary.clone()));
+    let smoke = sence.add_material("smoke", Isotropic::new(Color::new(0.2, 0.4, 0.9)));
+    let smoke = ConstantMedium::shared(boundary, 0.2, smoke);
+    sence.insert(None, Node::new("smoke").with_object(smoke));
+    let mist = sence.add_material("mist", Isotropic::new(Color::new(1.0, 1.0, 1.0)));
+    sence.insert(None, Node::new("mist").with_object(ConstantMedium::new(
+        Sphere::new(Point::new(0.0, 0.0, 0.0), 5000.0, glass), 0.0001, mist
+    )));
+
+    let surface = sence.add_material("earth", Lambertian::textured(earth_texture()?));
+    sence.push(Sphere::new(Point::new(400.0, 200.0, 400.0), 100.0, surface));
+    let marble = Lambertian::textured(NoiseTexture::new(SEED, 0.2));
+    let marble = sence.add_material("marble", marble);
+    sence.push(Sphere::new(Point::new(220.0, 280.0, 300.0), 80.0, marble));
+
+    let white = sence.add_material("white", Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let placed = Transform::translate(Vec3::new(-100.0, 270.0, 395.0))
+        * Transform::rotate(Vec3::new(0.0, 1.0, 0.0), 15.0);
+    let cluster = sence.insert(None, Node::new("cluster").with_transform(placed));
+    for _ in 0 .. 1000 {
+        let center = random_vec(&mut rng, 0.0 .. 165.0);
+        sence.insert(Some(cluster), Node::new("").with_object(Sphere::new(center, 10.0, white)));
+    }
+
+    let camera = look_at(
+        Point::new(478.0, 278.0, -600.0), Point::new(278.0, 278.0, 0.0), 40.0, width, height
+    );
+    Ok((sence, camera))
+}
+
+// Metallic-roughness spheres, metalness going up and roughness going
+// right.
+pub fn material_grid(width: u32, height: u32) -> io::Result<(Sence, Camera)> {
+    let mut sence = Sence::new();
+    let ground = sence.add_material("ground", Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    sence.push(Sphere::new(Point::new(0.0, -1000.0, 0.0), 1000.0, ground));
+    let n = 7;
+    let base = SolidColor(Color::new(0.9, 0.6, 0.2));
+    for row in 0 .. n {
+        for column in 0 .. n {
+            let metallic = row as f64 / (n - 1) as f64;
+            let roughness = column as f64 / (n - 1) as f64;
+            let material = sence.add_material(
+                format!("metallic {:.2} roughness {:.2}", metallic, roughness),
+                Pbr::new(base, metallic, roughness)
+            );
+            let center = Point::new(column as f64 - 3.0, row as f64 + 0.5, 0.0);
+            sence.push(Sphere::new(center, 0.4, material));
+        }
+    }
+    let camera = look_at(
+        Point::new(0.0, 3.5, 16.0), Point::new(0.0, 3.5, 0.0), 35.0, width, height
+    );
+    Ok((sence, camera))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Ray;
+    use crate::utils::Interval;
+
+    #[test]
+    fn every_scene_builds_and_saves() {
+        let has_map = std::path::Path::new(EARTH_MAP).exists();
+        for name in names() {
+            let (sence, camera) = match build(name, 32, 18).unwrap() {
+                Ok(built) => built,
+                // only the scenes with the earth need the map
+                Err(error) => {
+                    assert!(!has_map && ["earth", "final-scene"].contains(&name), "{}", name);
+                    assert_eq!(error.kind(), io::ErrorKind::NotFound);
+                    assert!(error.to_string().contains(EARTH_MAP), "{}", error);
+                    continue;
+                }
+            };
+            assert!(sence.nodes().next().is_some(), "{} is empty", name);
+            crate::serialize::to_text(&sence, Some(&camera)).unwrap();
+            // seeded, so checkpoints of built-in scenes resume
+            let (again, _) = build(name, 32, 18).unwrap().unwrap();
+            assert_eq!(again.fingerprint(), sence.fingerprint(), "{}", name);
+        }
+        assert!(build("missing", 32, 18).is_none());
+    }
+
+    #[test]
+    fn cornell_box_back_wall() {
+        let (sence, _) = cornell_box(32, 32).unwrap();
+        // through the open front and over the boxes
+        let ray = Ray::new(Point::new(278.0, 400.0, -800.0), Vec3::new(0.0, 0.0, 1.0));
+        let hit = sence.hit(&ray, Interval::new(0.001, f64::INFINITY)).unwrap();
+        assert!((hit.point().z() - 555.0).abs() < 1e-6);
+        assert_eq!(sence.materials().name(hit.material()), Some("white"));
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+// Pixel rectangle [x0, x1) x [y0, y1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32
+}
+
+impl Tile {
+    pub fn width(&self) -> u32 {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> u32 {
+        self.y1 - self.y0
+    }
+
+    pub fn area(&self) -> u32 {
+        self.width() * self.height()
+    }
+
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        (self.x0 .. self.x1).contains(&x) && (self.y0 .. self.y1).contains(&y)
+    }
+
+    pub fn intersect(&self, other: &Tile) -> Option<Tile> {
+        let tile = Tile {
+            x0: self.x0.max(other.x0),
+            y0: self.y0.max(other.y0),
+            x1: self.x1.min(other.x1),
+            y1: self.y1.min(other.y1)
+        };
+        (tile.x0 < tile.x1 && tile.y0 < tile.y1).then_some(tile)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TileOrder {
+    Scanline,
+    #[default]
+    Spiral,
+    Hilbert
+}
+
+// Splits the image into `size` x `size` buckets in the given order.
+pub fn tiles(width: u32, height: u32, size: u32, order: TileOrder) -> Vec<Tile> {
+    let size = size.max(1);
+    let (nx, ny) = (width.div_ceil(size), height.div_ceil(size));
+    let tile = |tx: u32, ty: u32| Tile {
+        x0: tx * size,
+        y0: ty * size,
+        x1: ((tx + 1) * size).min(width),
+        y1: ((ty + 1) * size).min(height)
+    };
+    let coords: Vec<(u32, u32)> = match order {
+        TileOrder::Scanline => (0 .. ny).flat_map(|y| (0 .. nx).map(move |x| (x, y))).collect(),
+        TileOrder::Spiral => spiral(nx, ny),
+        TileOrder::Hilbert => hilbert(nx, ny)
+    };
+    coords.into_iter().map(|(x, y)| tile(x, y)).collect()
+}
+
+// Square spiral starting at the center tile.
+fn spiral(nx: u32, ny: u32) -> Vec<(u32, u32)> {
+    let total = (nx * ny) as usize;
+    let mut coords = Vec::with_capacity(total);
+    let (mut x, mut y) = ((nx as i64 - 1) / 2, (ny as i64 - 1) / 2);
+    let (mut dx, mut dy) = (1i64, 0i64);
+    let mut leg = 1;
+    while coords.len() < total {
+        for _ in 0 .. 2 {
+            for _ in 0 .. leg {
+                if (0 .. nx as i64).contains(&x) && (0 .. ny as i64).contains(&y) {
+                    coords.push((x as u32, y as u32));
+                }
+                x += dx;
+                y += dy;
+            }
+            (dx, dy) = (-dy, dx);
+        }
+        leg += 1;
+    }
+    coords
+}
+
+fn hilbert(nx: u32, ny: u32) -> Vec<(u32, u32)> {
+    let n = nx.max(ny).next_power_of_two();
+    (0 .. n * n)
+        .map(|d| hilbert_d2xy(n, d))
+        .filter(|(x, y)| *x < nx && *y < ny)
+        .collect()
+}
+
+fn hilbert_d2xy(n: u32, d: u32) -> (u32, u32) {
+    let (mut x, mut y) = (0, 0);
+    let mut t = d;
+    let mut s = 1;
+    while s < n {
+        let rx = 1 & (t / 2);
+        let ry = 1 & (t ^ rx);
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+    (x, y)
+}
+
+// Per-worker tile deques. Tiles are dealt round-robin so the overall order
+// is kept; a worker whose deque runs dry steals from the back of the
+// fullest other deque.
+pub struct TileQueue<T> {
+    queues: Vec<Mutex<VecDeque<T>>>
+}
+
+impl<T> TileQueue<T> {
+    pub fn new(workers: usize) -> Self {
+        Self { queues: (0 .. workers.max(1)).map(|_| Mutex::new(VecDeque::new())).collect() }
+    }
+
+    pub fn fill(&self, items: impl IntoIterator<Item = T>) {
+        let n = self.queues.len();
+        let mut queues: Vec<_> = self.queues.iter().map(|q| q.lock().unwrap()).collect();
+        for (i, item) in items.into_iter().enumerate() {
+            queues[i % n].push_back(item);
+        }
+    }
+
+    pub fn clear(&self) {
+        for queue in &self.queues {
+            queue.lock().unwrap().clear();
+        }
+    }
+
+    pub fn next(&self, worker: usize) -> Option<T> {
+        let own = worker % self.queues.len();
+        if let Some(item) = self.queues[own].lock().unwrap().pop_front() {
+            return Some(item);
+        }
+        let victim = (0 .. self.queues.len())
+            .filter(|i| *i != own)
+            .max_by_key(|i| self.queues[*i].lock().unwrap().len())?;
+        self.queues[victim].lock().unwrap().pop_back()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orders_cover_image_once() {
+        for order in [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+            let tiles = tiles(100, 37, 16, order);
+            assert_eq!(tiles.len(), 7 * 3);
+            assert_eq!(tiles.iter().map(|t| t.area()).sum::<u32>(), 100 * 37);
+            for (i, a) in tiles.iter().enumerate() {
+                assert!(tiles[i + 1 ..].iter().all(|b| a != b), "{:?}", order);
+            }
+        }
+    }
+
+    #[test]
+    fn queue_hands_out_every_tile_once() {
+        let tiles = tiles(640, 480, 16, TileOrder::Hilbert);
+        let queue = TileQueue::new(4);
+        // the last worker starts with nothing and has to steal
+        queue.fill(tiles.iter().copied());
+        let moved: Vec<_> = queue.queues[3].lock().unwrap().drain(..).collect();
+        queue.queues[0].lock().unwrap().extend(moved);
+        let mut popped: Vec<Tile> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0 .. 4).map(|worker| {
+                let queue = &queue;
+                scope.spawn(move || std::iter::from_fn(|| queue.next(worker)).collect::<Vec<_>>())
+            }).collect();
+            workers.into_iter().flat_map(|w| w.join().unwrap()).collect()
+        });
+        assert_eq!(popped.len(), tiles.len());
+        popped.sort_by_key(|t| (t.y0, t.x0));
+        let mut expected = tiles.clone();
+        expected.sort_by_key(|t| (t.y0, t.x0));
+        assert_eq!(popped, expected);
+    }
+}
@@ -0,0 +1,559 @@
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+
+use super::vector::{Vec3, Point};
+use super::camera::Ray;
+use super::sence::{Hittable, HitRecord};
+use super::material::MaterialId;
+use super::bvh::Aabb;
+use super::utils::{self, Interval};
+
+// Signed distance to a surface, negative inside. Sphere tracing needs it
+// to never overestimate the distance to the nearest surface.
+pub trait Field: Send + Sync + Debug {
+    fn distance(&self, p: Point) -> f64;
+
+    // Content hash for checkpoints, see `Hittable::fingerprint`. None when
+    // the field can't tell, as closures can't.
+    fn fingerprint(&self) -> Option<u64> {
+        None
+    }
+
+    // Box the surface lies in, None when it's unknown or unbounded.
+    fn bounds(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+fn fingerprint(kind: &str, parts: impl IntoIterator<Item = u64>) -> Option<u64> {
+    let mut all = vec![utils::fnv1a(kind.as_bytes())];
+    all.extend(parts);
+    Some(utils::hash(&all))
+}
+
+fn bits(v: Vec3) -> [u64; 3] {
+    [v.x().to_bits(), v.y().to_bits(), v.z().to_bits()]
+}
+
+fn abs(v: Vec3) -> Vec3 {
+    Vec3::new(v.x().abs(), v.y().abs(), v.z().abs())
+}
+
+fn positive(v: Vec3) -> Vec3 {
+    Vec3::new(v.x().max(0.0), v.y().max(0.0), v.z().max(0.0))
+}
+
+fn mix(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+// User distance function. Only the name shows in scene dumps, so it should
+// tell different functions apart. Checkpoints can't look into the closure:
+// without a fingerprint, e.g. a hash of its parameters, a changed function
+// resumes the old render.
+pub struct Function<F> {
+    name: String,
+    distance: F,
+    fingerprint: Option<u64>
+}
+
+impl<F: Fn(Point) -> f64 + Send + Sync> Function<F> {
+    pub fn new(name: impl Into<String>, distance: F) -> Self {
+        Self { name: name.into(), distance, fingerprint: None }
+    }
+
+    pub fn with_fingerprint(mut self, fingerprint: u64) -> Self {
+        self.fingerprint = Some(fingerprint);
+        self
+    }
+}
+
+impl<F> Debug for Function<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Function").field(&self.name).finish()
+    }
+}
+
+impl<F: Fn(Point) -> f64 + Send + Sync> Field for Function<F> {
+    fn distance(&self, p: Point) -> f64 {
+        (self.distance)(p)
+    }
+
+    fn fingerprint(&self) -> Option<u64> {
+        fingerprint(&self.name, [self.fingerprint?])
+    }
+}
+
+// The primitives are centered on the origin, see `Translate`.
+
+#[derive(Debug, Clone, Copy)]
+pub struct Sphere {
+    pub radius: f64
+}
+
+impl Field for Sphere {
+    fn distance(&self, p: Point) -> f64 {
+        p.length() - self.radius
+    }
+
+    fn fingerprint(&self) -> Option<u64> {
+        fingerprint("sphere", [self.radius.to_bits()])
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        Some(Aabb::new(-r, r))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Cuboid {
+    pub half: Vec3
+}
+
+impl Field for Cuboid {
+    fn distance(&self, p: Point) -> f64 {
+        let q = abs(p) - self.half;
+        positive(q).length() + q.x().max(q.y()).max(q.z()).min(0.0)
+    }
+
+    fn fingerprint(&self) -> Option<u64> {
+        fingerprint("cuboid", bits(self.half))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::new(-self.half, self.half))
+    }
+}
+
+// Box of the given half extents with its edges rounded off by `radius`.
+#[derive(Debug, Clone, Copy)]
+pub struct RoundedCuboid {
+    pub half: Vec3,
+    pub radius: f64
+}
+
+impl Field for RoundedCuboid {
+    fn distance(&self, p: Point) -> f64 {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        Cuboid { half: self.half - r }.distance(p) - self.radius
+    }
+
+    fn fingerprint(&self) -> Option<u64> {
+        fingerprint("rounded cuboid", bits(self.half).into_iter().chain([self.radius.to_bits()]))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::new(-self.half, self.half))
+    }
+}
+
+// Ring around the y axis, as `sence::Torus`.
+#[derive(Debug, Clone, Copy)]
+pub struct Torus {
+    pub major: f64,
+    pub minor: f64
+}
+
+impl Field for Torus {
+    fn distance(&self, p: Point) -> f64 {
+        let ring = libm::sqrt(p.x() * p.x() + p.z() * p.z()) - self.major;
+        libm::sqrt(ring * ring + p.y() * p.y()) - self.minor
+    }
+
+    fn fingerprint(&self) -> Option<u64> {
+        fingerprint("torus", [self.major.to_bits(), self.minor.to_bits()])
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let outer = self.major + self.minor;
+        let corner = Vec3::new(outer, self.minor, outer);
+        Some(Aabb::new(-corner, corner))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Capsule {
+    pub a: Point,
+    pub b: Point,
+    pub radius: f64
+}
+
+impl Field for Capsule {
+    fn distance(&self, p: Point) -> f64 {
+        let (pa, ba) = (p - self.a, self.b - self.a);
+        let h = (pa.dot(&ba) / ba.length_squared().max(1e-12)).clamp(0.0, 1.0);
+        (pa - h * ba).length() - self.radius
+    }
+
+    fn fingerprint(&self) -> Option<u64> {
+        let ends = bits(self.a).into_iter().chain(bits(self.b));
+        fingerprint("capsule", ends.chain([self.radius.to_bits()]))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.a, self.b).pad(self.radius))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Translate {
+    field: Arc<dyn Field>,
+    offset: Vec3
+}
+
+impl Translate {
+    pub fn new(field: impl Field + 'static, offset: Vec3) -> Self {
+        Self { field: Arc::new(field), offset }
+    }
+}
+
+impl Field for Translate {
+    fn distance(&self, p: Point) -> f64 {
+        self.field.distance(p - self.offset)
+    }
+
+    fn fingerprint(&self) -> Option<u64> {
+        fingerprint("translate", bits(self.offset).into_iter().chain([self.field.fingerprint()?]))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let bounds = self.field.bounds()?;
+        Some(Aabb::new(bounds.min() + self.offset, bounds.max() + self.offset))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Combine {
+    Union,
+    Intersection,
+    // `a` with `b` cut away
+    Difference
+}
+
+// Boolean combination of two fields. With a positive `smoothness` the
+// seam is filleted over about that distance (polynomial smooth min).
+#[derive(Debug, Clone)]
+pub struct Smooth {
+    combine: Combine,
+    a: Arc<dyn Field>,
+    b: Arc<dyn Field>,
+    smoothness: f64
+}
+
+impl Smooth {
+    pub fn new(
+        combine: Combine, a: impl Field + 'static, b: impl Field + 'static, smoothness: f64
+    ) -> Self {
+        Self { combine, a: Arc::new(a), b: Arc::new(b), smoothness }
+    }
+
+    pub fn union(a: impl Field + 'static, b: impl Field + 'static, smoothness: f64) -> Self {
+        Self::new(Combine::Union, a, b, smoothness)
+    }
+}
+
+impl Field for Smooth {
+    fn distance(&self, p: Point) -> f64 {
+        let (a, b) = (self.a.distance(p), self.b.distance(p));
+        let k = self.smoothness;
+        if k <= 0.0 {
+            return match self.combine {
+                Combine::Union => a.min(b),
+                Combine::Intersection => a.max(b),
+                Combine::Difference => a.max(-b)
+            };
+        }
+        match self.combine {
+            Combine::Union => {
+                let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+                mix(b, a, h) - k * h * (1.0 - h)
+            }
+            Combine::Intersection => {
+                let h = (0.5 - 0.5 * (b - a) / k).clamp(0.0, 1.0);
+                mix(b, a, h) + k * h * (1.0 - h)
+            }
+            Combine::Difference => {
+                let h = (0.5 - 0.5 * (b + a) / k).clamp(0.0, 1.0);
+                mix(a, -b, h) + k * h * (1.0 - h)
+            }
+        }
+    }
+
+    fn fingerprint(&self) -> Option<u64> {
+        let (a, b) = (self.a.fingerprint()?, self.b.fingerprint()?);
+        fingerprint("smooth", [self.combine as u64, a, b, self.smoothness.to_bits()])
+    }
+
+    // The smooth union bulges out by at most a quarter of the smoothness,
+    // the other two only shrink `a`.
+    fn bounds(&self) -> Option<Aabb> {
+        match self.combine {
+            Combine::Union => {
+                let bounds = self.a.bounds()?.union(self.b.bounds()?);
+                Some(bounds.pad(self.smoothness.max(0.0) / 4.0))
+            }
+            Combine::Intersection | Combine::Difference => self.a.bounds()
+        }
+    }
+}
+
+// Morph from `a` at 0 to `b` at 1.
+#[derive(Debug, Clone)]
+pub struct Blend {
+    a: Arc<dyn Field>,
+    b: Arc<dyn Field>,
+    t: f64
+}
+
+impl Blend {
+    pub fn new(a: impl Field + 'static, b: impl Field + 'static, t: f64) -> Self {
+        Self { a: Arc::new(a), b: Arc::new(b), t }
+    }
+}
+
+impl Field for Blend {
+    fn distance(&self, p: Point) -> f64 {
+        mix(self.a.distance(p), self.b.distance(p), self.t)
+    }
+
+    fn fingerprint(&self) -> Option<u64> {
+        fingerprint("blend", [self.a.fingerprint()?, self.b.fingerprint()?, self.t.to_bits()])
+    }
+
+    // Inside the blend one of the two is negative.
+    fn bounds(&self) -> Option<Aabb> {
+        Some(self.a.bounds()?.union(self.b.bounds()?))
+    }
+}
+
+// Endless copies of `field` on a grid with the given spacing, 0 leaving an
+// axis alone. The field should fit in one cell around the origin.
+#[derive(Debug, Clone)]
+pub struct Repeat {
+    field: Arc<dyn Field>,
+    period: Vec3
+}
+
+impl Repeat {
+    pub fn new(field: impl Field + 'static, period: Vec3) -> Self {
+        Self { field: Arc::new(field), period }
+    }
+}
+
+impl Field for Repeat {
+    fn distance(&self, p: Point) -> f64 {
+        let wrap = |x: f64, period: f64| {
+            if period > 0.0 { x - period * (x / period).round() } else { x }
+        };
+        let q = Vec3::new(
+            wrap(p.x(), self.period.x()), wrap(p.y(), self.period.y()), wrap(p.z(), self.period.z())
+        );
+        self.field.distance(q)
+    }
+
+    fn fingerprint(&self) -> Option<u64> {
+        fingerprint("repeat", bits(self.period).into_iter().chain([self.field.fingerprint()?]))
+    }
+}
+
+// Surface of a distance field, found by sphere tracing: the ray advances
+// by the distance to the surface until it's within `epsilon` of it or
+// `max_steps` run out. Bounds come from the field unless given; without
+// any (`Function`, `Repeat`) the field is unbounded, marched over the whole
+// ray interval and, like every unbounded object, tried for every ray in the
+// scene, which at up to `max_steps` field evaluations a ray is slow.
+#[derive(Debug)]
+pub struct Sdf {
+    field: Arc<dyn Field>,
+    material: MaterialId,
+    bounds: Option<Aabb>,
+    max_steps: u32,
+    epsilon: f64
+}
+
+impl Sdf {
+    pub fn new(field: impl Field + 'static, material: MaterialId) -> Self {
+        let bounds = field.bounds();
+        Self { field: Arc::new(field), material, bounds, max_steps: 256, epsilon: 1e-4 }
+    }
+
+    // Padded by `epsilon`, which the march stops short of the surface.
+    fn padded_bounds(&self) -> Option<Aabb> {
+        self.bounds.map(|bounds| bounds.pad(self.epsilon))
+    }
+
+    pub fn with_bounds(mut self, bounds: Aabb) -> Self {
+        self.bounds = Some(bounds);
+        self
+    }
+
+    pub fn with_max_steps(mut self, max_steps: u32) -> Self {
+        self.max_steps = max_steps.max(1);
+        self
+    }
+
+    pub fn with_epsilon(mut self, epsilon: f64) -> Self {
+        self.epsilon = epsilon;
+        self
+    }
+
+    // Outward normal from central differences, `epsilon` apart.
+    pub fn normal(&self, p: Point) -> Vec3 {
+        let e = self.epsilon;
+        let axis = |offset: Vec3| self.field.distance(p + offset) - self.field.distance(p - offset);
+        let gradient = Vec3::new(
+            axis(Vec3::new(e, 0.0, 0.0)), axis(Vec3::new(0.0, e, 0.0)), axis(Vec3::new(0.0, 0.0, e))
+        );
+        if gradient.near_zero() { Vec3::new(0.0, 1.0, 0.0) } else { gradient.unit() }
+    }
+}
+
+impl Hittable for Sdf {
+    fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
+        let range = match self.padded_bounds() {
+            Some(bounds) => bounds.clip(ray, interval)?,
+            None => interval
+        };
+        let length = ray.direction().length();
+        // Rays starting inside march towards the surface from that side.
+        let mut side = self.field.distance(ray.at(range.min())).signum();
+        let mut time = range.min();
+        // Scattered rays start on the surface and first have to get off it,
+        // at a grazing angle that takes a few steps.
+        let mut leaving = range.min() <= interval.min();
+        for _ in 0 .. self.max_steps {
+            if time > range.max() {
+                return None;
+            }
+            let distance = side * self.field.distance(ray.at(time));
+            if distance < self.epsilon && leaving {
+                time += self.epsilon / length;
+                side = self.field.distance(ray.at(time)).signum();
+                continue;
+            }
+            leaving = false;
+            if distance < self.epsilon {
+                if !interval.surrounds(time) {
+                    return None;
+                }
+                let point = ray.at(time);
+                let outward = self.normal(point);
+                let (front, normal) = if outward.dot(&ray.direction()) < 0.0 {
+                    (true, outward)
+                } else {
+                    (false, -outward)
+                };
+                return Some(HitRecord::new(point, normal, front, self.material, time));
+            }
+            time += distance / length;
+        }
+        None
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.padded_bounds()
+    }
+
+    fn fingerprint(&self) -> Option<u64> {
+        let bounds = self.bounds.map_or([0; 6], |b| {
+            let (min, max) = (bits(b.min()), bits(b.max()));
+            [min[0], min[1], min[2], max[0], max[1], max[2]]
+        });
+        let field = self.field.fingerprint()?;
+        let settings = [field, self.material.index() as u64, self.max_steps as u64];
+        fingerprint("sdf", settings.into_iter().chain(bounds).chain([self.epsilon.to_bits()]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracing_matches_analytic_shapes() {
+        let m = MaterialId::from_index(0);
+        let interval = Interval::new(0.001, f64::INFINITY);
+        let z = Vec3::new(0.0, 0.0, 1.0);
+        let ray = Ray::new(Point::new(0.0, 0.0, 5.0), -z);
+
+        let ball = Sdf::new(Sphere { radius: 1.0 }, m);
+        let rec = ball.hit(&ray, interval).unwrap();
+        assert!((rec.time() - 4.0).abs() < 1e-3 && rec.front());
+        assert!((rec.normal() - z).length() < 1e-3);
+        // from inside, as refracted rays are
+        let inner = Ray::new(Point::new(0.0, 0.0, 0.0), z);
+        let rec = ball.hit(&inner, interval).unwrap();
+        assert!((rec.time() - 1.0).abs() < 1e-3 && !rec.front());
+
+        let half = Vec3::new(1.0, 1.0, 2.0);
+        let block = Sdf::new(Cuboid { half }, m).with_bounds(Aabb::new(-half, half).pad(0.1));
+        assert!((block.hit(&ray, interval).unwrap().time() - 3.0).abs() < 1e-3);
+        let beside = Ray::new(Point::new(1.5, 0.0, 5.0), -z);
+        assert!(block.hit(&beside, interval).is_none());
+
+        // repeated balls two apart along x, the ray passes between rows
+        let row = Sdf::new(Repeat::new(Sphere { radius: 0.5 }, Vec3::new(2.0, 0.0, 0.0)), m);
+        let along = Ray::new(Point::new(-7.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!((row.hit(&along, interval).unwrap().time() - 0.5).abs() < 1e-3);
+        assert!(row.hit(&Ray::new(Point::new(-7.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0)), interval)
+            .is_none());
+    }
+
+    #[test]
+    fn rays_leave_the_surface_they_start_on() {
+        let m = MaterialId::from_index(0);
+        let interval = Interval::new(0.001, f64::INFINITY);
+        let far = Translate::new(Sphere { radius: 0.5 }, Vec3::new(4.0, 0.0, 1.2));
+        let pair = Sdf::new(Smooth::new(Combine::Union, Sphere { radius: 1.0 }, far, 0.0), m);
+        let surface = Point::new(0.0, 0.0, 1.0);
+        // refracted into the ball, out the far side
+        let rec = pair.hit(&Ray::new(surface, Vec3::new(0.0, 0.0, -1.0)), interval).unwrap();
+        assert!((rec.time() - 2.0).abs() < 1e-3 && !rec.front());
+        // grazing off it onto the other ball of the same field
+        let rec = pair.hit(&Ray::new(surface, Vec3::new(1.0, 0.0, 0.05)), interval).unwrap();
+        assert!((rec.point().x() - 3.5).abs() < 1e-2 && rec.front(), "{:?}", rec.point());
+    }
+
+    #[test]
+    fn smooth_union_fills_the_seam() {
+        let a = Translate::new(Sphere { radius: 1.0 }, Vec3::new(-1.2, 0.0, 0.0));
+        let b = Translate::new(Sphere { radius: 1.0 }, Vec3::new(1.2, 0.0, 0.0));
+        let origin = Point::new(0.0, 0.0, 0.0);
+        assert!(Smooth::new(Combine::Union, a.clone(), b.clone(), 0.0).distance(origin) > 0.0);
+        assert!(Smooth::union(a, b, 1.0).distance(origin) < 0.0);
+    }
+
+    #[test]
+    fn fingerprints_follow_the_fields() {
+        let m = MaterialId::from_index(0);
+        let ball = |radius: f64| Sdf::new(Translate::new(Sphere { radius }, Vec3::default()), m);
+        assert_eq!(ball(1.0).fingerprint(), ball(1.0).fingerprint());
+        assert_ne!(ball(1.0).fingerprint(), ball(1.5).fingerprint());
+        assert!(ball(1.0).fingerprint().is_some());
+
+        let function = || Function::new("f", |p: Point| p.length() - 1.0);
+        assert!(Sdf::new(function(), m).fingerprint().is_none());
+        let mixed = Smooth::union(function().with_fingerprint(7), Sphere { radius: 1.0 }, 0.1);
+        assert!(Sdf::new(mixed, m).fingerprint().is_some());
+        assert_eq!(format!("{:?}", function().with_fingerprint(7)), "Function(\"f\")");
+    }
+
+    #[test]
+    fn bounds_come_from_the_fields() {
+        let m = MaterialId::from_index(0);
+        let ball = Translate::new(Sphere { radius: 1.0 }, Vec3::new(3.0, 0.0, 0.0));
+        let ring = Torus { major: 2.0, minor: 0.5 };
+        let both = Sdf::new(Smooth::union(ball.clone(), ring, 0.4), m).bounding_box().unwrap();
+        let eps = 1e-4 + 0.1;
+        assert!((both.min() - Vec3::new(-2.5 - eps, -1.0 - eps, -2.5 - eps)).length() < 1e-9);
+        assert!((both.max() - Vec3::new(4.0 + eps, 1.0 + eps, 2.5 + eps)).length() < 1e-9);
+        // whatever is cut away, the result stays inside `a`
+        let cut = Smooth::new(Combine::Difference, ball.clone(), Sphere { radius: 10.0 }, 0.4);
+        assert_eq!(cut.bounds().unwrap().max().x(), 4.0);
+
+        let function = Function::new("f", |p: Point| p.length() - 1.0);
+        assert!(Sdf::new(function, m).bounding_box().is_none());
+        assert!(Sdf::new(Repeat::new(ball, Vec3::new(8.0, 0.0, 0.0)), m).bounding_box().is_none());
+    }
+}
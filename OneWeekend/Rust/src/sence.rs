@@ -1,27 +1,98 @@
-use std::rc::Rc;
+use std::fmt::{self, Debug};
+use std::io;
+use std::sync::{Arc, OnceLock};
 
 use super::vector::{Vec3, Point};
 use super::camera::Ray;
-use super::material::Material;
-use super::utils::Interval;
+use super::material::{Material, MaterialId, MaterialLibrary};
+use super::sampler::Sampler;
+use super::spectrum::{SampledSpectrum, SampledWavelengths};
+use super::vector::Color;
+use super::transform::Transform;
+use super::bvh::{Aabb, Bvh};
+use super::utils::{self, Interval, PI};
+use super::serialize::{self, invalid, unsupported, Record};
 
-pub trait Hittable {
+pub trait Hittable: Send + Sync + Debug {
     fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord>;
+
+    // None for unbounded objects, which are then tested for every ray.
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+
+    // Scene file form, None for objects that can't be saved.
+    fn to_record(&self) -> Option<Record> {
+        None
+    }
+
+    // Every stretch of the ray's whole line inside the object, ascending.
+    // None for objects that don't enclose a volume, which can't be used
+    // in a `Csg`.
+    fn spans(&self, _ray: &Ray) -> Option<Vec<Span>> {
+        None
+    }
+
+    // Content hash checkpoints compare scenes by, by default that of the
+    // record form. None for objects that can't tell, see `Sence::fingerprint`.
+    fn fingerprint(&self) -> Option<u64> {
+        self.to_record().map(|record| serialize::fingerprint(&record))
+    }
 }
 
 pub struct HitRecord {
     point: Point,
     normal: Vec3,
     front: bool,
-    material: Rc<dyn Material>,
-    time: f64
+    material: MaterialId,
+    time: f64,
+    uv: (f64, f64),
+    tangent: Vec3,
+    color: Option<Color>
 }
 
 impl HitRecord {
-    fn new(
-        point: Point, normal: Vec3, front: bool, material: Rc<dyn Material>, time: f64
+    pub fn new(
+        point: Point, normal: Vec3, front: bool, material: MaterialId, time: f64
     ) -> Self {
-        Self { point, normal, front, material, time }
+        Self {
+            point, normal, front, material, time,
+            uv: (0.0, 0.0), tangent: Vec3::default(), color: None
+        }
+    }
+
+    pub fn with_uv(mut self, u: f64, v: f64) -> Self {
+        self.uv = (u, v);
+        self
+    }
+
+    // Surface direction of increasing u, used for normal mapping.
+    pub fn with_tangent(mut self, tangent: Vec3) -> Self {
+        self.tangent = tangent;
+        self
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    // The normal already faces the ray, only the side changes.
+    pub fn with_front(mut self, front: bool) -> Self {
+        self.front = front;
+        self
+    }
+
+    pub fn uv(&self) -> (f64, f64) {
+        self.uv
+    }
+
+    pub fn tangent(&self) -> Vec3 {
+        self.tangent
+    }
+
+    pub fn color(&self) -> Option<Color> {
+        self.color
     }
 
     pub fn normal(&self) -> Vec3 {
@@ -36,34 +107,538 @@ impl HitRecord {
         self.front
     }
 
-    pub fn material(&self) -> Rc<dyn Material> {
-        self.material.clone()
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    pub fn material(&self) -> MaterialId {
+        self.material
+    }
+}
+
+// Where a ray enters and leaves a solid.
+pub struct Span {
+    pub enter: HitRecord,
+    pub exit: HitRecord
+}
+
+// Handle to a node of a `Sence`. IDs aren't reused after removal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+impl NodeId {
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Visibility {
+    // Seen by rays coming straight from the camera.
+    pub camera: bool,
+    // Seen by scattered rays, so it shadows and reflects in other objects.
+    pub shadows: bool
+}
+
+impl Default for Visibility {
+    fn default() -> Self {
+        Self { camera: true, shadows: true }
+    }
+}
+
+// A named element of the scene graph. Its transform is relative to the
+// parent, the object (if any) is placed with the accumulated transform and
+// the visibility applies to the whole subtree.
+#[derive(Debug, Clone)]
+pub struct Node {
+    name: String,
+    transform: Transform,
+    object: Option<Arc<dyn Hittable>>,
+    visibility: Visibility,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>
+}
+
+impl Node {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(), transform: Transform::identity(), object: None,
+            visibility: Visibility::default(), parent: None, children: Vec::new()
+        }
+    }
+
+    pub fn with_object(mut self, object: impl Hittable + 'static) -> Self {
+        self.object = Some(Arc::new(object));
+        self
+    }
+
+    // Object that other nodes may be instancing as well.
+    pub fn with_shared(mut self, object: Arc<dyn Hittable>) -> Self {
+        self.object = Some(object);
+        self
+    }
+
+    pub fn with_transform(mut self, transform: Transform) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    pub fn with_visibility(mut self, visibility: Visibility) -> Self {
+        self.visibility = visibility;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn transform(&self) -> Transform {
+        self.transform
+    }
+
+    pub fn object(&self) -> Option<&Arc<dyn Hittable>> {
+        self.object.as_ref()
+    }
+
+    pub fn visibility(&self) -> Visibility {
+        self.visibility
+    }
+
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+}
+
+// Record of a hit in object space moved out by `transform`. Times stay the
+// same, the ray was moved in by the inverse.
+fn to_world(transform: &Transform, mut rec: HitRecord) -> HitRecord {
+    rec.point = transform.point(rec.point);
+    rec.normal = transform.normal(rec.normal).unit();
+    rec.tangent = transform.vector(rec.tangent);
+    rec
+}
+
+// Object placed in world space, flattened out of the graph for rendering.
+#[derive(Debug)]
+struct Instance {
+    object: Arc<dyn Hittable>,
+    transform: Option<Transform>,
+    visibility: Visibility
+}
+
+impl Hittable for Instance {
+    fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
+        let visible = if ray.is_primary() {
+            self.visibility.camera
+        } else {
+            self.visibility.shadows
+        };
+        if !visible {
+            return None;
+        }
+        let Some(transform) = &self.transform else {
+            return self.object.hit(ray, interval);
+        };
+        let rec = self.object.hit(&transform.inverse().ray(ray), interval)?;
+        Some(to_world(transform, rec))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let bounds = self.object.bounding_box()?;
+        Some(match &self.transform {
+            Some(transform) => bounds.transform(transform),
+            None => bounds
+        })
+    }
+}
+
+// Instances of the graph, the bounded ones in a BVH.
+#[derive(Debug, Default)]
+struct Flattened {
+    bounded: Vec<Instance>,
+    unbounded: Vec<Instance>,
+    bvh: Bvh
+}
+
+// Radiance of rays leaving the scene.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Background {
+    // White to light blue gradient going up.
+    #[default]
+    Sky,
+    Solid(Color)
+}
+
+impl Background {
+    pub fn value(&self, ray: &Ray) -> Color {
+        match self {
+            Self::Sky => {
+                let unit = ray.direction().unit();
+                let a = 0.5 * (unit.y() + 1.0);
+                (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0)
+            }
+            Self::Solid(color) => *color
+        }
+    }
+
+    fn to_record(self) -> Record {
+        match self {
+            Self::Sky => Record::new("sky"),
+            Self::Solid(color) => Record::new("solid").with("color", color)
+        }
+    }
+
+    fn from_record(record: &Record) -> io::Result<Self> {
+        match record.kind() {
+            "sky" => Ok(Self::Sky),
+            "solid" => Ok(Self::Solid(record.vector("color")?)),
+            other => Err(invalid(format!("unknown background {}", other)))
+        }
     }
 }
 
 pub struct Sence {
-    objects: Vec<Box<dyn Hittable>>
+    background: Background,
+    materials: MaterialLibrary,
+    nodes: Vec<Option<Node>>,
+    roots: Vec<NodeId>,
+    instances: OnceLock<Flattened>
 }
 
 impl Sence {
     pub fn new() -> Self {
-        Self { objects: Vec::new() }
+        Self {
+            background: Background::default(), materials: MaterialLibrary::new(), nodes: Vec::new(), roots: Vec::new(),
+            instances: OnceLock::new()
+        }
+    }
+
+    pub fn background(&self, ray: &Ray) -> Color {
+        self.background.value(ray)
+    }
+
+    pub fn set_background(&mut self, background: Background) {
+        self.background = background;
+    }
+
+    pub fn add_material(
+        &mut self, name: impl Into<String>, material: impl Material + 'static
+    ) -> MaterialId {
+        self.materials.add(name, material)
+    }
+
+    pub fn materials(&self) -> &MaterialLibrary {
+        &self.materials
+    }
+
+    pub fn materials_mut(&mut self) -> &mut MaterialLibrary {
+        &mut self.materials
+    }
+
+    // Scatters off the hit's material, counting it in the material's
+    // statistics. Paths hitting an unknown material are absorbed.
+    pub fn scatter(
+        &self, ray: &Ray, record: &HitRecord, sampler: &mut dyn Sampler
+    ) -> Option<(Ray, Color)> {
+        let result = self.materials.get(record.material)?.scatter(ray, record, sampler);
+        self.materials.record(record.material, result.is_some());
+        result
+    }
+
+    pub fn emitted(&self, ray: &Ray, record: &HitRecord) -> Color {
+        self.materials.get(record.material).map_or(Color::default(), |m| m.emitted(ray, record))
+    }
+
+    pub fn scatter_spectral(
+        &self, ray: &Ray, record: &HitRecord, sampler: &mut dyn Sampler,
+        lambda: &mut SampledWavelengths
+    ) -> Option<(Ray, SampledSpectrum)> {
+        let material = self.materials.get(record.material)?;
+        let result = material.scatter_spectral(ray, record, sampler, lambda);
+        self.materials.record(record.material, result.is_some());
+        result
+    }
+
+    // Adds an unnamed top-level node holding `hittable`.
+    pub fn push(&mut self, hittable: impl Hittable + 'static) -> NodeId {
+        self.insert(None, Node::new("").with_object(hittable))
+    }
+
+    // Adds `node` under `parent`, or at the top level. Children the node
+    // may list are ignored.
+    pub fn insert(&mut self, parent: Option<NodeId>, mut node: Node) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        node.children.clear();
+        node.parent = parent.filter(|&p| self.node(p).is_some());
+        match node.parent {
+            Some(parent) => self.nodes[parent.0].as_mut().unwrap().children.push(id),
+            None => self.roots.push(id)
+        }
+        self.nodes.push(Some(node));
+        self.instances.take();
+        id
+    }
+
+    pub fn node(&self, id: NodeId) -> Option<&Node> {
+        self.nodes.get(id.0)?.as_ref()
+    }
+
+    // First node called `name`, in insertion order.
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        self.nodes().find(|(_, node)| node.name == name).map(|(id, _)| id)
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = (NodeId, &Node)> {
+        self.nodes.iter().enumerate().filter_map(|(i, node)| Some((NodeId(i), node.as_ref()?)))
+    }
+
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
     }
 
-    pub fn push(&mut self, hittable: impl Hittable + 'static) {
-        self.objects.push(Box::new(hittable));
+    fn node_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        self.instances.take();
+        self.nodes.get_mut(id.0)?.as_mut()
+    }
+
+    pub fn set_name(&mut self, id: NodeId, name: impl Into<String>) -> bool {
+        self.node_mut(id).map(|node| node.name = name.into()).is_some()
+    }
+
+    pub fn set_transform(&mut self, id: NodeId, transform: Transform) -> bool {
+        self.node_mut(id).map(|node| node.transform = transform).is_some()
+    }
+
+    pub fn set_visibility(&mut self, id: NodeId, visibility: Visibility) -> bool {
+        self.node_mut(id).map(|node| node.visibility = visibility).is_some()
+    }
+
+    // Swaps the node's object, keeping its transform and children. Returns
+    // the previous object.
+    pub fn replace(
+        &mut self, id: NodeId, hittable: impl Hittable + 'static
+    ) -> Option<Arc<dyn Hittable>> {
+        self.node_mut(id)?.object.replace(Arc::new(hittable))
+    }
+
+    pub fn replace_named(
+        &mut self, name: &str, hittable: impl Hittable + 'static
+    ) -> Option<Arc<dyn Hittable>> {
+        self.replace(self.find(name)?, hittable)
+    }
+
+    // Removes the node together with its subtree, returning the node.
+    pub fn remove(&mut self, id: NodeId) -> Option<Node> {
+        let node = self.nodes.get_mut(id.0)?.take()?;
+        self.instances.take();
+        match node.parent {
+            Some(parent) => self.nodes[parent.0].as_mut().unwrap().children.retain(|&c| c != id),
+            None => self.roots.retain(|&r| r != id)
+        }
+        let mut stack = node.children.clone();
+        while let Some(child) = stack.pop() {
+            if let Some(child) = self.nodes[child.0].take() {
+                stack.extend(child.children);
+            }
+        }
+        Some(node)
+    }
+
+    pub fn remove_named(&mut self, name: &str) -> Option<Node> {
+        self.remove(self.find(name)?)
+    }
+
+    // Transform from the node's local space to world space.
+    pub fn world_transform(&self, id: NodeId) -> Option<Transform> {
+        let mut node = self.node(id)?;
+        let mut transform = node.transform;
+        while let Some(parent) = node.parent {
+            node = self.node(parent)?;
+            transform = node.transform * transform;
+        }
+        Some(transform)
     }
 
     pub fn clear(&mut self) {
-        self.objects.clear();
+        self.nodes.clear();
+        self.roots.clear();
+        self.instances.take();
+    }
+
+    // Records for the scene file: background, materials, objects (each
+    // once, however many nodes share it), then the nodes in ID order so
+    // parents come first.
+    pub(crate) fn to_records(&self) -> io::Result<Vec<Record>> {
+        let background = Record::new("background").with("value", self.background.to_record());
+        let mut records = vec![background];
+        for id in self.materials.ids() {
+            let name = self.materials.name(id).unwrap();
+            let material = self.materials.get(id).unwrap().to_record()
+                .ok_or_else(|| unsupported(format!("material {}", name)))?;
+            records.push(Record::new("material").with("name", name).with("value", material));
+        }
+        let mut objects: Vec<*const ()> = Vec::new();
+        let mut indices = vec![None; self.nodes.len()];
+        for (position, (id, node)) in self.nodes().enumerate() {
+            indices[id.0] = Some(position);
+            let mut record = Record::new("node")
+                .with("name", node.name.as_str())
+                .with("transform", node.transform.to_record())
+                .with("camera", node.visibility.camera)
+                .with("shadows", node.visibility.shadows);
+            if let Some(parent) = node.parent {
+                record = record.with("parent", indices[parent.0].unwrap());
+            }
+            if let Some(object) = &node.object {
+                let pointer = Arc::as_ptr(object) as *const ();
+                let index = match objects.iter().position(|&p| p == pointer) {
+                    Some(index) => index,
+                    None => {
+                        let value = object.to_record()
+                            .ok_or_else(|| unsupported(format!("object of node {:?}", node.name)))?;
+                        records.push(Record::new("object").with("value", value));
+                        objects.push(pointer);
+                        objects.len() - 1
+                    }
+                };
+                record = record.with("object", index);
+            }
+            records.push(record);
+        }
+        Ok(records)
+    }
+
+    // Hash of the scene's structure and contents, the scene file form
+    // without failing on parts that have none. Materials and objects that
+    // can't be saved and give no fingerprint only count, two of them with
+    // the same name compare equal.
+    pub fn fingerprint(&self) -> u64 {
+        let record = |record: Option<Record>| record.map_or(0, |r| serialize::fingerprint(&r));
+        let mut parts = vec![serialize::fingerprint(&self.background.to_record())];
+        for id in self.materials.ids() {
+            parts.push(utils::fnv1a(self.materials.name(id).unwrap().as_bytes()));
+            parts.push(record(self.materials.get(id).unwrap().to_record()));
+        }
+        let mut positions = vec![None; self.nodes.len()];
+        for (position, (id, node)) in self.nodes().enumerate() {
+            positions[id.0] = Some(position as u64);
+            parts.push(utils::fnv1a(node.name.as_bytes()));
+            parts.push(serialize::fingerprint(&node.transform.to_record()));
+            parts.push(node.visibility.camera as u64 | (node.visibility.shadows as u64) << 1);
+            parts.push(node.parent.map_or(u64::MAX, |parent| positions[parent.0].unwrap()));
+            parts.push(node.object.as_ref().map_or(0, |object| object.fingerprint().unwrap_or(1)));
+        }
+        utils::hash(&parts)
+    }
+
+    pub(crate) fn from_records(records: &[Record]) -> io::Result<Self> {
+        let mut sence = Self::new();
+        let mut objects = Vec::new();
+        let mut nodes = Vec::new();
+        let mut used = Vec::new();
+        for record in records {
+            match record.kind() {
+                "background" => {
+                    sence.background = Background::from_record(record.record("value")?)?;
+                }
+                "material" => {
+                    let material = serialize::material(record.record("value")?)?;
+                    sence.materials.add_shared(record.text("name")?, material);
+                }
+                "object" => {
+                    let value = record.record("value")?;
+                    serialize::material_indices(value, &mut used)?;
+                    objects.push(serialize::hittable(value)?);
+                }
+                "node" => {
+                    let parent = match record.get("parent") {
+                        Some(_) => Some(*nodes.get(record.integer("parent")?)
+                            .ok_or_else(|| invalid("node before its parent"))?),
+                        None => None
+                    };
+                    let mut node = Node::new(record.text("name")?)
+                        .with_transform(Transform::from_record(record.record("transform")?)?)
+                        .with_visibility(Visibility {
+                            camera: record.boolean("camera")?,
+                            shadows: record.boolean("shadows")?
+                        });
+                    if record.get("object").is_some() {
+                        let object = objects.get(record.integer("object")?)
+                            .ok_or_else(|| invalid("node refers to a missing object"))?;
+                        node = node.with_shared(object.clone());
+                    }
+                    nodes.push(sence.insert(parent, node));
+                }
+                other => return Err(invalid(format!("unknown record {}", other)))
+            }
+        }
+        // materials may come after the objects, so check once all are in
+        if let Some(index) = used.into_iter().find(|&index| index >= sence.materials.len()) {
+            return Err(invalid(format!("unknown material {}", index)));
+        }
+        Ok(sence)
+    }
+
+    fn flatten(&self) -> Flattened {
+        let mut instances = Vec::new();
+        let mut stack: Vec<_> = self.roots.iter()
+            .rev()
+            .map(|&id| (id, Transform::identity(), Visibility::default()))
+            .collect();
+        while let Some((id, parent, visibility)) = stack.pop() {
+            let node = self.nodes[id.0].as_ref().unwrap();
+            let transform = parent * node.transform;
+            let visibility = Visibility {
+                camera: visibility.camera && node.visibility.camera,
+                shadows: visibility.shadows && node.visibility.shadows
+            };
+            if let Some(object) = &node.object {
+                instances.push(Instance {
+                    object: object.clone(),
+                    transform: (!transform.is_identity()).then_some(transform),
+                    visibility
+                });
+            }
+            stack.extend(node.children.iter().rev().map(|&c| (c, transform, visibility)));
+        }
+        let (bounded, unbounded): (Vec<_>, Vec<_>) =
+            instances.into_iter().partition(|i| i.bounding_box().is_some());
+        let boxes: Vec<_> = bounded.iter().map(|i| i.bounding_box().unwrap()).collect();
+        Flattened { bvh: Bvh::new(&boxes), bounded, unbounded }
+    }
+}
+
+impl Default for Sence {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The flattened instances are a cache and left out, so the output only
+// depends on the graph.
+impl Debug for Sence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sence")
+            .field("background", &self.background)
+            .field("materials", &self.materials)
+            .field("nodes", &self.nodes)
+            .field("roots", &self.roots)
+            .finish()
     }
 }
 
 impl Hittable for Sence {
     fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
-        let mut record = None;
-        let mut max_time = interval.max();
-        for object in &self.objects {
+        let flattened = self.instances.get_or_init(|| self.flatten());
+        let mut record = flattened.bvh.hit(ray, interval, |i, interval| {
+            flattened.bounded[i].hit(ray, interval)
+        });
+        let mut max_time = record.as_ref().map_or(interval.max(), |r| r.time);
+        for object in &flattened.unbounded {
             if let Some(rec) = object.hit(
                 ray, Interval::new(interval.min(), max_time)
             ) {
@@ -75,17 +650,30 @@ impl Hittable for Sence {
         }
         record
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let flattened = self.instances.get_or_init(|| self.flatten());
+        flattened.unbounded.is_empty().then(|| flattened.bvh.bounds())
+    }
 }
 
+#[derive(Debug)]
 pub struct Sphere {
     center: Point,
     radius: f64,
-    material: Rc<dyn Material>
+    material: MaterialId
 }
 
 impl Sphere {
-    pub fn new(center: Point, radius: f64, material: impl Material + 'static) -> Self {
-        Self { center, radius, material: Rc::new(material) }
+    pub fn new(center: Point, radius: f64, material: MaterialId) -> Self {
+        Self { center, radius, material }
+    }
+
+    pub(crate) fn from_record(record: &Record) -> io::Result<Self> {
+        Ok(Self::new(
+            record.vector("center")?, record.number("radius")?,
+            MaterialId::from_index(record.integer("material")?)
+        ))
     }
 }
 
@@ -107,16 +695,1197 @@ impl Hittable for Sphere {
                 return None;
             }
         }
-        let time = root;
+        Some(self.record(ray, root))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = Vec3::new(1.0, 1.0, 1.0) * self.radius.abs();
+        Some(Aabb::new(self.center - r, self.center + r))
+    }
+
+    fn to_record(&self) -> Option<Record> {
+        Some(Record::new("sphere")
+            .with("center", self.center)
+            .with("radius", self.radius)
+            .with("material", self.material.index()))
+    }
+
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        let oc = ray.origin() - self.center;
+        let c = oc.length_squared() - self.radius * self.radius;
+        let roots = quadratic(ray.direction().length_squared(), oc.dot(&ray.direction()), c);
+        Some(roots.into_iter().map(|(t0, t1)| Span {
+            enter: self.record(ray, t0),
+            exit: self.record(ray, t1)
+        }).collect())
+    }
+}
+
+impl Sphere {
+    fn record(&self, ray: &Ray, time: f64) -> HitRecord {
+        let point = ray.at(time);
+        let outward = (point - self.center) / self.radius;
+        let (front, normal) = if outward.dot(&ray.direction()) < 0.0 {
+            (true, outward)
+        } else {
+            (false, -outward)
+        };
+        // u around the y axis starting at -x, v from the south pole
+        let theta = libm::acos((-outward.y()).clamp(-1.0, 1.0));
+        let phi = libm::atan2(-outward.z(), outward.x()) + PI;
+        let tangent = Vec3::new(libm::sin(phi), 0.0, libm::cos(phi));
+        HitRecord::new(point, normal, front, self.material, time)
+            .with_uv(phi / (2.0 * PI), theta / PI)
+            .with_tangent(tangent)
+    }
+}
+
+// Parallelogram spanned by `u` and `v` from the corner `q`. Its front
+// side faces u x v, texture coordinates run along the edges.
+#[derive(Debug)]
+pub struct Quad {
+    q: Point,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    normal: Vec3,
+    d: f64,
+    material: MaterialId
+}
+
+impl Quad {
+    pub fn new(q: Point, u: Vec3, v: Vec3, material: MaterialId) -> Self {
+        let n = u.cross(&v);
+        let normal = n.unit();
+        Self { q, u, v, w: n / n.dot(&n), normal, d: normal.dot(&q), material }
+    }
+
+    // The six faces of the axis-aligned box with opposite corners `a` and
+    // `b`, facing outwards.
+    pub fn cuboid(a: Point, b: Point, material: MaterialId) -> [Quad; 6] {
+        let min = Point::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()));
+        let max = Point::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()));
+        let dx = Vec3::new(max.x() - min.x(), 0.0, 0.0);
+        let dy = Vec3::new(0.0, max.y() - min.y(), 0.0);
+        let dz = Vec3::new(0.0, 0.0, max.z() - min.z());
+        [
+            Quad::new(Point::new(min.x(), min.y(), max.z()), dx, dy, material),
+            Quad::new(Point::new(max.x(), min.y(), max.z()), -dz, dy, material),
+            Quad::new(Point::new(max.x(), min.y(), min.z()), -dx, dy, material),
+            Quad::new(Point::new(min.x(), min.y(), min.z()), dz, dy, material),
+            Quad::new(Point::new(min.x(), max.y(), max.z()), dx, -dz, material),
+            Quad::new(Point::new(min.x(), min.y(), min.z()), dx, dz, material)
+        ]
+    }
+
+    pub(crate) fn from_record(record: &Record) -> io::Result<Self> {
+        Ok(Self::new(
+            record.vector("q")?, record.vector("u")?, record.vector("v")?,
+            MaterialId::from_index(record.integer("material")?)
+        ))
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
+        let denom = self.normal.dot(&ray.direction());
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+        let time = (self.d - self.normal.dot(&ray.origin())) / denom;
+        if !interval.surrounds(time) {
+            return None;
+        }
         let point = ray.at(time);
-        let (front, normal) = {
-            let n = (point - self.center) / self.radius;
-            if n.dot(&ray.direction()) < 0.0 {
-                (true, n)
-            } else {
-                (false, -n)
+        let planar = point - self.q;
+        let alpha = self.w.dot(&planar.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar));
+        if !(0.0 ..= 1.0).contains(&alpha) || !(0.0 ..= 1.0).contains(&beta) {
+            return None;
+        }
+        let front = denom < 0.0;
+        let normal = if front { self.normal } else { -self.normal };
+        Some(
+            HitRecord::new(point, normal, front, self.material, time)
+                .with_uv(alpha, beta)
+                .with_tangent(self.u)
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let corners = [self.q, self.q + self.u, self.q + self.v, self.q + self.u + self.v];
+        Some(corners.into_iter().fold(Aabb::empty(), Aabb::grow).pad(1e-4))
+    }
+
+    fn to_record(&self) -> Option<Record> {
+        Some(Record::new("quad")
+            .with("q", self.q)
+            .with("u", self.u)
+            .with("v", self.v)
+            .with("material", self.material.index()))
+    }
+}
+
+// Participating medium of constant density filling a closed `boundary`,
+// scattering with the (usually `Isotropic`) `material`. The free path is
+// drawn from a hash of the ray so renders stay deterministic. `hit` has no
+// sampler, so the draws aren't stratified like the camera's samples, and
+// rays with the same bits always scatter at the same distance.
+#[derive(Debug)]
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hittable>,
+    density: f64,
+    material: MaterialId
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: impl Hittable + 'static, density: f64, material: MaterialId) -> Self {
+        Self::shared(Arc::new(boundary), density, material)
+    }
+
+    pub fn shared(boundary: Arc<dyn Hittable>, density: f64, material: MaterialId) -> Self {
+        Self { boundary, density, material }
+    }
+
+    pub(crate) fn from_record(record: &Record) -> io::Result<Self> {
+        Ok(Self::shared(
+            serialize::hittable(record.record("boundary")?)?,
+            record.number("density")?,
+            MaterialId::from_index(record.integer("material")?)
+        ))
+    }
+
+    fn random(ray: &Ray) -> f64 {
+        let (o, d) = (ray.origin(), ray.direction());
+        let bits: Vec<u64> = (0 .. 3)
+            .flat_map(|a| [o.axis(a).to_bits(), d.axis(a).to_bits()])
+            .collect();
+        (utils::hash(&bits) >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
+        let all = Interval::new(f64::NEG_INFINITY, f64::INFINITY);
+        let enter = self.boundary.hit(ray, all)?;
+        let exit = self.boundary.hit(ray, Interval::new(enter.time() + 0.0001, f64::INFINITY))?;
+        let t0 = enter.time().max(interval.min()).max(0.0);
+        let t1 = exit.time().min(interval.max());
+        if t0 >= t1 {
+            return None;
+        }
+        let length = ray.direction().length();
+        let inside = (t1 - t0) * length;
+        let distance = -libm::log(1.0 - Self::random(ray)) / self.density;
+        if distance > inside {
+            return None;
+        }
+        let time = t0 + distance / length;
+        // normal and side are arbitrary inside a medium
+        Some(HitRecord::new(ray.at(time), Vec3::new(1.0, 0.0, 0.0), true, self.material, time))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+
+    fn to_record(&self) -> Option<Record> {
+        Some(Record::new("medium")
+            .with("boundary", self.boundary.to_record()?)
+            .with("density", self.density)
+            .with("material", self.material.index()))
+    }
+}
+
+// Surface point of an analytic primitive along a ray, with the outward
+// normal. Primitives list every crossing of the line so `hit` can take the
+// nearest one inside the interval.
+struct Crossing {
+    time: f64,
+    outward: Vec3,
+    uv: (f64, f64),
+    tangent: Vec3
+}
+
+impl Crossing {
+    fn new(time: f64, outward: Vec3, uv: (f64, f64), tangent: Vec3) -> Self {
+        Self { time, outward, uv, tangent }
+    }
+
+    fn record(self, ray: &Ray, material: MaterialId) -> HitRecord {
+        let (front, normal) = if self.outward.dot(&ray.direction()) < 0.0 {
+            (true, self.outward)
+        } else {
+            (false, -self.outward)
+        };
+        HitRecord::new(ray.at(self.time), normal, front, material, self.time)
+            .with_uv(self.uv.0, self.uv.1)
+            .with_tangent(self.tangent)
+    }
+}
+
+fn nearest(
+    crossings: Vec<Crossing>, ray: &Ray, interval: Interval, material: MaterialId
+) -> Option<HitRecord> {
+    crossings.into_iter()
+        .filter(|c| interval.surrounds(c.time))
+        .min_by(|a, b| a.time.total_cmp(&b.time))
+        .map(|c| c.record(ray, material))
+}
+
+// Pairs up the crossings of a closed surface. An odd one left over from a
+// grazing ray is dropped.
+fn spans(mut crossings: Vec<Crossing>, ray: &Ray, material: MaterialId) -> Vec<Span> {
+    crossings.sort_by(|a, b| a.time.total_cmp(&b.time));
+    let mut crossings = crossings.into_iter();
+    let mut spans = Vec::new();
+    while let (Some(enter), Some(exit)) = (crossings.next(), crossings.next()) {
+        spans.push(Span { enter: enter.record(ray, material), exit: exit.record(ray, material) });
+    }
+    spans
+}
+
+// Both roots of a t^2 + 2 b t + c, ascending.
+fn quadratic(a: f64, b: f64, c: f64) -> Option<(f64, f64)> {
+    if a.abs() < 1e-12 {
+        return None;
+    }
+    let dis = b * b - a * c;
+    if dis < 0.0 {
+        return None;
+    }
+    let (t0, t1) = ((-b - libm::sqrt(dis)) / a, (-b + libm::sqrt(dis)) / a);
+    Some((t0.min(t1), t0.max(t1)))
+}
+
+// Real roots in [lo, hi] of the polynomial with coefficients in ascending
+// powers. The derivative's roots split the range into monotonic pieces
+// which are bisected, so double roots (grazing rays) are missed.
+fn polynomial_roots(coeffs: &[f64], lo: f64, hi: f64) -> Vec<f64> {
+    let eval = |x: f64| coeffs.iter().rev().fold(0.0, |acc, c| acc * x + c);
+    if coeffs.len() <= 2 {
+        return match coeffs {
+            [c0, c1] if *c1 != 0.0 => Some(-c0 / c1).filter(|x| (lo ..= hi).contains(x)),
+            _ => None
+        }.into_iter().collect();
+    }
+    let derivative: Vec<f64> = coeffs.iter().enumerate().skip(1)
+        .map(|(i, c)| i as f64 * c)
+        .collect();
+    let mut bounds = vec![lo];
+    bounds.extend(polynomial_roots(&derivative, lo, hi));
+    bounds.push(hi);
+    let mut roots = Vec::new();
+    for pair in bounds.windows(2) {
+        let (mut a, mut b) = (pair[0], pair[1]);
+        let (fa, fb) = (eval(a), eval(b));
+        if fb == 0.0 {
+            roots.push(b);
+            continue;
+        }
+        if fa == 0.0 || fa.signum() == fb.signum() {
+            continue;
+        }
+        for _ in 0 .. 100 {
+            let m = 0.5 * (a + b);
+            if m <= a || m >= b {
+                break;
+            }
+            if eval(m).signum() == fa.signum() { a = m } else { b = m }
+        }
+        roots.push(0.5 * (a + b));
+    }
+    roots
+}
+
+// Angle around the y axis as in `Sphere`, and the direction it increases.
+fn around_y(x: f64, z: f64) -> (f64, Vec3) {
+    let phi = libm::atan2(-z, x) + PI;
+    (phi / (2.0 * PI), Vec3::new(libm::sin(phi), 0.0, libm::cos(phi)))
+}
+
+// Disk of `radius` on the plane y = `height` of the local frame.
+fn disk(
+    p: Point, d: Vec3, height: f64, radius: f64, outward: Vec3, crossings: &mut Vec<Crossing>
+) {
+    if d.y().abs() < 1e-12 {
+        return;
+    }
+    let t = (height - p.y()) / d.y();
+    let (x, z) = (p.x() + t * d.x(), p.z() + t * d.z());
+    if x * x + z * z <= radius * radius {
+        let uv = ((x / radius + 1.0) / 2.0, (z / radius + 1.0) / 2.0);
+        crossings.push(Crossing::new(t, outward, uv, Vec3::new(1.0, 0.0, 0.0)));
+    }
+}
+
+fn upright_box(base: Point, radius: f64, height: f64) -> Aabb {
+    let r = radius.abs();
+    Aabb::new(base - Vec3::new(r, 0.0, r), base + Vec3::new(r, height, r)).pad(1e-4)
+}
+
+// Cylinder standing on the disk around `base`, along +y up to `height`.
+// Capped by default; other orientations come from the node transform. u
+// runs around the axis and v up the side; the caps are mapped flat.
+#[derive(Debug)]
+pub struct Cylinder {
+    base: Point,
+    radius: f64,
+    height: f64,
+    capped: bool,
+    material: MaterialId
+}
+
+impl Cylinder {
+    pub fn new(base: Point, radius: f64, height: f64, material: MaterialId) -> Self {
+        Self { base, radius, height, capped: true, material }
+    }
+
+    pub fn with_caps(mut self, capped: bool) -> Self {
+        self.capped = capped;
+        self
+    }
+
+    pub(crate) fn from_record(record: &Record) -> io::Result<Self> {
+        Ok(Self::new(
+            record.vector("base")?, record.number("radius")?, record.number("height")?,
+            MaterialId::from_index(record.integer("material")?)
+        ).with_caps(record.boolean("capped")?))
+    }
+
+    fn crossings(&self, ray: &Ray) -> Vec<Crossing> {
+        let (p, d) = (ray.origin() - self.base, ray.direction());
+        let r = self.radius;
+        let mut crossings = Vec::new();
+        let a = d.x() * d.x() + d.z() * d.z();
+        let b = p.x() * d.x() + p.z() * d.z();
+        let c = p.x() * p.x() + p.z() * p.z() - r * r;
+        if let Some((t0, t1)) = quadratic(a, b, c) {
+            for t in [t0, t1] {
+                let q = p + t * d;
+                if (0.0 ..= self.height).contains(&q.y()) {
+                    let (u, tangent) = around_y(q.x(), q.z());
+                    let outward = Vec3::new(q.x() / r, 0.0, q.z() / r);
+                    crossings.push(Crossing::new(t, outward, (u, q.y() / self.height), tangent));
+                }
+            }
+        }
+        if self.capped {
+            disk(p, d, 0.0, r, Vec3::new(0.0, -1.0, 0.0), &mut crossings);
+            disk(p, d, self.height, r, Vec3::new(0.0, 1.0, 0.0), &mut crossings);
+        }
+        crossings
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
+        nearest(self.crossings(ray), ray, interval, self.material)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(upright_box(self.base, self.radius, self.height))
+    }
+
+    fn to_record(&self) -> Option<Record> {
+        Some(Record::new("cylinder")
+            .with("base", self.base)
+            .with("radius", self.radius)
+            .with("height", self.height)
+            .with("capped", self.capped)
+            .with("material", self.material.index()))
+    }
+
+    // Only closed with its caps.
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        self.capped.then(|| spans(self.crossings(ray), ray, self.material))
+    }
+}
+
+// Cone with its base disk around `base` and the apex `height` above it
+// along +y. Capped by default; mapped like `Cylinder`.
+#[derive(Debug)]
+pub struct Cone {
+    base: Point,
+    radius: f64,
+    height: f64,
+    capped: bool,
+    material: MaterialId
+}
+
+impl Cone {
+    pub fn new(base: Point, radius: f64, height: f64, material: MaterialId) -> Self {
+        Self { base, radius, height, capped: true, material }
+    }
+
+    pub fn with_caps(mut self, capped: bool) -> Self {
+        self.capped = capped;
+        self
+    }
+
+    pub(crate) fn from_record(record: &Record) -> io::Result<Self> {
+        Ok(Self::new(
+            record.vector("base")?, record.number("radius")?, record.number("height")?,
+            MaterialId::from_index(record.integer("material")?)
+        ).with_caps(record.boolean("capped")?))
+    }
+
+    fn crossings(&self, ray: &Ray) -> Vec<Crossing> {
+        let (p, d) = (ray.origin() - self.base, ray.direction());
+        let h = self.height;
+        // x^2 + z^2 = k^2 (h - y)^2
+        let k = self.radius / h;
+        let k2 = k * k;
+        let below = h - p.y();
+        let mut crossings = Vec::new();
+        let a = d.x() * d.x() + d.z() * d.z() - k2 * d.y() * d.y();
+        let b = p.x() * d.x() + p.z() * d.z() + k2 * below * d.y();
+        let c = p.x() * p.x() + p.z() * p.z() - k2 * below * below;
+        let roots = match quadratic(a, b, c) {
+            Some((t0, t1)) => vec![t0, t1],
+            // parallel to the side, a single crossing
+            None if a.abs() < 1e-12 && b != 0.0 => vec![-c / (2.0 * b)],
+            None => Vec::new()
+        };
+        for t in roots {
+            let q = p + t * d;
+            if (0.0 ..= h).contains(&q.y()) {
+                let (u, tangent) = around_y(q.x(), q.z());
+                let radial = libm::sqrt(q.x() * q.x() + q.z() * q.z());
+                let outward = if radial > 0.0 {
+                    Vec3::new(q.x() / radial, k, q.z() / radial).unit()
+                } else {
+                    Vec3::new(0.0, 1.0, 0.0)
+                };
+                crossings.push(Crossing::new(t, outward, (u, q.y() / h), tangent));
+            }
+        }
+        if self.capped {
+            disk(p, d, 0.0, self.radius, Vec3::new(0.0, -1.0, 0.0), &mut crossings);
+        }
+        crossings
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
+        nearest(self.crossings(ray), ray, interval, self.material)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(upright_box(self.base, self.radius, self.height))
+    }
+
+    fn to_record(&self) -> Option<Record> {
+        Some(Record::new("cone")
+            .with("base", self.base)
+            .with("radius", self.radius)
+            .with("height", self.height)
+            .with("capped", self.capped)
+            .with("material", self.material.index()))
+    }
+
+    // Only closed with its caps.
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        self.capped.then(|| spans(self.crossings(ray), ray, self.material))
+    }
+}
+
+// Torus around the y axis through `center`, the tube of radius `minor`
+// following a circle of radius `major` in the xz plane. u runs around the
+// y axis, v around the tube starting on its inner equator.
+#[derive(Debug)]
+pub struct Torus {
+    center: Point,
+    major: f64,
+    minor: f64,
+    material: MaterialId
+}
+
+impl Torus {
+    pub fn new(center: Point, major: f64, minor: f64, material: MaterialId) -> Self {
+        Self { center, major, minor, material }
+    }
+
+    pub(crate) fn from_record(record: &Record) -> io::Result<Self> {
+        Ok(Self::new(
+            record.vector("center")?, record.number("major")?, record.number("minor")?,
+            MaterialId::from_index(record.integer("material")?)
+        ))
+    }
+
+    fn crossings(&self, ray: &Ray) -> Vec<Crossing> {
+        let (big, small) = (self.major, self.minor);
+        let length = ray.direction().length();
+        let d = ray.direction() / length;
+        // Solve around the point of the line nearest the center to keep the
+        // quartic's coefficients small.
+        let shift = -(ray.origin() - self.center).dot(&d);
+        let p = ray.origin() - self.center + shift * d;
+        let reach = big + small;
+        if p.length_squared() > reach * reach {
+            return Vec::new();
+        }
+        // (|p + s d|^2 + R^2 - r^2)^2 = 4 R^2 ((p + s d)_x^2 + (p + s d)_z^2)
+        let pd = p.dot(&d);
+        let g = p.length_squared() + big * big - small * small;
+        let four = 4.0 * big * big;
+        let a = d.x() * d.x() + d.z() * d.z();
+        let b = p.x() * d.x() + p.z() * d.z();
+        let c = p.x() * p.x() + p.z() * p.z();
+        let coeffs = [
+            g * g - four * c,
+            4.0 * pd * g - 2.0 * four * b,
+            4.0 * pd * pd + 2.0 * g - four * a,
+            4.0 * pd,
+            1.0
+        ];
+        let limit = 2.0 * reach;
+        polynomial_roots(&coeffs, -limit, limit).into_iter().map(|s| {
+            let q = p + s * d;
+            let radial = libm::sqrt(q.x() * q.x() + q.z() * q.z()).max(1e-12);
+            let ring = Vec3::new(q.x() / radial, 0.0, q.z() / radial);
+            let outward = (q - big * ring) / small;
+            let (u, tangent) = around_y(q.x(), q.z());
+            let v = (libm::atan2(q.y(), radial - big) + PI) / (2.0 * PI);
+            Crossing::new((shift + s) / length, outward, (u, v), tangent)
+        }).collect()
+    }
+}
+
+impl Hittable for Torus {
+    fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
+        nearest(self.crossings(ray), ray, interval, self.material)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let (w, h) = (self.major.abs() + self.minor.abs(), self.minor.abs());
+        let r = Vec3::new(w, h, w);
+        Some(Aabb::new(self.center - r, self.center + r).pad(1e-4))
+    }
+
+    fn to_record(&self) -> Option<Record> {
+        Some(Record::new("torus")
+            .with("center", self.center)
+            .with("major", self.major)
+            .with("minor", self.minor)
+            .with("material", self.material.index()))
+    }
+
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        Some(spans(self.crossings(ray), ray, self.material))
+    }
+}
+
+// Points within `radius` of the segment from `a` to `b`. u runs around the
+// segment, v along it from the cap at `a` to the cap at `b`.
+#[derive(Debug)]
+pub struct Capsule {
+    a: Point,
+    b: Point,
+    radius: f64,
+    material: MaterialId
+}
+
+impl Capsule {
+    pub fn new(a: Point, b: Point, radius: f64, material: MaterialId) -> Self {
+        Self { a, b, radius, material }
+    }
+
+    pub(crate) fn from_record(record: &Record) -> io::Result<Self> {
+        Ok(Self::new(
+            record.vector("a")?, record.vector("b")?, record.number("radius")?,
+            MaterialId::from_index(record.integer("material")?)
+        ))
+    }
+
+    fn crossings(&self, ray: &Ray) -> Vec<Crossing> {
+        let axis = self.b - self.a;
+        let length = axis.length();
+        let w = if length > 1e-12 { axis / length } else { Vec3::new(0.0, 1.0, 0.0) };
+        let helper = if w.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let e1 = (helper - helper.dot(&w) * w).unit();
+        let e2 = w.cross(&e1);
+        let r = self.radius;
+        let (p, d) = (ray.origin() - self.a, ray.direction());
+        let mut roots = Vec::new();
+        // side, then the two spheres each on its own side of the segment
+        let (pp, dp) = (p - p.dot(&w) * w, d - d.dot(&w) * w);
+        let side = quadratic(dp.length_squared(), pp.dot(&dp), pp.length_squared() - r * r);
+        if let Some((t0, t1)) = side {
+            roots.extend([t0, t1].into_iter().filter(|t| {
+                (0.0 ..= length).contains(&(p + *t * d).dot(&w))
+            }));
+        }
+        for (end, outside) in [(0.0, -1.0), (length, 1.0)] {
+            let oc = p - end * w;
+            let cap = quadratic(d.length_squared(), oc.dot(&d), oc.length_squared() - r * r);
+            if let Some((t0, t1)) = cap {
+                roots.extend([t0, t1].into_iter().filter(|t| {
+                    (oc + *t * d).dot(&w) * outside > 0.0
+                }));
+            }
+        }
+        let span = length + 2.0 * r;
+        roots.into_iter().map(|t| {
+            let q = p + t * d;
+            let along = q.dot(&w);
+            let outward = (q - along.clamp(0.0, length) * w) / r;
+            let phi = libm::atan2(q.dot(&e2), q.dot(&e1));
+            let radial = libm::cos(phi) * e1 + libm::sin(phi) * e2;
+            let uv = ((phi + PI) / (2.0 * PI), ((along + r) / span).clamp(0.0, 1.0));
+            Crossing::new(t, outward, uv, w.cross(&radial))
+        }).collect()
+    }
+}
+
+impl Hittable for Capsule {
+    fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
+        nearest(self.crossings(ray), ray, interval, self.material)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = Vec3::new(1.0, 1.0, 1.0) * self.radius.abs();
+        Some(Aabb::new(self.a - r, self.a + r).union(Aabb::new(self.b - r, self.b + r)))
+    }
+
+    fn to_record(&self) -> Option<Record> {
+        Some(Record::new("capsule")
+            .with("a", self.a)
+            .with("b", self.b)
+            .with("radius", self.radius)
+            .with("material", self.material.index()))
+    }
+
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        Some(spans(self.crossings(ray), ray, self.material))
+    }
+}
+
+// Solid axis-aligned box between the corners `a` and `b`. Unlike
+// `Quad::cuboid` it encloses a volume, so it can be used in a `Csg`. Each
+// face is mapped flat.
+#[derive(Debug)]
+pub struct Cuboid {
+    min: Point,
+    max: Point,
+    material: MaterialId
+}
+
+impl Cuboid {
+    pub fn new(a: Point, b: Point, material: MaterialId) -> Self {
+        let min = Point::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()));
+        let max = Point::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()));
+        Self { min, max, material }
+    }
+
+    pub(crate) fn from_record(record: &Record) -> io::Result<Self> {
+        Ok(Self::new(
+            record.vector("min")?, record.vector("max")?,
+            MaterialId::from_index(record.integer("material")?)
+        ))
+    }
+
+    fn crossings(&self, ray: &Ray) -> Vec<Crossing> {
+        let (o, d) = (ray.origin(), ray.direction());
+        let (mut near, mut far) = ((f64::NEG_INFINITY, 0), (f64::INFINITY, 0));
+        for axis in 0 .. 3 {
+            let (lo, hi) = (self.min.axis(axis), self.max.axis(axis));
+            if d.axis(axis) == 0.0 {
+                if !(lo ..= hi).contains(&o.axis(axis)) {
+                    return Vec::new();
+                }
+                continue;
+            }
+            let t0 = (lo - o.axis(axis)) / d.axis(axis);
+            let t1 = (hi - o.axis(axis)) / d.axis(axis);
+            if t0.min(t1) > near.0 {
+                near = (t0.min(t1), axis);
             }
+            if t0.max(t1) < far.0 {
+                far = (t0.max(t1), axis);
+            }
+        }
+        if near.0 > far.0 {
+            return Vec::new();
+        }
+        let unit = |axis: usize, length: f64| {
+            let mut v = [0.0; 3];
+            v[axis] = length;
+            Vec3::new(v[0], v[1], v[2])
         };
-        Some(HitRecord::new(point, normal, front, self.material.clone(), time))
+        let size = self.max - self.min;
+        [(near, -1.0), (far, 1.0)].into_iter().map(|((time, axis), side)| {
+            let local = ray.at(time) - self.min;
+            let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+            let uv = (local.axis(a) / size.axis(a), local.axis(b) / size.axis(b));
+            let outward = unit(axis, side * d.axis(axis).signum());
+            Crossing::new(time, outward, uv, unit(a, 1.0))
+        }).collect()
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
+        nearest(self.crossings(ray), ray, interval, self.material)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.min, self.max).pad(1e-4))
+    }
+
+    fn to_record(&self) -> Option<Record> {
+        Some(Record::new("cuboid")
+            .with("min", self.min)
+            .with("max", self.max)
+            .with("material", self.material.index()))
+    }
+
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        Some(spans(self.crossings(ray), ray, self.material))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Union,
+    Intersection,
+    // `a` with `b` cut away
+    Difference
+}
+
+impl Operation {
+    fn inside(self, a: bool, b: bool) -> bool {
+        match self {
+            Self::Union => a || b,
+            Self::Intersection => a && b,
+            Self::Difference => a && !b
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Union => "union",
+            Self::Intersection => "intersection",
+            Self::Difference => "difference"
+        }
+    }
+}
+
+// Object moved by a transform. Nodes place whole objects; this places the
+// operands of a `Csg`, e.g. a cylinder turned to drill along x.
+#[derive(Debug)]
+pub struct Transformed {
+    object: Arc<dyn Hittable>,
+    transform: Transform
+}
+
+impl Transformed {
+    pub fn new(object: impl Hittable + 'static, transform: Transform) -> Self {
+        Self::shared(Arc::new(object), transform)
+    }
+
+    pub fn shared(object: Arc<dyn Hittable>, transform: Transform) -> Self {
+        Self { object, transform }
+    }
+
+    pub(crate) fn from_record(record: &Record) -> io::Result<Self> {
+        Ok(Self::shared(
+            serialize::hittable(record.record("object")?)?,
+            Transform::from_record(record.record("transform")?)?
+        ))
+    }
+}
+
+impl Hittable for Transformed {
+    fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
+        let rec = self.object.hit(&self.transform.inverse().ray(ray), interval)?;
+        Some(to_world(&self.transform, rec))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.object.bounding_box()?.transform(&self.transform))
+    }
+
+    fn to_record(&self) -> Option<Record> {
+        Some(Record::new("transformed")
+            .with("object", self.object.to_record()?)
+            .with("transform", self.transform.to_record()))
+    }
+
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        let spans = self.object.spans(&self.transform.inverse().ray(ray))?;
+        Some(spans.into_iter().map(|span| Span {
+            enter: to_world(&self.transform, span.enter),
+            exit: to_world(&self.transform, span.exit)
+        }).collect())
+    }
+}
+
+// Boolean combination of two solids in the same space, e.g. a lens as the
+// intersection of two spheres. Each surface keeps its operand's material.
+// Both operands must report `spans`, otherwise nothing is hit.
+#[derive(Debug)]
+pub struct Csg {
+    operation: Operation,
+    a: Arc<dyn Hittable>,
+    b: Arc<dyn Hittable>
+}
+
+impl Csg {
+    pub fn new(
+        operation: Operation, a: impl Hittable + 'static, b: impl Hittable + 'static
+    ) -> Self {
+        Self::shared(operation, Arc::new(a), Arc::new(b))
+    }
+
+    pub fn shared(operation: Operation, a: Arc<dyn Hittable>, b: Arc<dyn Hittable>) -> Self {
+        Self { operation, a, b }
+    }
+
+    pub(crate) fn from_record(record: &Record) -> io::Result<Self> {
+        let operation = match record.text("operation")? {
+            "union" => Operation::Union,
+            "intersection" => Operation::Intersection,
+            "difference" => Operation::Difference,
+            other => return Err(invalid(format!("unknown csg operation {}", other)))
+        };
+        Ok(Self::shared(
+            operation,
+            serialize::hittable(record.record("a")?)?,
+            serialize::hittable(record.record("b")?)?
+        ))
+    }
+}
+
+impl Hittable for Csg {
+    fn hit(&self, ray: &Ray, interval: Interval) -> Option<HitRecord> {
+        self.spans(ray)?.into_iter()
+            .flat_map(|span| [span.enter, span.exit])
+            .find(|record| interval.surrounds(record.time()))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        match self.operation {
+            Operation::Union => Some(self.a.bounding_box()?.union(self.b.bounding_box()?)),
+            Operation::Intersection | Operation::Difference => self.a.bounding_box()
+        }
+    }
+
+    fn to_record(&self) -> Option<Record> {
+        Some(Record::new("csg")
+            .with("operation", self.operation.name())
+            .with("a", self.a.to_record()?)
+            .with("b", self.b.to_record()?))
+    }
+
+    // Walks the operands' boundaries in order, keeping those where the
+    // inside of the result changes.
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        let mut events = Vec::new();
+        for (in_a, spans) in [(true, self.a.spans(ray)?), (false, self.b.spans(ray)?)] {
+            for span in spans {
+                events.push((span.enter, in_a, true));
+                events.push((span.exit, in_a, false));
+            }
+        }
+        events.sort_by(|x, y| x.0.time().total_cmp(&y.0.time()));
+        let (mut in_a, mut in_b) = (false, false);
+        let mut enter = None;
+        let mut spans = Vec::new();
+        for (record, from_a, entering) in events {
+            let before = self.operation.inside(in_a, in_b);
+            if from_a { in_a = entering } else { in_b = entering }
+            match (before, self.operation.inside(in_a, in_b)) {
+                (false, true) => enter = Some(record.with_front(true)),
+                (true, false) => if let Some(enter) = enter.take() {
+                    spans.push(Span { enter, exit: record.with_front(false) });
+                }
+                _ => {}
+            }
+        }
+        Some(spans)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn ball(sence: &mut Sence) -> Sphere {
+        let gray = sence.add_material("gray", Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        Sphere::new(Point::new(0.0, 0.0, 0.0), 1.0, gray)
+    }
+
+    #[test]
+    fn graph_edits() {
+        let mut sence = Sence::new();
+        let ball = ball(&mut sence);
+        let group = sence.insert(
+            None, Node::new("group").with_transform(Transform::translate(Vec3::new(0.0, 0.0, -5.0)))
+        );
+        let child = sence.insert(
+            Some(group),
+            Node::new("ball").with_object(ball)
+                .with_transform(Transform::scale(Vec3::new(2.0, 2.0, 2.0)))
+        );
+        let ray = Ray::new(Point::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = sence.hit(&ray, Interval::new(0.001, f64::INFINITY)).unwrap();
+        assert!((hit.time() - 3.0).abs() < 1e-9);
+        assert!((hit.normal() - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-9);
+        assert_eq!(sence.find("ball"), Some(child));
+
+        let hidden = Visibility { camera: false, shadows: true };
+        sence.set_visibility(group, hidden);
+        assert!(sence.hit(&ray.with_primary(true), Interval::new(0.001, f64::INFINITY)).is_none());
+        assert!(sence.hit(&ray, Interval::new(0.001, f64::INFINITY)).is_some());
+
+        sence.remove_named("group");
+        assert!(sence.node(child).is_none());
+        assert!(sence.hit(&ray, Interval::new(0.001, f64::INFINITY)).is_none());
+    }
+
+    #[test]
+    fn materials_are_shared_and_counted() {
+        let mut sence = Sence::new();
+        let ball = ball(&mut sence);
+        let gray = sence.materials().find("gray").unwrap();
+        sence.push(ball);
+        let ray = Ray::new(Point::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = sence.hit(&ray, Interval::new(0.001, f64::INFINITY)).unwrap();
+        assert_eq!(rec.material(), gray);
+
+        let mut sampler = crate::sampler::SamplerKind::default().build(1, 0);
+        assert!(sence.scatter(&ray, &rec, sampler.as_mut()).is_some());
+        sence.materials_mut().set(gray, crate::material::Metal::new(Color::new(1.0, 1.0, 1.0), 0.0));
+        let reflected = sence.scatter(&ray, &rec, sampler.as_mut()).unwrap().0;
+        assert!((reflected.direction().unit() - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-9);
+        assert_eq!(sence.materials().stats(gray).unwrap().hits, 2);
+    }
+
+    #[test]
+    fn quadrics_hit_outside_first() {
+        let m = MaterialId::from_index(0);
+        let interval = Interval::new(0.001, f64::INFINITY);
+        let check = |object: &dyn Hittable, from: Point, to: Vec3, time: f64, normal: Vec3| {
+            let rec = object.hit(&Ray::new(from, to), interval).unwrap();
+            assert!((rec.time() - time).abs() < 1e-6, "{:?} at {}", object, rec.time());
+            assert!((rec.normal() - normal).length() < 1e-6, "{:?}", object);
+            assert!(rec.front());
+        };
+        let origin = Point::new(0.0, 0.0, 0.0);
+        let (x, y) = (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+
+        let cylinder = Cylinder::new(origin, 1.0, 2.0, m);
+        check(&cylinder, Point::new(-3.0, 1.0, 0.0), x, 2.0, -x);
+        check(&cylinder, Point::new(0.5, 5.0, 0.0), -y, 3.0, y);
+        let tube = Cylinder::new(origin, 1.0, 2.0, m).with_caps(false);
+        assert!(tube.hit(&Ray::new(Point::new(0.5, 5.0, 0.0), -y), interval).is_none());
+
+        let cone = Cone::new(origin, 1.0, 2.0, m);
+        check(&cone, Point::new(-3.0, 1.0, 0.0), x, 2.5, Vec3::new(-1.0, 0.5, 0.0).unit());
+        check(&cone, Point::new(0.0, -5.0, 0.0), y, 5.0, -y);
+
+        let torus = Torus::new(origin, 2.0, 0.5, m);
+        check(&torus, Point::new(-5.0, 0.0, 0.0), x, 2.5, -x);
+        check(&torus, Point::new(2.0, 5.0, 0.0), -y, 4.5, y);
+        assert!(torus.hit(&Ray::new(Point::new(0.0, 5.0, 0.0), -y), interval).is_none());
+
+        let capsule = Capsule::new(origin, Point::new(0.0, 2.0, 0.0), 0.5, m);
+        check(&capsule, Point::new(-3.0, 1.0, 0.0), x, 2.5, -x);
+        check(&capsule, Point::new(0.0, 5.0, 0.0), -y, 2.5, y);
+        check(&capsule, Point::new(0.0, -5.0, 0.0), y, 4.5, -y);
+
+        // u is 0 just past the seam on -x and 1 just before it, v runs up
+        let uv = |object: &dyn Hittable, from: Point, to: Vec3| {
+            object.hit(&Ray::new(from, to), interval).unwrap().uv()
+        };
+        let near = |(u, v): (f64, f64), (eu, ev): (f64, f64)| {
+            assert!((u - eu).abs() < 1e-2 && (v - ev).abs() < 1e-2, "{:?}", (u, v));
+        };
+        near(uv(&cylinder, Point::new(-3.0, 1.0, 1e-4), x), (0.0, 0.5));
+        near(uv(&cylinder, Point::new(-3.0, 1.0, -1e-4), x), (1.0, 0.5));
+        near(uv(&cylinder, Point::new(3.0, 0.5, 0.0), -x), (0.5, 0.25));
+        near(uv(&cylinder, Point::new(0.0, 1.5, -3.0), Vec3::new(0.0, 0.0, 1.0)), (0.75, 0.75));
+        // the torus' v seam is its inner equator, the outer one is halfway
+        near(uv(&torus, Point::new(0.0, 1e-4, 0.0), x), (0.5, 1.0));
+        near(uv(&torus, Point::new(0.0, -1e-4, 0.0), x), (0.5, 0.0));
+        near(uv(&torus, Point::new(-5.0, 0.0, 0.0), x), (0.0, 0.5));
+        near(uv(&torus, Point::new(2.0, 5.0, 0.0), -y), (0.5, 0.75));
+
+        // every point hit lies in the bounding box, also away from the origin
+        let base = Point::new(1.0, -2.0, 3.0);
+        let shapes: [Box<dyn Hittable>; 8] = [
+            Box::new(cylinder), Box::new(cone), Box::new(torus), Box::new(capsule),
+            Box::new(Cylinder::new(base, 0.5, 3.0, m)),
+            Box::new(Cone::new(base, 2.0, 0.5, m)),
+            Box::new(Torus::new(base, 1.0, 0.25, m)),
+            Box::new(Capsule::new(base, Point::new(-1.0, 0.0, 2.0), 0.75, m))
+        ];
+        for shape in &shapes {
+            let bounds = shape.bounding_box().unwrap();
+            let (low, size) = (bounds.min(), bounds.max() - bounds.min());
+            let mut hits = 0;
+            for i in 0 .. 512 {
+                // Fibonacci directions aimed at spread points of the box
+                let z = 1.0 - (2 * i + 1) as f64 / 512.0;
+                let angle = i as f64 * 2.399963;
+                let side = libm::sqrt(1.0 - z * z);
+                let out = Vec3::new(side * libm::cos(angle), z, side * libm::sin(angle));
+                let frac = |k: f64| (i as f64 * k).fract();
+                let target = low + Vec3::new(
+                    frac(0.618) * size.x(), frac(0.414) * size.y(), frac(0.732) * size.z()
+                );
+                let ray = Ray::new(target + 20.0 * out, -out);
+                let Some(rec) = shape.hit(&ray, interval) else { continue };
+                hits += 1;
+                let p = rec.point();
+                for a in 0 .. 3 {
+                    let (lo, hi) = (bounds.min().axis(a), bounds.max().axis(a));
+                    assert!((lo ..= hi).contains(&p.axis(a)), "{:?} outside at {:?}", shape, p);
+                }
+            }
+            assert!(hits > 100, "{:?} only hit {} times", shape, hits);
+        }
+    }
+
+    #[test]
+    fn csg_lens_and_drilled_block() {
+        let m = MaterialId::from_index(0);
+        let interval = Interval::new(0.001, f64::INFINITY);
+        let (x, z) = (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+
+        let lens = Csg::new(
+            Operation::Intersection,
+            Sphere::new(Point::new(0.0, 0.0, -1.5), 2.0, m),
+            Sphere::new(Point::new(0.0, 0.0, 1.5), 2.0, m)
+        );
+        let rec = lens.hit(&Ray::new(Point::new(0.0, 0.0, 5.0), -z), interval).unwrap();
+        assert!((rec.time() - 4.5).abs() < 1e-9 && rec.front());
+        assert!((rec.normal() - z).length() < 1e-9);
+        assert!(lens.hit(&Ray::new(Point::new(1.5, 0.0, 5.0), -z), interval).is_none());
+
+        let block = Csg::new(
+            Operation::Difference,
+            Cuboid::new(Point::new(-1.0, -1.0, -1.0), Point::new(1.0, 1.0, 1.0), m),
+            Cylinder::new(Point::new(0.0, -2.0, 0.0), 0.5, 4.0, m)
+        );
+        let down = Ray::new(Point::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(block.hit(&down, interval).is_none());
+        let across = Ray::new(Point::new(-5.0, 0.0, 0.0), x);
+        let spans = block.spans(&across).unwrap();
+        let times: Vec<f64> = spans.iter()
+            .flat_map(|span| [span.enter.time(), span.exit.time()])
+            .collect();
+        assert_eq!(times.len(), 4);
+        for (t, expected) in times.into_iter().zip([4.0, 4.5, 5.5, 6.0]) {
+            assert!((t - expected).abs() < 1e-9);
+        }
+        // from inside the hole the wall is entered
+        let rec = block.hit(&Ray::new(Point::new(0.0, 0.0, 0.0), x), interval).unwrap();
+        assert!((rec.time() - 0.5).abs() < 1e-9 && rec.front());
+        assert!((rec.normal() + x).length() < 1e-9);
+
+        // the same hole drilled along x, by turning the cylinder over
+        let turned = Transform::rotate(Vec3::new(0.0, 0.0, 1.0), 90.0);
+        let drilled = Csg::new(
+            Operation::Difference,
+            Cuboid::new(Point::new(-1.0, -1.0, -1.0), Point::new(1.0, 1.0, 1.0), m),
+            Transformed::new(Cylinder::new(Point::new(0.0, -2.0, 0.0), 0.5, 4.0, m), turned)
+        );
+        assert!(drilled.hit(&across, interval).is_none());
+        let down = Ray::new(Point::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let spans = drilled.spans(&down).unwrap();
+        let times: Vec<f64> = spans.iter()
+            .flat_map(|span| [span.enter.time(), span.exit.time()])
+            .collect();
+        assert_eq!(times.len(), 4);
+        for (t, expected) in times.into_iter().zip([4.0, 4.5, 5.5, 6.0]) {
+            assert!((t - expected).abs() < 1e-9, "{}", t);
+        }
+        // the hole's wall, seen from inside it, faces the axis
+        let rec = drilled.hit(&Ray::new(Point::new(0.0, 0.0, 0.0), -z), interval).unwrap();
+        assert!((rec.time() - 0.5).abs() < 1e-9 && rec.front());
+        assert!((rec.normal() - z).length() < 1e-9);
+        let bounds = drilled.bounding_box().unwrap();
+        assert!(bounds.max().x() < 1.1);
+    }
+
+    #[test]
+    fn quad_hits_and_uvs() {
+        let m = MaterialId::from_index(0);
+        let interval = Interval::new(0.001, f64::INFINITY);
+        let z = Vec3::new(0.0, 0.0, 1.0);
+        let (u, v) = (Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let quad = Quad::new(Point::new(1.0, 0.0, 0.0), u, v, m);
+
+        let rec = quad.hit(&Ray::new(Point::new(1.5, 0.75, 3.0), -z), interval).unwrap();
+        assert!((rec.time() - 3.0).abs() < 1e-9 && rec.front());
+        assert!((rec.normal() - z).length() < 1e-9);
+        let (u, v) = rec.uv();
+        assert!((u - 0.25).abs() < 1e-9 && (v - 0.75).abs() < 1e-9);
+        // from behind the normal faces the ray
+        let rec = quad.hit(&Ray::new(Point::new(2.5, 0.5, -2.0), z * 2.0), interval).unwrap();
+        assert!((rec.time() - 1.0).abs() < 1e-9 && !rec.front());
+        assert!((rec.normal() + z).length() < 1e-9);
+        assert!((rec.uv().0 - 0.75).abs() < 1e-9);
+
+        for (x, y) in [(0.9, 0.5), (3.1, 0.5), (2.0, 1.1), (2.0, -0.1)] {
+            assert!(quad.hit(&Ray::new(Point::new(x, y, 3.0), -z), interval).is_none());
+        }
+        // parallel to the plane, and behind the ray origin
+        let along = Ray::new(Point::new(0.0, 0.5, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(quad.hit(&along, interval).is_none());
+        assert!(quad.hit(&Ray::new(Point::new(1.5, 0.5, 3.0), z), interval).is_none());
+    }
+
+    #[test]
+    fn medium_free_paths_are_exponential() {
+        let m = MaterialId::from_index(0);
+        let interval = Interval::new(0.001, f64::INFINITY);
+        let center = Point::new(0.0, 0.0, 0.0);
+        let boundary: Arc<dyn Hittable> = Arc::new(Sphere::new(center, 100.0, m));
+        let density = 2.0;
+        let medium = ConstantMedium::shared(boundary.clone(), density, m);
+        let n = 4096;
+        let (mut sum, mut beyond_mean) = (0.0, 0);
+        let mut distances = Vec::new();
+        for i in 0 .. n {
+            let origin = Point::new((i % 64) as f64 / 128.0, (i / 64) as f64 / 128.0, -200.0);
+            // the path is measured in distance, not ray time
+            let ray = Ray::new(origin, Vec3::new(0.0, 0.0, (1 + i % 3) as f64));
+            let rec = medium.hit(&ray, interval).unwrap();
+            assert_eq!(medium.hit(&ray, interval).unwrap().time(), rec.time());
+            let enter = boundary.hit(&ray, interval).unwrap().time();
+            let distance = (rec.time() - enter) * ray.direction().length();
+            sum += distance;
+            beyond_mean += (distance > 1.0 / density) as u32;
+            distances.push(distance);
+        }
+        // Kolmogorov-Smirnov against 1 - e^(-density x), 1% critical value
+        distances.sort_by(f64::total_cmp);
+        let gap = distances.iter().enumerate().map(|(i, &x)| {
+            let cdf = 1.0 - libm::exp(-density * x);
+            (cdf - i as f64 / n as f64).max((i + 1) as f64 / n as f64 - cdf)
+        }).fold(0.0, f64::max);
+        assert!(gap < 1.63 / (n as f64).sqrt(), "KS distance {}", gap);
+        let mean = sum / n as f64;
+        assert!((mean * density - 1.0).abs() < 0.05, "mean {}", mean);
+        let survival = beyond_mean as f64 / n as f64;
+        assert!((survival - libm::exp(-1.0)).abs() < 0.02, "{}", survival);
+
+        // through a unit ball about 1 - e^-2 of the rays scatter
+        let ball = ConstantMedium::new(Sphere::new(center, 1.0, m), 1.0, m);
+        let hits = (0 .. n).filter(|i| {
+            let origin = Point::new((i % 64) as f64 / 1024.0, (i / 64) as f64 / 1024.0, -5.0);
+            ball.hit(&Ray::new(origin, Vec3::new(0.0, 0.0, 1.0)), interval).is_some()
+        }).count();
+        let expected = 1.0 - libm::exp(-2.0);
+        assert!((hits as f64 / n as f64 - expected).abs() < 0.02, "{}", hits);
     }
 }
@@ -0,0 +1,556 @@
+// The crate's own scene files.
+//
+// A file is a header line followed by records, one per line. A record is a
+// kind and named fields, `sphere { center [0 1 0] radius 1 material 2 }`;
+// values are numbers, `true`/`false`, quoted strings, lists in brackets or
+// nested records. Numbers are written with enough digits to read back
+// exactly, so a loaded scene renders the same as the one saved.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::vector::Vec3;
+use super::camera::Camera;
+use super::sence::{
+    Capsule, Cone, ConstantMedium, Csg, Cuboid, Cylinder, Hittable, Quad, Sence, Sphere, Torus,
+    Transformed
+};
+use super::material::{
+    Dielectric, DiffuseLight, Isotropic, Lambertian, Material, Metal, Pbr
+};
+use super::texture::{
+    CheckerTexture, ImageTexture, NoiseTexture, SolidColor, Texture, VertexColor
+};
+use super::projection::{Equirectangular, Fisheye, Orthographic, Perspective, Projection};
+use super::mesh::TriangleMesh;
+use super::utils;
+
+const HEADER: &str = "rtl-scene 1";
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    Bool(bool),
+    Number(f64),
+    Text(String),
+    List(Vec<Value>),
+    Record(Record)
+}
+
+#[derive(Debug, Clone)]
+pub struct Record {
+    kind: String,
+    fields: Vec<(String, Value)>
+}
+
+pub(crate) fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+// Error for scene parts that have no record form.
+pub(crate) fn unsupported(what: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, format!("{} can't be saved", what.into()))
+}
+
+impl Value {
+    pub fn as_number(&self) -> io::Result<f64> {
+        match self {
+            Self::Number(v) => Ok(*v),
+            other => Err(invalid(format!("expected a number, got {:?}", other)))
+        }
+    }
+
+    pub fn as_record(&self) -> io::Result<&Record> {
+        match self {
+            Self::Record(r) => Ok(r),
+            other => Err(invalid(format!("expected a record, got {:?}", other)))
+        }
+    }
+
+    pub fn as_list(&self) -> io::Result<&[Value]> {
+        match self {
+            Self::List(values) => Ok(values),
+            other => Err(invalid(format!("expected a list, got {:?}", other)))
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(v: bool) -> Self {
+        Self::Bool(v)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Self {
+        Self::Number(v)
+    }
+}
+
+impl From<u32> for Value {
+    fn from(v: u32) -> Self {
+        Self::Number(v as f64)
+    }
+}
+
+impl From<usize> for Value {
+    fn from(v: usize) -> Self {
+        Self::Number(v as f64)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Self {
+        Self::Text(v.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Self::Text(v)
+    }
+}
+
+impl From<Vec3> for Value {
+    fn from(v: Vec3) -> Self {
+        Self::List((0 .. 3).map(|i| Self::Number(v.axis(i))).collect())
+    }
+}
+
+impl From<Vec<f64>> for Value {
+    fn from(v: Vec<f64>) -> Self {
+        Self::List(v.into_iter().map(Self::Number).collect())
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(v: Vec<Value>) -> Self {
+        Self::List(v)
+    }
+}
+
+impl From<Record> for Value {
+    fn from(v: Record) -> Self {
+        Self::Record(v)
+    }
+}
+
+impl Record {
+    pub fn new(kind: impl Into<String>) -> Self {
+        Self { kind: kind.into(), fields: Vec::new() }
+    }
+
+    pub fn with(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.fields.push((name.to_string(), value.into()));
+        self
+    }
+
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.fields.iter().find(|(n, _)| n == name).map(|(_, v)| v)
+    }
+
+    fn field(&self, name: &str) -> io::Result<&Value> {
+        self.get(name).ok_or_else(|| invalid(format!("{} without {}", self.kind, name)))
+    }
+
+    pub fn number(&self, name: &str) -> io::Result<f64> {
+        self.field(name)?.as_number()
+    }
+
+    pub fn integer(&self, name: &str) -> io::Result<usize> {
+        let v = self.number(name)?;
+        if v < 0.0 || v.fract() != 0.0 {
+            return Err(invalid(format!("{} of {} isn't an index", name, self.kind)));
+        }
+        Ok(v as usize)
+    }
+
+    pub fn boolean(&self, name: &str) -> io::Result<bool> {
+        match self.field(name)? {
+            Value::Bool(v) => Ok(*v),
+            other => Err(invalid(format!("expected a bool, got {:?}", other)))
+        }
+    }
+
+    pub fn text(&self, name: &str) -> io::Result<&str> {
+        match self.field(name)? {
+            Value::Text(v) => Ok(v),
+            other => Err(invalid(format!("expected a string, got {:?}", other)))
+        }
+    }
+
+    pub fn list(&self, name: &str) -> io::Result<&[Value]> {
+        self.field(name)?.as_list()
+    }
+
+    pub fn numbers(&self, name: &str) -> io::Result<Vec<f64>> {
+        self.list(name)?.iter().map(Value::as_number).collect()
+    }
+
+    pub fn vector(&self, name: &str) -> io::Result<Vec3> {
+        match self.numbers(name)?.as_slice() {
+            [x, y, z] => Ok(Vec3::new(*x, *y, *z)),
+            _ => Err(invalid(format!("{} of {} isn't a vector", name, self.kind)))
+        }
+    }
+
+    pub fn record(&self, name: &str) -> io::Result<&Record> {
+        self.field(name)?.as_record()
+    }
+}
+
+// Objects, materials, textures and projections by record kind.
+
+pub(crate) fn hittable(record: &Record) -> io::Result<Arc<dyn Hittable>> {
+    Ok(match record.kind() {
+        "sphere" => Arc::new(Sphere::from_record(record)?),
+        "quad" => Arc::new(Quad::from_record(record)?),
+        "medium" => Arc::new(ConstantMedium::from_record(record)?),
+        "cylinder" => Arc::new(Cylinder::from_record(record)?),
+        "cone" => Arc::new(Cone::from_record(record)?),
+        "torus" => Arc::new(Torus::from_record(record)?),
+        "capsule" => Arc::new(Capsule::from_record(record)?),
+        "cuboid" => Arc::new(Cuboid::from_record(record)?),
+        "csg" => Arc::new(Csg::from_record(record)?),
+        "transformed" => Arc::new(Transformed::from_record(record)?),
+        "mesh" => Arc::new(TriangleMesh::from_record(record)?),
+        other => return Err(invalid(format!("unknown object {}", other)))
+    })
+}
+
+// The material indices an object record and the records nested in it
+// refer to, so a loaded scene can check them against its library.
+pub(crate) fn material_indices(record: &Record, out: &mut Vec<usize>) -> io::Result<()> {
+    fn walk(value: &Value, out: &mut Vec<usize>) -> io::Result<()> {
+        match value {
+            Value::Record(record) => material_indices(record, out),
+            Value::List(values) => values.iter().try_for_each(|value| walk(value, out)),
+            _ => Ok(())
+        }
+    }
+    for (name, value) in &record.fields {
+        match value {
+            Value::Number(_) if name == "material" => out.push(record.integer(name)?),
+            value => walk(value, out)?
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn material(record: &Record) -> io::Result<Arc<dyn Material>> {
+    Ok(match record.kind() {
+        "lambertian" => Arc::new(Lambertian::from_record(record)?),
+        "diffuse_light" => Arc::new(DiffuseLight::from_record(record)?),
+        "isotropic" => Arc::new(Isotropic::from_record(record)?),
+        "metal" => Arc::new(Metal::from_record(record)?),
+        "dielectric" => Arc::new(Dielectric::from_record(record)?),
+        "pbr" => Arc::new(Pbr::from_record(record)?),
+        other => return Err(invalid(format!("unknown material {}", other)))
+    })
+}
+
+pub(crate) fn texture(record: &Record) -> io::Result<Arc<dyn Texture>> {
+    Ok(match record.kind() {
+        "solid" => Arc::new(SolidColor(record.vector("color")?)),
+        "vertex_color" => Arc::new(VertexColor),
+        "image" => Arc::new(ImageTexture::from_record(record)?),
+        "checker" => Arc::new(CheckerTexture::from_record(record)?),
+        "noise" => Arc::new(NoiseTexture::from_record(record)?),
+        other => return Err(invalid(format!("unknown texture {}", other)))
+    })
+}
+
+pub(crate) fn projection(record: &Record) -> io::Result<Arc<dyn Projection>> {
+    Ok(match record.kind() {
+        "perspective" => Arc::new(Perspective),
+        "orthographic" => Arc::new(Orthographic::from_record(record)?),
+        "fisheye" => Arc::new(Fisheye::from_record(record)?),
+        "equirectangular" => Arc::new(Equirectangular),
+        other => return Err(invalid(format!("unknown projection {}", other)))
+    })
+}
+
+fn write_value(out: &mut String, value: &Value) {
+    match value {
+        Value::Bool(v) => out.push_str(if *v { "true" } else { "false" }),
+        // Debug formatting is the shortest text that parses back exactly
+        Value::Number(v) => out.push_str(&format!("{:?}", v)),
+        Value::Text(v) => {
+            out.push('"');
+            for c in v.chars() {
+                if c == '"' || c == '\\' {
+                    out.push('\\');
+                }
+                out.push(c);
+            }
+            out.push('"');
+        }
+        Value::List(values) => {
+            out.push('[');
+            for (i, v) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push(' ');
+                }
+                write_value(out, v);
+            }
+            out.push(']');
+        }
+        Value::Record(record) => write_record(out, record)
+    }
+}
+
+// Hash of the record's text, for checkpoint fingerprints.
+pub(crate) fn fingerprint(record: &Record) -> u64 {
+    let mut out = String::new();
+    write_record(&mut out, record);
+    utils::fnv1a(out.as_bytes())
+}
+
+fn write_record(out: &mut String, record: &Record) {
+    out.push_str(&record.kind);
+    out.push_str(" {");
+    for (name, value) in &record.fields {
+        out.push(' ');
+        out.push_str(name);
+        out.push(' ');
+        write_value(out, value);
+    }
+    out.push_str(" }");
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Open(char),
+    Close(char)
+}
+
+fn tokenize(text: &str) -> io::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '[' | '{' => tokens.push(Token::Open(c)),
+            ']' | '}' => tokens.push(Token::Close(c)),
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => s.extend(chars.next()),
+                        Some(c) => s.push(c),
+                        None => return Err(invalid("unterminated string"))
+                    }
+                }
+                tokens.push(Token::Text(s));
+            }
+            c => {
+                let mut word = String::from(c);
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "[]{}\"".contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: std::vec::IntoIter<Token>
+}
+
+impl Parser {
+    fn next(&mut self) -> io::Result<Token> {
+        self.tokens.next().ok_or_else(|| invalid("unexpected end of file"))
+    }
+
+    fn value(&mut self, token: Token) -> io::Result<Value> {
+        match token {
+            Token::Text(s) => Ok(Value::Text(s)),
+            Token::Open('[') => {
+                let mut values = Vec::new();
+                loop {
+                    match self.next()? {
+                        Token::Close(']') => return Ok(Value::List(values)),
+                        token => values.push(self.value(token)?)
+                    }
+                }
+            }
+            Token::Word(w) => match w.as_str() {
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                _ => match w.parse() {
+                    Ok(v) => Ok(Value::Number(v)),
+                    Err(_) => Ok(Value::Record(self.record(w)?))
+                }
+            },
+            other => Err(invalid(format!("unexpected {:?}", other)))
+        }
+    }
+
+    fn record(&mut self, kind: String) -> io::Result<Record> {
+        if self.next()? != Token::Open('{') {
+            return Err(invalid(format!("expected {{ after {}", kind)));
+        }
+        let mut record = Record::new(kind);
+        loop {
+            match self.next()? {
+                Token::Close('}') => return Ok(record),
+                Token::Word(name) => {
+                    let token = self.next()?;
+                    let value = self.value(token)?;
+                    record.fields.push((name, value));
+                }
+                other => return Err(invalid(format!("expected a field name, got {:?}", other)))
+            }
+        }
+    }
+}
+
+// Writes the scene and optionally the camera. Fails for objects, materials
+// or textures that have no record form, e.g. user-defined ones.
+pub fn to_text(sence: &Sence, camera: Option<&Camera>) -> io::Result<String> {
+    let mut records = Vec::new();
+    if let Some(camera) = camera {
+        records.push(camera.to_record()?);
+    }
+    records.extend(sence.to_records()?);
+    let mut out = String::from(HEADER);
+    out.push('\n');
+    for record in &records {
+        write_record(&mut out, record);
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+pub fn from_text(text: &str) -> io::Result<(Sence, Option<Camera>)> {
+    let text = text.strip_prefix(HEADER).ok_or_else(|| invalid("not a scene file"))?;
+    let mut parser = Parser { tokens: tokenize(text)?.into_iter() };
+    let mut records = Vec::new();
+    while let Some(token) = parser.tokens.next() {
+        let Token::Word(kind) = token else {
+            return Err(invalid(format!("expected a record, got {:?}", token)));
+        };
+        records.push(parser.record(kind)?);
+    }
+    let (cameras, records): (Vec<_>, Vec<_>) =
+        records.into_iter().partition(|record| record.kind() == "camera");
+    let camera = cameras.first().map(Camera::from_record).transpose()?;
+    Ok((Sence::from_records(&records)?, camera))
+}
+
+pub fn save(path: impl AsRef<Path>, sence: &Sence, camera: Option<&Camera>) -> io::Result<()> {
+    fs::write(path, to_text(sence, camera)?)
+}
+
+pub fn load(path: impl AsRef<Path>) -> io::Result<(Sence, Option<Camera>)> {
+    from_text(&fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector::{Color, Point};
+    use crate::sence::Node;
+    use crate::transform::Transform;
+    use crate::material::Ior;
+    use crate::mesh::MeshData;
+    use crate::texture::Image;
+    use crate::lens::{Aperture, Lens};
+    use crate::sampler::SamplerKind;
+
+    fn scene() -> (Sence, Camera) {
+        let mut sence = Sence::new();
+        let ground = sence.add_material("ground", Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let glass = sence.add_material("glass \"bk7\"", Dielectric::with_ior(Ior::BK7));
+        let light = sence.add_material("light", DiffuseLight::new(Color::new(4.0, 4.0, 4.0)));
+        let checker = Image::new(2, 1, vec![Color::new(0.9, 0.1, 0.1), Color::new(0.1, 0.1, 0.9)]);
+        let checker = ImageTexture::new(Arc::new(checker));
+        let pbr = sence.add_material("pbr", Pbr::new(checker, 0.3, 0.4));
+        sence.add_material("metal", Metal::new(Color::new(0.7, 0.6, 0.5), 0.1));
+
+        sence.push(Sphere::new(Point::new(0.0, -1000.0, 0.0), 1000.0, ground));
+        sence.push(Sphere::new(Point::new(0.0, 1.0, 0.0), 1.0, glass));
+        sence.push(Sphere::new(Point::new(0.0, 6.0, 0.0), 2.0, light));
+        let quad = MeshData {
+            positions: vec![
+                Point::new(-1.0, 0.0, 0.0), Point::new(1.0, 0.0, 0.0),
+                Point::new(1.0, 2.0, 0.0), Point::new(-1.0, 2.0, 0.0)
+            ],
+            uvs: vec![(0.0, 1.0), (1.0, 1.0), (1.0, 0.0), (0.0, 0.0)],
+            indices: vec![[0, 1, 2], [0, 2, 3]],
+            ..MeshData::default()
+        };
+        let quad: Arc<dyn Hittable> = Arc::new(TriangleMesh::new(quad, pbr));
+        let turn = Transform::rotate(Vec3::new(0.0, 1.0, 0.0), 30.0);
+        let group = sence.insert(None, Node::new("panels").with_transform(turn));
+        for x in [-2.5, 2.5] {
+            let placed = Transform::translate(Vec3::new(x, 0.0, -1.0))
+                * Transform::scale(Vec3::new(0.7, 1.0, 1.0));
+            let panel = Node::new("panel").with_shared(quad.clone()).with_transform(placed);
+            sence.insert(Some(group), panel);
+        }
+
+        let lens = Lens::new(35.0, 2.0)
+            .with_focus_distance(8.0)
+            .with_aperture(Aperture::Polygon { blades: 6, rotation: 15.0 });
+        let camera = Camera::physical(
+            Point::new(1.0, 2.0, 8.0), Vec3::new(-0.1, -0.2, -1.0), Vec3::new(0.0, 1.0, 0.0),
+            8, lens, 24, 16
+        );
+        (sence, camera)
+    }
+
+    fn render(sence: &Sence, camera: &Camera) -> Vec<u64> {
+        let mut sampler = SamplerKind::Independent.build(1, 7);
+        (0 .. 4).flat_map(|index| camera.render(sence, sampler.as_mut(), index))
+            .flat_map(|p| [p.x(), p.y(), p.z()])
+            .map(f64::to_bits)
+            .collect()
+    }
+
+    #[test]
+    fn round_trip_renders_identically() {
+        let (sence, camera) = scene();
+        let text = to_text(&sence, Some(&camera)).unwrap();
+        let (loaded, loaded_camera) = from_text(&text).unwrap();
+        let loaded_camera = loaded_camera.unwrap();
+
+        assert_eq!(to_text(&loaded, Some(&loaded_camera)).unwrap(), text);
+        // the two panels share one mesh
+        assert_eq!(text.lines().filter(|l| l.starts_with("object")).count(), 4);
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", sence));
+        assert_eq!(loaded.nodes().filter(|(_, n)| n.name() == "panel").count(), 2);
+        assert_eq!(render(&loaded, &loaded_camera), render(&sence, &camera));
+    }
+
+    #[test]
+    fn unknown_materials_fail() {
+        let (sence, _) = scene();
+        let text = to_text(&sence, None).unwrap();
+        let count = sence.materials().len();
+        let csg = format!(
+            "object {{ value csg {{ operation \"union\" a sphere {{ center [0 0 0] radius 1 \
+             material 0 }} b sphere {{ center [1 0 0] radius 1 material {} }} }} }}\n",
+            count
+        );
+        let error = from_text(&(text.clone() + &csg)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), format!("unknown material {}", count));
+        let fixed = csg.replace(&format!("material {} ", count), "material 1 ");
+        from_text(&(text + &fixed)).unwrap();
+    }
+}
@@ -0,0 +1,285 @@
+use std::ops::{Add, Mul, MulAssign};
+use std::sync::OnceLock;
+
+use super::vector::Color;
+
+pub const LAMBDA_MIN: f64 = 380.0;
+pub const LAMBDA_MAX: f64 = 720.0;
+pub const N_SAMPLES: usize = 4;
+
+const CIE_Y_INTEGRAL: f64 = 106.856895;
+
+const XYZ_TO_SRGB: [[f64; 3]; 3] = [
+    [3.2404542, -1.5371385, -0.4985314],
+    [-0.9692660, 1.8760108, 0.0415560],
+    [0.0556434, -0.2040259, 1.0572252]
+];
+
+// Smits' RGB to reflectance tables, 10 bins over [LAMBDA_MIN, LAMBDA_MAX].
+const SMITS_WHITE: [f64; 10] =
+    [1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000];
+const SMITS_CYAN: [f64; 10] =
+    [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000];
+const SMITS_MAGENTA: [f64; 10] =
+    [1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959];
+const SMITS_YELLOW: [f64; 10] =
+    [0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840];
+const SMITS_RED: [f64; 10] =
+    [0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149];
+const SMITS_GREEN: [f64; 10] =
+    [0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025];
+const SMITS_BLUE: [f64; 10] =
+    [1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496];
+
+fn gaussian(x: f64, mu: f64, sigma1: f64, sigma2: f64) -> f64 {
+    let t = (x - mu) / if x < mu { sigma1 } else { sigma2 };
+    libm::exp(-0.5 * t * t)
+}
+
+// Multi-lobe fit of the CIE 1931 color matching functions (Wyman et al. 2013).
+pub fn cie_xyz(lambda: f64) -> (f64, f64, f64) {
+    let x = 1.056 * gaussian(lambda, 599.8, 37.9, 31.0)
+        + 0.362 * gaussian(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * gaussian(lambda, 501.1, 20.4, 26.2);
+    let y = 0.821 * gaussian(lambda, 568.8, 46.9, 40.5)
+        + 0.286 * gaussian(lambda, 530.9, 16.3, 31.1);
+    let z = 1.217 * gaussian(lambda, 437.0, 11.8, 36.0)
+        + 0.681 * gaussian(lambda, 459.0, 26.0, 13.8);
+    (x, y, z)
+}
+
+pub fn xyz_to_linear_srgb(x: f64, y: f64, z: f64) -> Color {
+    let m = &XYZ_TO_SRGB;
+    Color::new(
+        m[0][0] * x + m[0][1] * y + m[0][2] * z,
+        m[1][0] * x + m[1][1] * y + m[1][2] * z,
+        m[2][0] * x + m[2][1] * y + m[2][2] * z
+    )
+}
+
+// RGB response of a constant unit spectrum over the sampled range. Results
+// are divided by it so that white stays white despite the equal-energy
+// illuminant and the truncated wavelength range.
+fn white_balance() -> Color {
+    static WHITE: OnceLock<Color> = OnceLock::new();
+    *WHITE.get_or_init(|| {
+        let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+        let steps = 1000;
+        let dl = (LAMBDA_MAX - LAMBDA_MIN) / steps as f64;
+        for i in 0 .. steps {
+            let (cx, cy, cz) = cie_xyz(LAMBDA_MIN + (i as f64 + 0.5) * dl);
+            x += cx * dl;
+            y += cy * dl;
+            z += cz * dl;
+        }
+        xyz_to_linear_srgb(x, y, z) / CIE_Y_INTEGRAL
+    })
+}
+
+// Hero wavelength sampling: one wavelength is drawn uniformly and the
+// others are spread evenly across the visible range from it.
+#[derive(Debug, Clone, Copy)]
+pub struct SampledWavelengths {
+    lambda: [f64; N_SAMPLES],
+    pdf: [f64; N_SAMPLES]
+}
+
+impl SampledWavelengths {
+    pub fn sample_uniform(u: f64) -> Self {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let delta = range / N_SAMPLES as f64;
+        let mut lambda = [0.0; N_SAMPLES];
+        lambda[0] = LAMBDA_MIN + u * range;
+        for i in 1 .. N_SAMPLES {
+            lambda[i] = lambda[i - 1] + delta;
+            if lambda[i] > LAMBDA_MAX {
+                lambda[i] -= range;
+            }
+        }
+        Self { lambda, pdf: [1.0 / range; N_SAMPLES] }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    pub fn get(&self, index: usize) -> f64 {
+        self.lambda[index]
+    }
+
+    pub fn secondary_terminated(&self) -> bool {
+        self.pdf[1..].iter().all(|pdf| *pdf == 0.0)
+    }
+
+    // Called when an interaction depends on wavelength (e.g. dispersion),
+    // after which only the hero wavelength stays valid.
+    pub fn terminate_secondary(&mut self) {
+        if self.secondary_terminated() {
+            return;
+        }
+        for pdf in &mut self.pdf[1..] {
+            *pdf = 0.0;
+        }
+        self.pdf[0] /= N_SAMPLES as f64;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SampledSpectrum {
+    values: [f64; N_SAMPLES]
+}
+
+impl SampledSpectrum {
+    pub fn new(values: [f64; N_SAMPLES]) -> Self {
+        Self { values }
+    }
+
+    pub fn constant(value: f64) -> Self {
+        Self { values: [value; N_SAMPLES] }
+    }
+
+    pub fn get(&self, index: usize) -> f64 {
+        self.values[index]
+    }
+
+    // Smits' upsampling of a linear RGB triple.
+    pub fn from_rgb(rgb: Color, lambda: &SampledWavelengths) -> Self {
+        let (r, g, b) = (rgb.x(), rgb.y(), rgb.z());
+        let mut values = [0.0; N_SAMPLES];
+        for (value, l) in values.iter_mut().zip(lambda.lambda) {
+            let bin = (((l - LAMBDA_MIN) / (LAMBDA_MAX - LAMBDA_MIN) * 10.0) as usize).min(9);
+            *value = if r <= g && r <= b {
+                r * SMITS_WHITE[bin] + if g <= b {
+                    (g - r) * SMITS_CYAN[bin] + (b - g) * SMITS_BLUE[bin]
+                } else {
+                    (b - r) * SMITS_CYAN[bin] + (g - b) * SMITS_GREEN[bin]
+                }
+            } else if g <= r && g <= b {
+                g * SMITS_WHITE[bin] + if r <= b {
+                    (r - g) * SMITS_MAGENTA[bin] + (b - r) * SMITS_BLUE[bin]
+                } else {
+                    (b - g) * SMITS_MAGENTA[bin] + (r - b) * SMITS_RED[bin]
+                }
+            } else {
+                b * SMITS_WHITE[bin] + if r <= g {
+                    (r - b) * SMITS_YELLOW[bin] + (g - r) * SMITS_GREEN[bin]
+                } else {
+                    (g - b) * SMITS_YELLOW[bin] + (r - g) * SMITS_RED[bin]
+                }
+            };
+        }
+        Self { values }
+    }
+
+    pub fn to_xyz(&self, lambda: &SampledWavelengths) -> (f64, f64, f64) {
+        let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+        for i in 0 .. N_SAMPLES {
+            if lambda.pdf[i] == 0.0 {
+                continue;
+            }
+            let (cx, cy, cz) = cie_xyz(lambda.lambda[i]);
+            let s = self.values[i] / lambda.pdf[i];
+            x += cx * s;
+            y += cy * s;
+            z += cz * s;
+        }
+        let n = N_SAMPLES as f64 * CIE_Y_INTEGRAL;
+        (x / n, y / n, z / n)
+    }
+
+    pub fn to_rgb(&self, lambda: &SampledWavelengths) -> Color {
+        let (x, y, z) = self.to_xyz(lambda);
+        let rgb = xyz_to_linear_srgb(x, y, z);
+        let white = white_balance();
+        Color::new(rgb.x() / white.x(), rgb.y() / white.y(), rgb.z() / white.z())
+    }
+}
+
+impl Add for SampledSpectrum {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        let mut values = self.values;
+        for (v, r) in values.iter_mut().zip(rhs.values) {
+            *v += r;
+        }
+        Self { values }
+    }
+}
+
+impl Mul for SampledSpectrum {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        let mut values = self.values;
+        for (v, r) in values.iter_mut().zip(rhs.values) {
+            *v *= r;
+        }
+        Self { values }
+    }
+}
+
+impl Mul<f64> for SampledSpectrum {
+    type Output = Self;
+
+    fn mul(self, rhs: f64) -> Self::Output {
+        Self { values: self.values.map(|v| v * rhs) }
+    }
+}
+
+impl MulAssign for SampledSpectrum {
+    fn mul_assign(&mut self, rhs: Self) {
+        *self = *self * rhs;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Dielectric, Ior, Material, MaterialId};
+    use crate::sence::HitRecord;
+    use crate::camera::Ray;
+    use crate::vector::{Vec3, Point};
+
+    #[test]
+    fn white_round_trips() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let n = 1000;
+        let mut sum = Color::default();
+        for i in 0 .. n {
+            let lambda = SampledWavelengths::sample_uniform((i as f64 + 0.5) / n as f64);
+            sum += SampledSpectrum::from_rgb(white, &lambda).to_rgb(&lambda);
+        }
+        let mean = sum / n as f64;
+        assert!((mean - white).length() < 0.02, "{:?}", mean);
+    }
+
+    #[test]
+    fn glass_indices() {
+        assert!((Ior::BK7.at(Ior::REFERENCE_WAVELENGTH) - 1.5168).abs() < 1e-4);
+        assert!(Ior::BK7.at(450.0) > Ior::BK7.at(650.0));
+        let cauchy = Ior::Cauchy { a: 1.5, b: 0.01 };
+        assert!((cauchy.at(500.0) - 1.54).abs() < 1e-12);
+    }
+
+    #[test]
+    fn dispersion_keeps_only_the_hero() {
+        let mut lambda = SampledWavelengths::sample_uniform(0.3);
+        let pdf = lambda.pdf[0];
+        let ray = Ray::new(Point::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let (origin, normal) = (Point::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        let record = HitRecord::new(origin, normal, true, MaterialId::from_index(0), 1.0);
+        let mut sampler = crate::sampler::SamplerKind::default().build(1, 0);
+        Dielectric::new(1.5).scatter_spectral(&ray, &record, sampler.as_mut(), &mut lambda);
+        assert!(!lambda.secondary_terminated());
+
+        Dielectric::with_ior(Ior::BK7)
+            .scatter_spectral(&ray, &record, sampler.as_mut(), &mut lambda)
+            .unwrap();
+        assert!(lambda.secondary_terminated());
+        assert!((lambda.pdf[0] - pdf / N_SAMPLES as f64).abs() < 1e-15);
+        // a second dispersive bounce doesn't divide again
+        lambda.terminate_secondary();
+        assert!((lambda.pdf[0] - pdf / N_SAMPLES as f64).abs() < 1e-15);
+    }
+}
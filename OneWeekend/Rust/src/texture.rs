@@ -0,0 +1,337 @@
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use super::vector::{Vec3, Point, Color};
+use super::sence::HitRecord;
+use super::color;
+use super::serialize::{invalid, Record};
+
+pub trait Texture: Send + Sync + Debug {
+    fn value(&self, record: &HitRecord) -> Color;
+
+    // Scene file form, None for textures that can't be saved.
+    fn to_record(&self) -> Option<Record> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SolidColor(pub Color);
+
+impl Texture for SolidColor {
+    fn value(&self, _: &HitRecord) -> Color {
+        self.0
+    }
+
+    fn to_record(&self) -> Option<Record> {
+        Some(Record::new("solid").with("color", self.0))
+    }
+}
+
+// Interpolated per-vertex color of the hit, white where there is none.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VertexColor;
+
+impl Texture for VertexColor {
+    fn value(&self, record: &HitRecord) -> Color {
+        record.color().unwrap_or(Color::new(1.0, 1.0, 1.0))
+    }
+
+    fn to_record(&self) -> Option<Record> {
+        Some(Record::new("vertex_color"))
+    }
+}
+
+// Linear RGB pixels, top row first.
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>
+}
+
+// Pixel data is left out, only the size and a hash of the contents.
+impl Debug for Image {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bytes: Vec<u8> = self.pixels.iter()
+            .flat_map(|p| [p.x(), p.y(), p.z()])
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        f.debug_struct("Image")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("hash", &super::utils::fnv1a(&bytes))
+            .finish()
+    }
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert_eq!(pixels.len(), width * height, "image size mismatch");
+        Self { width, height, pixels }
+    }
+
+    // Builds from 8-bit channels; `srgb` decodes the transfer curve for
+    // color data, leave it off for normal maps and other linear data.
+    pub fn from_rgb8(width: usize, height: usize, channels: usize, data: &[u8], srgb: bool) -> Self {
+        Self::from_channels(width, height, channels, data.iter().map(|&v| v as f64 / 255.0), srgb)
+    }
+
+    pub fn from_channels(
+        width: usize, height: usize, channels: usize, data: impl Iterator<Item = f64>, srgb: bool
+    ) -> Self {
+        let data: Vec<f64> = data.collect();
+        let decode = |v: f64| if srgb { color::srgb_to_linear(v) } else { v };
+        let pixels = data.chunks(channels).map(|c| match channels {
+            1 | 2 => Color::new(decode(c[0]), decode(c[0]), decode(c[0])),
+            _ => Color::new(decode(c[0]), decode(c[1]), decode(c[2]))
+        }).collect();
+        Self::new(width, height, pixels)
+    }
+
+    // Loads a PNG, any bit depth.
+    pub fn load(path: impl AsRef<Path>, srgb: bool) -> io::Result<Self> {
+        let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(io::Error::other)?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).map_err(io::Error::other)?;
+        Ok(Self::from_rgb8(
+            info.width as usize, info.height as usize, info.color_type.samples(),
+            &buffer[.. info.buffer_size()], srgb
+        ))
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    fn pixel(&self, x: isize, y: isize) -> Color {
+        let x = x.rem_euclid(self.width as isize) as usize;
+        let y = y.rem_euclid(self.height as isize) as usize;
+        self.pixels[y * self.width + x]
+    }
+
+    // Bilinear lookup with repeating edges, v = 0 is the top row as in
+    // glTF.
+    pub fn sample(&self, u: f64, v: f64) -> Color {
+        let x = u * self.width as f64 - 0.5;
+        let y = v * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as isize, y0 as isize);
+        let top = self.pixel(x0, y0) * (1.0 - fx) + self.pixel(x0 + 1, y0) * fx;
+        let bottom = self.pixel(x0, y0 + 1) * (1.0 - fx) + self.pixel(x0 + 1, y0 + 1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
+
+// Image looked up by the hit's texture coordinates, multiplied by `factor`.
+#[derive(Debug, Clone)]
+pub struct ImageTexture {
+    image: Arc<Image>,
+    factor: Color,
+    flip_v: bool
+}
+
+impl ImageTexture {
+    pub fn new(image: Arc<Image>) -> Self {
+        Self { image, factor: Color::new(1.0, 1.0, 1.0), flip_v: false }
+    }
+
+    pub fn with_factor(mut self, factor: Color) -> Self {
+        self.factor = factor;
+        self
+    }
+
+    // For surfaces whose v runs upwards, like `Sphere`.
+    pub fn with_flip_v(mut self, flip_v: bool) -> Self {
+        self.flip_v = flip_v;
+        self
+    }
+
+    // Pixels are stored in the record, not as a path to the image file.
+    pub(crate) fn from_record(record: &Record) -> io::Result<Self> {
+        let (width, height) = (record.integer("width")?, record.integer("height")?);
+        let values = record.numbers("pixels")?;
+        if values.len() != width * height * 3 {
+            return Err(invalid("image size mismatch"));
+        }
+        let pixels = values.chunks(3).map(|c| Color::new(c[0], c[1], c[2])).collect();
+        let image = Image::new(width, height, pixels);
+        Ok(Self::new(Arc::new(image))
+            .with_factor(record.vector("factor")?)
+            .with_flip_v(record.boolean("flip_v")?))
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, record: &HitRecord) -> Color {
+        let (u, v) = record.uv();
+        let v = if self.flip_v { 1.0 - v } else { v };
+        self.image.sample(u, v) * self.factor
+    }
+
+    fn to_record(&self) -> Option<Record> {
+        let image = &self.image;
+        let pixels: Vec<f64> = image.pixels.iter().flat_map(|p| [p.x(), p.y(), p.z()]).collect();
+        Some(Record::new("image")
+            .with("width", image.width)
+            .with("height", image.height)
+            .with("pixels", pixels)
+            .with("factor", self.factor)
+            .with("flip_v", self.flip_v))
+    }
+}
+
+// 3D checkerboard of unit cells scaled by `scale`, so it doesn't depend on
+// texture coordinates.
+#[derive(Debug, Clone, Copy)]
+pub struct CheckerTexture {
+    scale: f64,
+    even: Color,
+    odd: Color
+}
+
+impl CheckerTexture {
+    pub fn new(scale: f64, even: Color, odd: Color) -> Self {
+        Self { scale, even, odd }
+    }
+
+    pub(crate) fn from_record(record: &Record) -> io::Result<Self> {
+        Ok(Self::new(record.number("scale")?, record.vector("even")?, record.vector("odd")?))
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, record: &HitRecord) -> Color {
+        let p = record.point();
+        let sum: i64 = (0 .. 3).map(|a| (p.axis(a) / self.scale).floor() as i64).sum();
+        if sum % 2 == 0 { self.even } else { self.odd }
+    }
+
+    fn to_record(&self) -> Option<Record> {
+        Some(Record::new("checker")
+            .with("scale", self.scale)
+            .with("even", self.even)
+            .with("odd", self.odd))
+    }
+}
+
+const PERLIN_POINTS: usize = 256;
+
+// Ken Perlin's gradient noise over random unit vectors. The tables come
+// from `seed`, so the same seed gives the same noise.
+#[derive(Clone)]
+struct Perlin {
+    gradients: Vec<Vec3>,
+    permutations: [Vec<usize>; 3]
+}
+
+impl Perlin {
+    fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let gradients = (0 .. PERLIN_POINTS).map(|_| {
+            let (u, v) = (rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0));
+            Vec3::unit_vector_from(u, v)
+        }).collect();
+        let permutations = std::array::from_fn(|_| {
+            let mut p: Vec<usize> = (0 .. PERLIN_POINTS).collect();
+            for i in (1 .. PERLIN_POINTS).rev() {
+                p.swap(i, rng.gen_range(0 ..= i));
+            }
+            p
+        });
+        Self { gradients, permutations }
+    }
+
+    fn noise(&self, p: Point) -> f64 {
+        let floor = [p.x().floor(), p.y().floor(), p.z().floor()];
+        let f = Vec3::new(p.x() - floor[0], p.y() - floor[1], p.z() - floor[2]);
+        // Hermite smoothing of the trilinear weights
+        let s = |t: f64| t * t * (3.0 - 2.0 * t);
+        let mut sum = 0.0;
+        for di in 0 .. 2 {
+            for dj in 0 .. 2 {
+                for dk in 0 .. 2 {
+                    let corner = |axis: usize, d: usize| {
+                        (floor[axis] as i64 + d as i64).rem_euclid(PERLIN_POINTS as i64) as usize
+                    };
+                    let index = self.permutations[0][corner(0, di)]
+                        ^ self.permutations[1][corner(1, dj)]
+                        ^ self.permutations[2][corner(2, dk)];
+                    let (i, j, k) = (di as f64, dj as f64, dk as f64);
+                    let weight = Vec3::new(f.x() - i, f.y() - j, f.z() - k);
+                    sum += (i * s(f.x()) + (1.0 - i) * (1.0 - s(f.x())))
+                        * (j * s(f.y()) + (1.0 - j) * (1.0 - s(f.y())))
+                        * (k * s(f.z()) + (1.0 - k) * (1.0 - s(f.z())))
+                        * self.gradients[index].dot(&weight);
+                }
+            }
+        }
+        sum
+    }
+
+    // Sum of `depth` octaves of noise.
+    fn turbulence(&self, p: Point, depth: u32) -> f64 {
+        let (mut sum, mut p, mut weight) = (0.0, p, 1.0);
+        for _ in 0 .. depth {
+            sum += weight * self.noise(p);
+            weight *= 0.5;
+            p *= 2.0;
+        }
+        sum.abs()
+    }
+}
+
+// Marble-like stripes along z perturbed by Perlin turbulence; `scale` is
+// the stripe frequency.
+#[derive(Clone)]
+pub struct NoiseTexture {
+    seed: u64,
+    scale: f64,
+    perlin: Perlin
+}
+
+impl NoiseTexture {
+    pub fn new(seed: u64, scale: f64) -> Self {
+        Self { seed, scale, perlin: Perlin::new(seed) }
+    }
+
+    // The seed is kept as text, numbers in scene files are doubles.
+    pub(crate) fn from_record(record: &Record) -> io::Result<Self> {
+        let seed = record.text("seed")?.parse().map_err(|_| invalid("bad noise seed"))?;
+        Ok(Self::new(seed, record.number("scale")?))
+    }
+}
+
+// The noise tables follow from the seed and are left out.
+impl Debug for NoiseTexture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NoiseTexture")
+            .field("seed", &self.seed)
+            .field("scale", &self.scale)
+            .finish()
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, record: &HitRecord) -> Color {
+        let p = record.point();
+        let phase = self.scale * p.z() + 10.0 * self.perlin.turbulence(p, 7);
+        Color::new(0.5, 0.5, 0.5) * (1.0 + libm::sin(phase))
+    }
+
+    fn to_record(&self) -> Option<Record> {
+        Some(Record::new("noise").with("seed", self.seed.to_string()).with("scale", self.scale))
+    }
+}
@@ -0,0 +1,220 @@
+use std::io;
+use std::ops::Mul;
+
+use super::vector::{Vec3, Point};
+use super::camera::Ray;
+use super::utils;
+use super::serialize::{invalid, Record};
+
+type Matrix = [[f64; 4]; 4];
+
+const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0]
+];
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut m = [[0.0; 4]; 4];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0 .. 4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+// Gauss-Jordan elimination with partial pivoting.
+fn invert(m: &Matrix) -> Option<Matrix> {
+    let mut a = *m;
+    let mut inv = IDENTITY;
+    for c in 0 .. 4 {
+        let pivot = (c .. 4).max_by(|&i, &j| a[i][c].abs().total_cmp(&a[j][c].abs()))?;
+        if a[pivot][c].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(c, pivot);
+        inv.swap(c, pivot);
+        let d = a[c][c];
+        for k in 0 .. 4 {
+            a[c][k] /= d;
+            inv[c][k] /= d;
+        }
+        for r in 0 .. 4 {
+            if r != c {
+                let f = a[r][c];
+                for k in 0 .. 4 {
+                    a[r][k] -= f * a[c][k];
+                    inv[r][k] -= f * inv[c][k];
+                }
+            }
+        }
+    }
+    Some(inv)
+}
+
+// Affine transform with its inverse kept alongside.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    matrix: Matrix,
+    inverse: Matrix
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Self { matrix: IDENTITY, inverse: IDENTITY }
+    }
+
+    // Row-major matrix acting on column vectors. None if it's singular.
+    pub fn from_matrix(matrix: [[f64; 4]; 4]) -> Option<Self> {
+        Some(Self { matrix, inverse: invert(&matrix)? })
+    }
+
+    pub fn translate(offset: Vec3) -> Self {
+        let mut matrix = IDENTITY;
+        let mut inverse = IDENTITY;
+        for (i, v) in [offset.x(), offset.y(), offset.z()].into_iter().enumerate() {
+            matrix[i][3] = v;
+            inverse[i][3] = -v;
+        }
+        Self { matrix, inverse }
+    }
+
+    pub fn scale(factor: Vec3) -> Self {
+        let mut matrix = IDENTITY;
+        let mut inverse = IDENTITY;
+        for (i, v) in [factor.x(), factor.y(), factor.z()].into_iter().enumerate() {
+            matrix[i][i] = v;
+            inverse[i][i] = 1.0 / v;
+        }
+        Self { matrix, inverse }
+    }
+
+    // Rotation by `degrees` around `axis`, counter-clockwise looking down
+    // the axis.
+    pub fn rotate(axis: Vec3, degrees: f64) -> Self {
+        let a = axis.unit();
+        let theta = utils::degrees_to_radians(degrees);
+        let (s, c) = (libm::sin(theta), libm::cos(theta));
+        let (x, y, z) = (a.x(), a.y(), a.z());
+        let matrix = [
+            [x * x + (1.0 - x * x) * c, x * y * (1.0 - c) - z * s, x * z * (1.0 - c) + y * s, 0.0],
+            [x * y * (1.0 - c) + z * s, y * y + (1.0 - y * y) * c, y * z * (1.0 - c) - x * s, 0.0],
+            [x * z * (1.0 - c) - y * s, y * z * (1.0 - c) + x * s, z * z + (1.0 - z * z) * c, 0.0],
+            [0.0, 0.0, 0.0, 1.0]
+        ];
+        let mut inverse = IDENTITY;
+        for (i, row) in inverse.iter_mut().enumerate().take(3) {
+            for (j, value) in row.iter_mut().enumerate().take(3) {
+                *value = matrix[j][i];
+            }
+        }
+        Self { matrix, inverse }
+    }
+
+    pub fn matrix(&self) -> [[f64; 4]; 4] {
+        self.matrix
+    }
+
+    pub fn inverse(&self) -> Self {
+        Self { matrix: self.inverse, inverse: self.matrix }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.matrix == IDENTITY
+    }
+
+    pub fn point(&self, p: Point) -> Point {
+        let m = &self.matrix;
+        let (x, y, z) = (p.x(), p.y(), p.z());
+        let w = m[3][0] * x + m[3][1] * y + m[3][2] * z + m[3][3];
+        Vec3::new(
+            m[0][0] * x + m[0][1] * y + m[0][2] * z + m[0][3],
+            m[1][0] * x + m[1][1] * y + m[1][2] * z + m[1][3],
+            m[2][0] * x + m[2][1] * y + m[2][2] * z + m[2][3]
+        ) / w
+    }
+
+    pub fn vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.matrix;
+        let (x, y, z) = (v.x(), v.y(), v.z());
+        Vec3::new(
+            m[0][0] * x + m[0][1] * y + m[0][2] * z,
+            m[1][0] * x + m[1][1] * y + m[1][2] * z,
+            m[2][0] * x + m[2][1] * y + m[2][2] * z
+        )
+    }
+
+    // Normals go through the inverse transpose; the result isn't
+    // renormalized.
+    pub fn normal(&self, n: Vec3) -> Vec3 {
+        let m = &self.inverse;
+        let (x, y, z) = (n.x(), n.y(), n.z());
+        Vec3::new(
+            m[0][0] * x + m[1][0] * y + m[2][0] * z,
+            m[0][1] * x + m[1][1] * y + m[2][1] * z,
+            m[0][2] * x + m[1][2] * y + m[2][2] * z
+        )
+    }
+
+    // Both matrices are kept so a loaded transform is bit for bit the
+    // saved one.
+    pub(crate) fn to_record(self) -> Record {
+        let flat = |m: &Matrix| m.iter().flatten().copied().collect::<Vec<f64>>();
+        Record::new("transform")
+            .with("matrix", flat(&self.matrix))
+            .with("inverse", flat(&self.inverse))
+    }
+
+    pub(crate) fn from_record(record: &Record) -> io::Result<Self> {
+        let matrix = |name| -> io::Result<Matrix> {
+            let v = record.numbers(name)?;
+            if v.len() != 16 {
+                return Err(invalid(format!("{} of transform needs 16 values", name)));
+            }
+            Ok(std::array::from_fn(|r| std::array::from_fn(|c| v[r * 4 + c])))
+        };
+        Ok(Self { matrix: matrix("matrix")?, inverse: matrix("inverse")? })
+    }
+
+    // The direction isn't normalized, so ray times stay the same.
+    pub fn ray(&self, ray: &Ray) -> Ray {
+        Ray::new(self.point(ray.origin()), self.vector(ray.direction()))
+            .with_primary(ray.is_primary())
+    }
+}
+
+// `a * b` applies `b` first.
+impl Mul for Transform {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        Self {
+            matrix: multiply(&self.matrix, &rhs.matrix),
+            inverse: multiply(&rhs.inverse, &self.inverse)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inverse_round_trips() {
+        let t = Transform::translate(Vec3::new(1.0, -2.0, 3.0))
+            * Transform::rotate(Vec3::new(1.0, 1.0, 0.0), 30.0)
+            * Transform::scale(Vec3::new(2.0, 0.5, 3.0));
+        let p = Vec3::new(0.3, 0.7, -1.1);
+        assert!((t.inverse().point(t.point(p)) - p).length() < 1e-12);
+        let general = Transform::from_matrix(t.matrix()).unwrap();
+        assert!((general.inverse().point(t.point(p)) - p).length() < 1e-12);
+    }
+}
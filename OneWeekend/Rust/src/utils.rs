@@ -10,6 +10,24 @@ pub fn randomf(min: f64, max: f64) -> f64 {
     rand::thread_rng().gen_range(min..max)
 }
 
+// Stable 64-bit FNV-1a, used to fingerprint scenes and settings.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |h, b| (h ^ *b as u64).wrapping_mul(0x100000001b3))
+}
+
+pub fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5d329728ea185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81dadef4bc2dd44d);
+    v ^= v >> 33;
+    v
+}
+
+pub fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0x9e3779b97f4a7c15, |h, v| mix_bits(h ^ v.wrapping_add(0x9e3779b97f4a7c15)))
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Interval {
     min: f64,
@@ -36,6 +36,27 @@ impl Vec3 {
         }
     }
 
+    pub fn unit_vector_from(u: f64, v: f64) -> Self {
+        let z = 1.0 - 2.0 * u;
+        let r = libm::sqrt(libm::fmax(0.0, 1.0 - z * z));
+        let phi = 2.0 * utils::PI * v;
+        Self::new(r * libm::cos(phi), r * libm::sin(phi), z)
+    }
+
+    // Concentric mapping of the unit square onto the unit disk.
+    pub fn in_unit_disk_from(u: f64, v: f64) -> Self {
+        let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+        if a == 0.0 && b == 0.0 {
+            return Self::default();
+        }
+        let (r, theta) = if libm::fabs(a) > libm::fabs(b) {
+            (a, utils::PI / 4.0 * (b / a))
+        } else {
+            (b, utils::PI / 2.0 - utils::PI / 4.0 * (a / b))
+        };
+        Self::new(r * libm::cos(theta), r * libm::sin(theta), 0.0)
+    }
+
     pub fn random_unit_vector() -> Self {
         Self::random_in_unit_sphere().unit()
     }
@@ -79,6 +100,11 @@ impl Vec3 {
         self.data[2]
     }
 
+    // Component by axis index, 0 to 2.
+    pub fn axis(&self, axis: usize) -> f64 {
+        self.data[axis]
+    }
+
     pub fn length(&self) -> f64 {
         libm::sqrt(self.length_squared())
     }